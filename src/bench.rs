@@ -0,0 +1,220 @@
+use crate::css;
+use crate::dom::{self, AttrMap, Node};
+use crate::layout::Rect;
+use crate::media::MediaEnvironment;
+use crate::style::{self, StyleContext, StyleNode};
+use crate::style_sharing::SharingStats;
+use std::time::{Duration, Instant};
+
+// 在生成的大文档上比较逐条匹配、规则索引、样式共享、并行遍历以及增量计算的样式计算耗时
+// 运行：cargo run --release -- --bench
+pub fn run() {
+  let stylesheet = css::parse(generate_css(5000));
+  let mut root = generate_document(200, 10);
+  let viewport = Rect {
+    x: 0.0,
+    y: 0.0,
+    width: 800.0,
+    height: 600.0,
+  };
+  let mut context = StyleContext::new(&stylesheet, viewport, MediaEnvironment::new(viewport));
+
+  println!(
+    "rules: {}, elements: {}, threads: {}",
+    stylesheet.rules.len(),
+    count_elements(&root),
+    rayon::current_num_threads()
+  );
+
+  // 名称、是否使用规则索引、是否共享样式、是否并行
+  let configs = [
+    ("linear", false, false, false),
+    ("indexed", true, false, false),
+    ("indexed + sharing", true, true, false),
+    ("parallel", true, true, true),
+  ];
+  let mut baseline: Option<(Duration, StyleNode)> = None;
+  for &(name, use_rule_index, use_style_sharing, use_parallel_traversal) in &configs {
+    context.use_rule_index = use_rule_index;
+    context.use_style_sharing = use_style_sharing;
+    context.use_parallel_traversal = use_parallel_traversal;
+    let (duration, tree, stats) = measure(&root, &context);
+
+    print!("{:<18} {:>8.1} ms", name, millis(duration));
+    if let Some((linear, linear_tree)) = &baseline {
+      // 各种方式的结果必须完全一致
+      assert!(
+        same_values(linear_tree, &tree),
+        "{} changed the styles",
+        name
+      );
+      print!("  speedup {:.1}x", millis(*linear) / millis(duration));
+    }
+    if use_style_sharing {
+      print!("  sharing hit rate {:.1}%", stats.hit_rate() * 100.0);
+    }
+    println!();
+
+    if baseline.is_none() {
+      baseline = Some((duration, tree));
+    }
+  }
+
+  context.use_parallel_traversal = false;
+  measure_incremental(&mut root, &context);
+}
+
+// 修改一个元素的 class 后增量计算，与完整计算对比
+fn measure_incremental(root: &mut Node, context: &StyleContext) {
+  style::incremental_style_tree(root, context);
+  // html > body > div > ul > li
+  root.children[0].children[5].children[0].children[3].set_attribute("class", "item c4");
+
+  let start = Instant::now();
+  let (tree, stats) = style::incremental_style_tree(root, context);
+  let duration = start.elapsed();
+  let (full_duration, full_tree, _) = measure(tree.node, context);
+  assert!(
+    same_values(&full_tree, &tree),
+    "Incremental restyle changed the styles"
+  );
+
+  println!(
+    "{:<18} {:>8.1} ms  full restyle {:.1} ms  restyled {} of {} nodes",
+    "incremental",
+    millis(duration),
+    millis(full_duration),
+    stats.restyled,
+    stats.restyled + stats.reused
+  );
+}
+
+// 运行三次取最快的一次
+fn measure<'a>(root: &'a Node, context: &StyleContext) -> (Duration, StyleNode<'a>, SharingStats) {
+  let mut best = Duration::MAX;
+  let mut result = None;
+  for _ in 0..3 {
+    let start = Instant::now();
+    let (tree, stats) = style::style_tree_with_stats(root, context);
+    best = best.min(start.elapsed());
+    result = Some((tree, stats));
+  }
+  let (tree, stats) = result.unwrap();
+  (best, tree, stats)
+}
+
+fn millis(duration: Duration) -> f64 {
+  duration.as_secs_f64() * 1000.0
+}
+
+// 各种形式的选择器，其中大部分不会匹配文档中的元素
+fn generate_css(rules: usize) -> String {
+  let mut css = String::new();
+  for i in 0..rules {
+    let rule = match i % 6 {
+      0 => format!(".c{} {{ margin-left: {}px; }}\n", i % 97, i % 10),
+      1 => format!("#n{} {{ color: #123456; }}\n", i),
+      2 => format!("div .c{} span {{ padding: 1px; }}\n", i % 89),
+      3 => format!("ul > li.c{} {{ border-width: 1px; }}\n", i),
+      4 => format!("p.x{} a {{ color: red; }}\n", i),
+      _ => format!("section{} {{ display: block; }}\n", i),
+    };
+    css.push_str(&rule);
+  }
+  css
+}
+
+// html > body > div.section > ul > li > span、a，每个列表只有第一项有 id
+fn generate_document(sections: usize, items: usize) -> Node {
+  let sections = (0..sections)
+    .map(|i| {
+      let items = (0..items)
+        .map(|j| {
+          let n = i * items + j;
+          let id = if j == 0 {
+            format!("n{}", n)
+          } else {
+            String::new()
+          };
+          element(
+            "li",
+            &id,
+            &format!("item c{}", n % 5),
+            vec![
+              element("span", "", "label", vec![dom::text(format!("item {}", n))]),
+              element("a", "", "", vec![dom::text("link".to_string())]),
+            ],
+          )
+        })
+        .collect();
+      element(
+        "div",
+        "",
+        &format!("section c{}", i % 20),
+        vec![element("ul", "", "list", items)],
+      )
+    })
+    .collect();
+
+  element("html", "", "", vec![element("body", "", "", sections)])
+}
+
+fn element(tag_name: &str, id: &str, class: &str, children: Vec<Node>) -> Node {
+  let mut attrs = AttrMap::new();
+  if !id.is_empty() {
+    attrs.insert("id".to_string(), id.to_string());
+  }
+  if !class.is_empty() {
+    attrs.insert("class".to_string(), class.to_string());
+  }
+  dom::elem(tag_name.to_string(), attrs, children)
+}
+
+fn count_elements(node: &Node) -> usize {
+  let own = match node.node_type {
+    dom::NodeType::Element(_) => 1,
+    dom::NodeType::Text(_) => 0,
+  };
+  own + node.children.iter().map(count_elements).sum::<usize>()
+}
+
+fn same_values(a: &StyleNode, b: &StyleNode) -> bool {
+  a.specified_values == b.specified_values
+    && a.computed_style == b.computed_style
+    && a.children.len() == b.children.len()
+    && a
+      .children
+      .iter()
+      .zip(&b.children)
+      .all(|(a, b)| same_values(a, b))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parallel_traversal_matches_serial() {
+    let stylesheet = css::parse(generate_css(600));
+    let root = generate_document(40, 10);
+    let viewport = Rect {
+      x: 0.0,
+      y: 0.0,
+      width: 800.0,
+      height: 600.0,
+    };
+    let mut context = StyleContext::new(&stylesheet, viewport, MediaEnvironment::new(viewport));
+    context.use_style_sharing = true;
+    let serial = style::style_tree(&root, &context);
+
+    // 固定线程数，保证单核机器上也会划分任务
+    context.use_parallel_traversal = true;
+    let pool = rayon::ThreadPoolBuilder::new()
+      .num_threads(4)
+      .build()
+      .unwrap();
+    let (parallel, stats) = pool.install(|| style::style_tree_with_stats(&root, &context));
+    assert!(same_values(&serial, &parallel));
+    assert!(stats.hits > 0);
+  }
+}
@@ -0,0 +1,794 @@
+use crate::css::{CalcNode, CalcType, Color, Unit, Value};
+use crate::layout::Rect;
+use crate::properties;
+use crate::style::{Display, PropertyMap};
+
+// 长度的计算值，百分比及含百分比的 calc() 要到布局时才能根据包含块解析
+#[derive(Debug, Clone, PartialEq)]
+pub enum Size {
+  Auto,
+  Px(f32),
+  Percent(f32),
+  Calc(Box<CalcNode>),
+}
+
+// 四个方向上的值
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Sides<T> {
+  pub top: T,
+  pub right: T,
+  pub bottom: T,
+  pub left: T,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BorderStyle {
+  None,
+  Hidden,
+  Dotted,
+  Dashed,
+  Solid,
+  Double,
+  Groove,
+  Ridge,
+  Inset,
+  Outset,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FontStyle {
+  Normal,
+  Italic,
+  Oblique,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextAlign {
+  Left,
+  Right,
+  Center,
+}
+
+// normal 折叠空白并自动换行，nowrap 折叠空白但不换行，pre 保留空白和换行符
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WhiteSpace {
+  Normal,
+  Nowrap,
+  Pre,
+}
+
+// visible 以外的取值使盒子建立新的块格式化上下文
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Overflow {
+  Visible,
+  Hidden,
+  Auto,
+  Scroll,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LineHeight {
+  Normal,
+  Number(f32),
+  Px(f32),
+}
+
+// 解析相对单位所需的上下文
+#[derive(Debug, Clone, Copy)]
+pub struct LengthContext {
+  pub font_size: f32,
+  pub root_font_size: f32,
+  pub viewport: Rect,
+  pub font_units: FontUnits,
+}
+
+// ex、ch 相对字号的比例，分别为字体中 x 的高度与 "0" 的步进宽度
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FontUnits {
+  pub ex: f32,
+  pub ch: f32,
+}
+
+// 没有字体度量信息时（如媒体查询中，或字体缺少对应的数据）近似取 0.5em
+impl Default for FontUnits {
+  fn default() -> FontUnits {
+    FontUnits { ex: 0.5, ch: 0.5 }
+  }
+}
+
+// font-size 的初始值 medium
+pub const MEDIUM_FONT_SIZE: f32 = 16.0;
+
+// 层叠之后的计算值，布局和绘制只读取这里的强类型字段
+#[derive(Debug, Clone, PartialEq)]
+pub struct ComputedStyle {
+  pub display: Display,
+  pub width: Size,
+  pub height: Size,
+  pub margin: Sides<Size>,
+  pub padding: Sides<Size>,
+  pub border_width: Sides<f32>,
+  pub border_style: Sides<BorderStyle>,
+  // 色值为 None 表示 transparent
+  pub border_color: Sides<Option<Color>>,
+  pub background_color: Option<Color>,
+  pub color: Color,
+  pub font_family: Vec<String>,
+  pub font_size: f32,
+  pub font_weight: u16,
+  pub font_style: FontStyle,
+  // 相对正常宽度的百分比
+  pub font_stretch: f32,
+  pub line_height: LineHeight,
+  pub text_align: TextAlign,
+  pub white_space: WhiteSpace,
+  pub overflow: Overflow,
+  pub container_type: ContainerType,
+  pub container_name: Vec<String>,
+}
+
+// 查询容器的类型，normal 表示不是容器
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContainerType {
+  Normal,
+  Size,
+  InlineSize,
+}
+
+const BLACK: Color = Color {
+  r: 0,
+  g: 0,
+  b: 0,
+  a: 255,
+};
+
+impl Size {
+  pub fn is_auto(&self) -> bool {
+    *self == Size::Auto
+  }
+
+  // 百分比和 calc() 相对 basis 解析为像素值
+  pub fn resolve(&self, basis: f32) -> Size {
+    match self {
+      Size::Percent(p) => Size::Px(basis * p / 100.0),
+      Size::Calc(node) => Size::Px(node.evaluate(basis)),
+      size => size.clone(),
+    }
+  }
+
+  // auto 按 0 处理，百分比和 calc() 需先调用 resolve
+  pub fn to_px(&self) -> f32 {
+    match *self {
+      Size::Px(f) => f,
+      Size::Auto | Size::Percent(_) | Size::Calc(_) => 0.0,
+    }
+  }
+}
+
+impl CalcNode {
+  // 将相对单位解析为 px，百分比保留到布局时计算
+  pub fn absolutize(&self, context: &LengthContext) -> CalcNode {
+    let absolutize = |node: &CalcNode| Box::new(node.absolutize(context));
+    match self {
+      CalcNode::Number(_) | CalcNode::Percentage(_) => self.clone(),
+      CalcNode::Length(f, unit) => CalcNode::Length(context.to_px(*f, *unit), Unit::Px),
+      CalcNode::Sum(a, b) => CalcNode::Sum(absolutize(a), absolutize(b)),
+      CalcNode::Difference(a, b) => CalcNode::Difference(absolutize(a), absolutize(b)),
+      CalcNode::Product(a, b) => CalcNode::Product(absolutize(a), absolutize(b)),
+      CalcNode::Quotient(a, b) => CalcNode::Quotient(absolutize(a), absolutize(b)),
+      CalcNode::Min(args) => {
+        CalcNode::Min(args.iter().map(|arg| arg.absolutize(context)).collect())
+      }
+      CalcNode::Max(args) => {
+        CalcNode::Max(args.iter().map(|arg| arg.absolutize(context)).collect())
+      }
+      CalcNode::Clamp(min, value, max) => {
+        CalcNode::Clamp(absolutize(min), absolutize(value), absolutize(max))
+      }
+    }
+  }
+
+  pub fn has_percentage(&self) -> bool {
+    match self {
+      CalcNode::Percentage(_) => true,
+      CalcNode::Number(_) | CalcNode::Length(..) => false,
+      CalcNode::Sum(a, b)
+      | CalcNode::Difference(a, b)
+      | CalcNode::Product(a, b)
+      | CalcNode::Quotient(a, b) => a.has_percentage() || b.has_percentage(),
+      CalcNode::Min(args) | CalcNode::Max(args) => args.iter().any(|arg| arg.has_percentage()),
+      CalcNode::Clamp(min, value, max) => {
+        min.has_percentage() || value.has_percentage() || max.has_percentage()
+      }
+    }
+  }
+
+  // 计算表达式的值，长度需已调用 absolutize 解析为 px，percent_basis 为百分比的参照值
+  pub fn evaluate(&self, percent_basis: f32) -> f32 {
+    let evaluate = |node: &CalcNode| node.evaluate(percent_basis);
+    match self {
+      CalcNode::Number(f) | CalcNode::Length(f, _) => *f,
+      CalcNode::Percentage(p) => percent_basis * p / 100.0,
+      CalcNode::Sum(a, b) => evaluate(a) + evaluate(b),
+      CalcNode::Difference(a, b) => evaluate(a) - evaluate(b),
+      CalcNode::Product(a, b) => evaluate(a) * evaluate(b),
+      CalcNode::Quotient(a, b) => evaluate(a) / evaluate(b),
+      CalcNode::Min(args) => args.iter().map(evaluate).fold(f32::INFINITY, f32::min),
+      CalcNode::Max(args) => args.iter().map(evaluate).fold(f32::NEG_INFINITY, f32::max),
+      // min 大于 max 时以 min 为准
+      CalcNode::Clamp(min, value, max) => evaluate(value).min(evaluate(max)).max(evaluate(min)),
+    }
+  }
+}
+
+impl LengthContext {
+  // 将带单位的长度转换为像素值，1in = 96px
+  pub fn to_px(&self, f: f32, unit: Unit) -> f32 {
+    let viewport = self.viewport;
+    match unit {
+      Unit::Px => f,
+      Unit::Pt => f * 96.0 / 72.0,
+      Unit::Pc => f * 16.0,
+      Unit::In => f * 96.0,
+      Unit::Cm => f * 96.0 / 2.54,
+      Unit::Mm => f * 96.0 / 25.4,
+      Unit::Q => f * 96.0 / 101.6,
+      Unit::Em => f * self.font_size,
+      Unit::Rem => f * self.root_font_size,
+      Unit::Ex => f * self.font_size * self.font_units.ex,
+      Unit::Ch => f * self.font_size * self.font_units.ch,
+      Unit::Vw => f * viewport.width / 100.0,
+      Unit::Vh => f * viewport.height / 100.0,
+      Unit::Vmin => f * viewport.width.min(viewport.height) / 100.0,
+      Unit::Vmax => f * viewport.width.max(viewport.height) / 100.0,
+    }
+  }
+}
+
+// 是否有属性值使用了 ex、ch，只有这时才需要查找字体
+pub fn uses_font_units(values: &PropertyMap) -> bool {
+  fn is_font_unit(unit: Unit) -> bool {
+    matches!(unit, Unit::Ex | Unit::Ch)
+  }
+  fn calc_uses_font_units(node: &CalcNode) -> bool {
+    match node {
+      CalcNode::Number(_) | CalcNode::Percentage(_) => false,
+      CalcNode::Length(_, unit) => is_font_unit(*unit),
+      CalcNode::Sum(a, b)
+      | CalcNode::Difference(a, b)
+      | CalcNode::Product(a, b)
+      | CalcNode::Quotient(a, b) => calc_uses_font_units(a) || calc_uses_font_units(b),
+      CalcNode::Min(args) | CalcNode::Max(args) => args.iter().any(calc_uses_font_units),
+      CalcNode::Clamp(min, value, max) => [min, value, max]
+        .iter()
+        .any(|node| calc_uses_font_units(node)),
+    }
+  }
+
+  values.values().any(|value| match value {
+    Value::Length(_, unit) => is_font_unit(*unit),
+    Value::Calc(node) => calc_uses_font_units(node),
+    _ => false,
+  })
+}
+
+// 将属性值中的相对单位解析为像素值，子节点继承的即为计算值
+// font-size 中的 em 和百分比相对父节点字号，其余属性的 em 相对自身字号
+// font_units 取自元素自身的字体，font-size 中的 ex、ch 也按它计算
+pub fn absolutize(
+  values: &mut PropertyMap,
+  parent: Option<&PropertyMap>,
+  root_font_size: f32,
+  viewport: Rect,
+  font_units: FontUnits,
+) {
+  let parent_font_size = match parent.and_then(|parent| parent.get("font-size")) {
+    Some(Value::Length(f, Unit::Px)) => *f,
+    _ => MEDIUM_FONT_SIZE,
+  };
+  let parent_context = LengthContext {
+    font_size: parent_font_size,
+    root_font_size,
+    viewport,
+    font_units,
+  };
+
+  let font_size = match values.get("font-size") {
+    Some(Value::Length(f, unit)) => parent_context.to_px(*f, *unit),
+    Some(Value::Percentage(p)) => parent_font_size * p / 100.0,
+    Some(Value::Calc(node)) => node.absolutize(&parent_context).evaluate(parent_font_size),
+    Some(Value::Keyword(s)) => match &*s.to_ascii_lowercase() {
+      "xx-small" => 9.0,
+      "x-small" => 10.0,
+      "small" => 13.0,
+      "large" => 18.0,
+      "x-large" => 24.0,
+      "xx-large" => 32.0,
+      "xxx-large" => 48.0,
+      "smaller" => parent_font_size / 1.2,
+      "larger" => parent_font_size * 1.2,
+      _ => MEDIUM_FONT_SIZE,
+    },
+    _ => parent_font_size,
+  };
+  values.insert("font-size".to_string(), Value::Length(font_size, Unit::Px));
+
+  let context = LengthContext {
+    font_size,
+    ..parent_context
+  };
+  for value in values.values_mut() {
+    match value {
+      Value::Length(f, unit) => *value = Value::Length(context.to_px(*f, *unit), Unit::Px),
+      // 不含百分比的 calc() 可以直接求值
+      Value::Calc(node) => {
+        let node = node.absolutize(&context);
+        *value = match node.calc_type() {
+          _ if node.has_percentage() => Value::Calc(Box::new(node)),
+          Some(CalcType::Number) => Value::Number(node.evaluate(0.0)),
+          _ => Value::Length(node.evaluate(0.0), Unit::Px),
+        };
+      }
+      _ => {}
+    }
+  }
+
+  // line-height 的百分比相对自身字号
+  let line_height = match values.get("line-height") {
+    Some(Value::Percentage(p)) => Some(font_size * p / 100.0),
+    Some(Value::Calc(node)) => Some(node.evaluate(font_size)),
+    _ => None,
+  };
+  if let Some(line_height) = line_height {
+    values.insert(
+      "line-height".to_string(),
+      Value::Length(line_height, Unit::Px),
+    );
+  }
+
+  // font-weight 统一为数值，bolder、lighter 相对父节点
+  let parent_weight = font_weight(parent.and_then(|parent| parent.get("font-weight")));
+  let weight = match values.get("font-weight") {
+    Some(Value::Keyword(s)) => match &*s.to_ascii_lowercase() {
+      "bold" => 700,
+      "bolder" => match parent_weight {
+        0..=349 => 400,
+        350..=549 => 700,
+        _ => 900,
+      },
+      "lighter" => match parent_weight {
+        0..=99 => parent_weight,
+        100..=549 => 100,
+        550..=749 => 400,
+        _ => 700,
+      },
+      _ => 400,
+    },
+    value => font_weight(value),
+  };
+  values.insert("font-weight".to_string(), Value::Number(weight as f32));
+
+  // color 中的 currentcolor 指父节点的颜色，等同于 inherit
+  if let Some(Value::Keyword(s)) = values.get("color") {
+    if s.eq_ignore_ascii_case("currentcolor") {
+      let color = parent
+        .and_then(|parent| parent.get("color").cloned())
+        .or_else(|| properties::initial_value("color"))
+        .unwrap();
+      values.insert("color".to_string(), color);
+    }
+  }
+}
+
+impl<T> Sides<T> {
+  fn from_fn<F>(f: F) -> Sides<T>
+  where
+    F: Fn(&str) -> T,
+  {
+    Sides {
+      top: f("top"),
+      right: f("right"),
+      bottom: f("bottom"),
+      left: f("left"),
+    }
+  }
+}
+
+impl ComputedStyle {
+  // 根据层叠、继承后的属性值计算出强类型的样式
+  pub fn compute(values: &PropertyMap) -> ComputedStyle {
+    let color = match get(values, "color") {
+      Some(Value::ColorValue(color)) => *color,
+      _ => BLACK,
+    };
+
+    let border_style = Sides::from_fn(|side| {
+      let name = format!("border-{}-style", side);
+      border_style(get(values, &name))
+    });
+
+    // border-style 为 none 或 hidden 时，边框宽度的计算值为 0
+    let border_width = Sides::from_fn(|side| match border_style_of(&border_style, side) {
+      BorderStyle::None | BorderStyle::Hidden => 0.0,
+      _ => {
+        let name = format!("border-{}-width", side);
+        length(get(values, &name))
+      }
+    });
+
+    let border_color = Sides::from_fn(|side| {
+      let name = format!("border-{}-color", side);
+      color_value(get(values, &name), color)
+    });
+
+    let margin = Sides::from_fn(|side| {
+      let name = format!("margin-{}", side);
+      size(get(values, &name))
+    });
+
+    let padding = Sides::from_fn(|side| {
+      let name = format!("padding-{}", side);
+      size(get(values, &name))
+    });
+
+    let background_color = color_value(get(values, "background-color"), color);
+
+    ComputedStyle {
+      display: display(get(values, "display")),
+      width: size(get(values, "width")),
+      height: size(get(values, "height")),
+      margin,
+      padding,
+      border_width,
+      border_style,
+      border_color,
+      background_color,
+      color,
+      font_family: font_family(get(values, "font-family")),
+      font_size: match get(values, "font-size") {
+        Some(Value::Length(f, Unit::Px)) => *f,
+        _ => MEDIUM_FONT_SIZE,
+      },
+      font_weight: font_weight(get(values, "font-weight")),
+      font_style: font_style(get(values, "font-style")),
+      font_stretch: font_stretch(get(values, "font-stretch")),
+      line_height: match get(values, "line-height") {
+        Some(Value::Number(f)) => LineHeight::Number(*f),
+        Some(Value::Length(f, Unit::Px)) => LineHeight::Px(*f),
+        _ => LineHeight::Normal,
+      },
+      text_align: text_align(get(values, "text-align")),
+      white_space: white_space(get(values, "white-space")),
+      overflow: overflow(get(values, "overflow")),
+      container_type: container_type(get(values, "container-type")),
+      container_name: container_name(get(values, "container-name")),
+    }
+  }
+}
+
+// 查找 name 的值，不存在时取初始值，简写属性已在解析时展开
+fn get<'a>(values: &'a PropertyMap, name: &str) -> Option<&'a Value> {
+  values
+    .get(name)
+    .or_else(|| properties::lookup(name).map(|property| &property.initial))
+}
+
+fn keyword(value: Option<&Value>) -> Option<String> {
+  match value {
+    Some(Value::Keyword(s)) => Some(s.to_ascii_lowercase()),
+    _ => None,
+  }
+}
+
+fn display(value: Option<&Value>) -> Display {
+  match keyword(value).as_deref() {
+    Some("block") => Display::Block,
+    Some("list-item") => Display::ListItem,
+    Some("table") => Display::Table,
+    Some("table-caption") => Display::TableCaption,
+    Some("table-column-group") => Display::TableColumnGroup,
+    Some("table-column") => Display::TableColumn,
+    Some("table-header-group") => Display::TableHeaderGroup,
+    Some("table-row-group") => Display::TableRowGroup,
+    Some("table-footer-group") => Display::TableFooterGroup,
+    Some("table-row") => Display::TableRow,
+    Some("table-cell") => Display::TableCell,
+    Some("none") => Display::None,
+    _ => Display::Inline,
+  }
+}
+
+// width、height、margin、padding 等可以为百分比的长度
+fn size(value: Option<&Value>) -> Size {
+  match value {
+    Some(Value::Length(f, Unit::Px)) => Size::Px(*f),
+    Some(Value::Percentage(p)) => Size::Percent(*p),
+    Some(Value::Calc(node)) => Size::Calc(node.clone()),
+    Some(Value::Keyword(s)) if s.eq_ignore_ascii_case("auto") => Size::Auto,
+    _ => Size::Px(0.0),
+  }
+}
+
+// border-width 的长度
+fn length(value: Option<&Value>) -> f32 {
+  match value {
+    Some(Value::Length(f, Unit::Px)) => *f,
+    Some(Value::Keyword(s)) => match &*s.to_ascii_lowercase() {
+      "thin" => 1.0,
+      "medium" => 3.0,
+      "thick" => 5.0,
+      _ => 0.0,
+    },
+    _ => 0.0,
+  }
+}
+
+// 色值，currentcolor 取 color 属性的值，完全透明时返回 None
+fn color_value(value: Option<&Value>, current_color: Color) -> Option<Color> {
+  match value {
+    Some(Value::ColorValue(color)) if color.a == 0 => None,
+    Some(Value::ColorValue(color)) => Some(*color),
+    Some(Value::Keyword(s)) if s.eq_ignore_ascii_case("currentcolor") => Some(current_color),
+    _ => None,
+  }
+}
+
+fn border_style(value: Option<&Value>) -> BorderStyle {
+  match keyword(value).as_deref() {
+    Some("hidden") => BorderStyle::Hidden,
+    Some("dotted") => BorderStyle::Dotted,
+    Some("dashed") => BorderStyle::Dashed,
+    Some("solid") => BorderStyle::Solid,
+    Some("double") => BorderStyle::Double,
+    Some("groove") => BorderStyle::Groove,
+    Some("ridge") => BorderStyle::Ridge,
+    Some("inset") => BorderStyle::Inset,
+    Some("outset") => BorderStyle::Outset,
+    _ => BorderStyle::None,
+  }
+}
+
+fn border_style_of(styles: &Sides<BorderStyle>, side: &str) -> BorderStyle {
+  match side {
+    "top" => styles.top,
+    "right" => styles.right,
+    "bottom" => styles.bottom,
+    _ => styles.left,
+  }
+}
+
+fn font_family(value: Option<&Value>) -> Vec<String> {
+  let family = |value: &Value| match value {
+    Value::Keyword(s) | Value::Str(s) => Some(s.clone()),
+    _ => None,
+  };
+  let families: Vec<String> = match value {
+    Some(Value::List(list)) => list.iter().filter_map(family).collect(),
+    Some(value) => family(value).into_iter().collect(),
+    None => Vec::new(),
+  };
+  if families.is_empty() {
+    vec!["serif".to_string()]
+  } else {
+    families
+  }
+}
+
+fn font_weight(value: Option<&Value>) -> u16 {
+  match value {
+    Some(Value::Number(f)) => f.clamp(1.0, 1000.0) as u16,
+    Some(Value::Keyword(s)) if s.eq_ignore_ascii_case("bold") => 700,
+    _ => 400,
+  }
+}
+
+// 关键字换算为百分比
+fn font_stretch(value: Option<&Value>) -> f32 {
+  if let Some(Value::Percentage(p)) = value {
+    return p.clamp(50.0, 200.0);
+  }
+  match keyword(value).as_deref() {
+    Some("ultra-condensed") => 50.0,
+    Some("extra-condensed") => 62.5,
+    Some("condensed") => 75.0,
+    Some("semi-condensed") => 87.5,
+    Some("semi-expanded") => 112.5,
+    Some("expanded") => 125.0,
+    Some("extra-expanded") => 150.0,
+    Some("ultra-expanded") => 200.0,
+    _ => 100.0,
+  }
+}
+
+fn container_type(value: Option<&Value>) -> ContainerType {
+  match keyword(value).as_deref() {
+    Some("size") => ContainerType::Size,
+    Some("inline-size") => ContainerType::InlineSize,
+    _ => ContainerType::Normal,
+  }
+}
+
+// 容器名以空格分隔，none 表示没有名字
+fn container_name(value: Option<&Value>) -> Vec<String> {
+  match value {
+    Some(Value::Keyword(s)) if !s.eq_ignore_ascii_case("none") => {
+      s.split_whitespace().map(|name| name.to_string()).collect()
+    }
+    _ => Vec::new(),
+  }
+}
+
+fn text_align(value: Option<&Value>) -> TextAlign {
+  match keyword(value).as_deref() {
+    Some("right") => TextAlign::Right,
+    Some("center") => TextAlign::Center,
+    _ => TextAlign::Left,
+  }
+}
+
+fn white_space(value: Option<&Value>) -> WhiteSpace {
+  match keyword(value).as_deref() {
+    Some("nowrap") => WhiteSpace::Nowrap,
+    Some("pre") => WhiteSpace::Pre,
+    _ => WhiteSpace::Normal,
+  }
+}
+
+fn overflow(value: Option<&Value>) -> Overflow {
+  match keyword(value).as_deref() {
+    Some("hidden") => Overflow::Hidden,
+    Some("auto") => Overflow::Auto,
+    Some("scroll") => Overflow::Scroll,
+    _ => Overflow::Visible,
+  }
+}
+
+fn font_style(value: Option<&Value>) -> FontStyle {
+  match keyword(value).as_deref() {
+    Some("italic") => FontStyle::Italic,
+    Some("oblique") => FontStyle::Oblique,
+    _ => FontStyle::Normal,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::color;
+  use crate::css;
+
+  // 解析 "name: value; ..." 形式的声明，简写属性展开为普通属性
+  fn specified(declarations: &str) -> PropertyMap {
+    declarations
+      .split(';')
+      .filter(|declaration| !declaration.trim().is_empty())
+      .flat_map(|declaration| {
+        let (name, value) = declaration.split_once(':').unwrap();
+        css::parse_declaration_value(name.trim(), value).unwrap()
+      })
+      .collect()
+  }
+
+  // 根节点字号为 10px，视口为 800x600
+  fn compute(declarations: &str, parent: Option<&PropertyMap>) -> (PropertyMap, ComputedStyle) {
+    let mut values = specified(declarations);
+    let viewport = Rect {
+      width: 800.0,
+      height: 600.0,
+      ..Default::default()
+    };
+    absolutize(&mut values, parent, 10.0, viewport, FontUnits::default());
+    let style = ComputedStyle::compute(&values);
+    (values, style)
+  }
+
+  fn named(name: &str) -> Color {
+    color::named_color(name).unwrap()
+  }
+
+  #[test]
+  fn resolves_font_relative_and_viewport_units() {
+    let parent = specified("font-size: 20px");
+    let (_, style) = compute(
+      "font-size: 2em; width: 2em; height: 50%; padding-left: 1rem; margin-top: 10vw; \
+       border-top-style: solid; border-top-width: 0.5em",
+      Some(&parent),
+    );
+    // font-size 的 em 相对父节点字号，其余属性相对自身字号
+    assert_eq!(style.font_size, 40.0);
+    assert_eq!(style.width, Size::Px(80.0));
+    // 百分比留到布局时解析
+    assert_eq!(style.height, Size::Percent(50.0));
+    assert_eq!(style.padding.left, Size::Px(10.0));
+    assert_eq!(style.margin.top, Size::Px(80.0));
+    assert_eq!(style.border_width.top, 20.0);
+
+    let (_, style) = compute("font-size: 150%", Some(&parent));
+    assert_eq!(style.font_size, 30.0);
+    let (_, style) = compute("font-size: 3rem", Some(&parent));
+    assert_eq!(style.font_size, 30.0);
+    // 根节点没有父节点，相对 medium
+    let (_, style) = compute("font-size: 2em", None);
+    assert_eq!(style.font_size, 2.0 * MEDIUM_FONT_SIZE);
+  }
+
+  #[test]
+  fn resolves_line_height() {
+    let (_, style) = compute("font-size: 20px; line-height: 150%", None);
+    assert_eq!(style.line_height, LineHeight::Px(30.0));
+    let (_, style) = compute("font-size: 20px; line-height: 2em", None);
+    assert_eq!(style.line_height, LineHeight::Px(40.0));
+    // 数字作为倍数继承，由子节点按自身字号计算
+    let (_, style) = compute("font-size: 20px; line-height: 1.5", None);
+    assert_eq!(style.line_height, LineHeight::Number(1.5));
+    let (_, style) = compute("", None);
+    assert_eq!(style.line_height, LineHeight::Normal);
+  }
+
+  #[test]
+  fn resolves_keywords() {
+    let parent = specified("font-size: 20px; font-weight: bold");
+    let (_, style) = compute("font-size: larger; font-weight: lighter", Some(&parent));
+    assert_eq!(style.font_size, 24.0);
+    assert_eq!(style.font_weight, 400);
+    let (_, style) = compute("font-size: small; font-weight: bolder", Some(&parent));
+    assert_eq!(style.font_size, 13.0);
+    assert_eq!(style.font_weight, 900);
+
+    let (_, style) = compute(
+      "border-top: thick solid; border-right: thick none; width: auto",
+      None,
+    );
+    assert_eq!(style.border_width.top, 5.0);
+    // border-style 为 none 时宽度为 0
+    assert_eq!(style.border_width.right, 0.0);
+    assert_eq!(style.width, Size::Auto);
+
+    // 未设置的属性取初始值
+    let (_, style) = compute("", None);
+    assert_eq!(style.display, Display::Inline);
+    assert_eq!(style.font_size, MEDIUM_FONT_SIZE);
+    assert_eq!(style.font_weight, 400);
+    assert_eq!(style.color, BLACK);
+    assert_eq!(style.background_color, None);
+  }
+
+  #[test]
+  fn resolves_current_color() {
+    let (_, style) = compute(
+      "color: blue; border-top: 1px solid; border-left-color: currentcolor; \
+       background-color: CurrentColor",
+      None,
+    );
+    assert_eq!(style.border_color.top, Some(named("blue")));
+    assert_eq!(style.border_color.left, Some(named("blue")));
+    assert_eq!(style.background_color, Some(named("blue")));
+
+    // color 中的 currentcolor 取父节点的颜色，子节点继承的是颜色本身
+    let parent = specified("color: red");
+    let (values, style) = compute("color: currentcolor", Some(&parent));
+    assert_eq!(style.color, named("red"));
+    assert_eq!(values.get("color"), Some(&Value::ColorValue(named("red"))));
+    let (_, style) = compute("color: currentcolor", None);
+    assert_eq!(style.color, BLACK);
+  }
+
+  #[test]
+  fn resolves_ex_and_ch_with_font_units() {
+    let mut values = specified("width: 2ex; height: 2ch; margin-top: calc(1ex + 1ch)");
+    assert!(uses_font_units(&values));
+    let viewport = Rect::default();
+    let units = FontUnits { ex: 0.25, ch: 0.75 };
+    absolutize(&mut values, None, 16.0, viewport, units);
+    let style = ComputedStyle::compute(&values);
+    assert_eq!(style.width, Size::Px(8.0));
+    assert_eq!(style.height, Size::Px(24.0));
+    assert_eq!(style.margin.top, Size::Px(16.0));
+    assert!(!uses_font_units(&values));
+
+    // 没有字体度量信息时取 0.5em
+    let (_, style) = compute("width: 2ex; height: 2ch", None);
+    assert_eq!(style.width, Size::Px(16.0));
+    assert_eq!(style.height, Size::Px(16.0));
+  }
+}
@@ -1,8 +1,106 @@
+use crate::color;
+use crate::computed::FontStyle;
+use crate::media::{MediaFeature, MediaQuery, MediaQueryList, MediaType, RangeOp};
+use crate::properties;
+use crate::shorthand;
 use crate::source;
+use std::path::{Component, Path, PathBuf};
 
 #[derive(Debug)]
 pub struct StyleSheet {
-  pub rules: Vec<Rule>,
+  pub rules: Vec<CssRule>,
+  // 解析时忽略的内容，如不支持的 @ 规则
+  pub diagnostics: Vec<String>,
+}
+
+// 样式表中的规则，普通的样式规则或 @ 规则
+#[derive(Debug)]
+pub enum CssRule {
+  Style(Rule),
+  Media(MediaRule),
+  Import(ImportRule),
+  Supports(SupportsRule),
+  Layer(LayerRule),
+  Container(ContainerRule),
+  FontFace(FontFaceRule),
+}
+
+// @font-face 规则，声明一个可以在 font-family 中使用的字体
+#[derive(Debug, Clone, PartialEq)]
+pub struct FontFaceRule {
+  pub family: String,
+  pub sources: Vec<FontSource>,
+  // 粗细、宽度的范围，只有一个值时两端相同
+  pub weight: (u16, u16),
+  pub style: FontStyle,
+  pub stretch: (f32, f32),
+  // unicode-range 中的闭区间，为空时表示所有字符
+  pub unicode_range: Vec<(u32, u32)>,
+}
+
+// @font-face 的 src 中的一项，按顺序使用第一个可以加载的
+#[derive(Debug, Clone, PartialEq)]
+pub enum FontSource {
+  // url("a.woff2") format("woff2")
+  Url(String, Option<String>),
+  // local("Font Name")，使用已加载的字体
+  Local(String),
+}
+
+// @import 规则，被引用样式表的规则内联在 rules 中，queries 匹配时才生效
+#[derive(Debug)]
+pub struct ImportRule {
+  pub url: String,
+  pub queries: MediaQueryList,
+  pub rules: Vec<CssRule>,
+}
+
+// @supports 规则，条件中的属性均被支持时其中的规则才生效
+#[derive(Debug)]
+pub struct SupportsRule {
+  pub condition: Condition<SupportsFeature>,
+  pub rules: Vec<CssRule>,
+}
+
+// @supports 中的单项检测
+#[derive(Debug, Clone, PartialEq)]
+pub enum SupportsFeature {
+  // (display: flex)
+  Declaration(String, String),
+  // 无法识别的形式，如 selector(a > b)，总是不满足
+  Unknown(String),
+}
+
+// @layer 规则，语句形式 @layer a, b; 只声明层的顺序，块形式 @layer a { ... } 的名字可省略
+// 层名可以是 a.b 形式的嵌套层
+#[derive(Debug)]
+pub struct LayerRule {
+  pub names: Vec<String>,
+  pub rules: Option<Vec<CssRule>>,
+}
+
+// @container 规则，最近的同名容器尺寸满足条件时其中的规则才生效
+#[derive(Debug)]
+pub struct ContainerRule {
+  pub name: Option<String>,
+  pub condition: Condition<MediaFeature>,
+  pub rules: Vec<CssRule>,
+}
+
+// @supports、@container 中以 not、and、or 组合的条件
+#[derive(Debug, Clone, PartialEq)]
+pub enum Condition<T> {
+  Test(T),
+  Not(Box<Condition<T>>),
+  And(Vec<Condition<T>>),
+  Or(Vec<Condition<T>>),
+}
+
+// @media 规则，媒体查询匹配时其中的规则才生效
+#[derive(Debug)]
+pub struct MediaRule {
+  pub queries: MediaQueryList,
+  pub rules: Vec<CssRule>,
 }
 
 #[derive(Debug)]
@@ -11,9 +109,14 @@ pub struct Rule {
   pub declarations: Vec<Declaration>,
 }
 
+// 复合选择器，右边的简单选择器为目标元素
 #[derive(Debug)]
 pub enum Selector {
   Simple(SimpleSelector),
+  // 后代选择器，如 div p
+  Descendant(Box<Selector>, SimpleSelector),
+  // 子选择器，如 ul > li
+  Child(Box<Selector>, SimpleSelector),
 }
 
 #[derive(Debug)]
@@ -23,22 +126,79 @@ pub struct SimpleSelector {
   pub class: Vec<String>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Declaration {
   pub name: String,
   pub value: Value,
+  pub important: bool,
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Value {
   Keyword(String),
+  Number(f32),
+  Percentage(f32),
   Length(f32, Unit),
   ColorValue(Color),
+  Calc(Box<CalcNode>),
+  // 分辨率，统一为 dppx，用于媒体查询
+  Resolution(f32),
+  // 带引号的字符串，如 "Times New Roman"
+  Str(String),
+  // 逗号分隔的列表，如 font-family: Arial, sans-serif
+  List(Vec<Value>),
+  // 未解析的原始文本，用于自定义属性及包含 var() 的值，在计算值阶段替换后重新解析
+  // shorthand 为声明时的简写属性名，替换后需要按简写重新展开
+  Unparsed {
+    css: String,
+    shorthand: Option<String>,
+  },
 }
 
+// calc()、min()、max()、clamp() 的表达式树
 #[derive(Debug, Clone, PartialEq)]
+pub enum CalcNode {
+  Number(f32),
+  Percentage(f32),
+  Length(f32, Unit),
+  Sum(Box<CalcNode>, Box<CalcNode>),
+  Difference(Box<CalcNode>, Box<CalcNode>),
+  Product(Box<CalcNode>, Box<CalcNode>),
+  Quotient(Box<CalcNode>, Box<CalcNode>),
+  Min(Vec<CalcNode>),
+  Max(Vec<CalcNode>),
+  Clamp(Box<CalcNode>, Box<CalcNode>, Box<CalcNode>),
+}
+
+// 表达式的结果类型，长度与百分比相加得到 LengthPercentage
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CalcType {
+  Number,
+  Length,
+  Percentage,
+  LengthPercentage,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Unit {
+  // 绝对单位
   Px,
+  Pt,
+  Pc,
+  Cm,
+  Mm,
+  Q,
+  In,
+  // 相对字体大小
+  Em,
+  Rem,
+  Ex,
+  Ch,
+  // 相对视口大小
+  Vw,
+  Vh,
+  Vmin,
+  Vmax,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -54,235 +214,2307 @@ impl Copy for Color {}
 // css 解析器
 struct CSSParser {
   source_helper: source::SourceHelper,
+  diagnostics: Vec<String>,
+}
+
+impl<T> Condition<T> {
+  pub fn evaluate(&self, test: &dyn Fn(&T) -> bool) -> bool {
+    match self {
+      Condition::Test(t) => test(t),
+      Condition::Not(condition) => !condition.evaluate(test),
+      Condition::And(conditions) => conditions.iter().all(|c| c.evaluate(test)),
+      Condition::Or(conditions) => conditions.iter().any(|c| c.evaluate(test)),
+    }
+  }
+}
+
+impl SupportsFeature {
+  // 根据属性注册表判断是否支持
+  pub fn is_supported(&self) -> bool {
+    match self {
+      SupportsFeature::Declaration(name, value) => properties::supports(name, value),
+      SupportsFeature::Unknown(_) => false,
+    }
+  }
 }
 
 pub type Specificity = (usize, usize, usize);
 
 impl Selector {
   // 优先级：id > class > tag
+  // 组合选择器为各部分之和
+  pub fn specificity(&self) -> Specificity {
+    match self {
+      Selector::Simple(simple) => simple.specificity(),
+      Selector::Descendant(left, right) | Selector::Child(left, right) => {
+        let (a, b, c) = left.specificity();
+        let (d, e, f) = right.specificity();
+        (a + d, b + e, c + f)
+      }
+    }
+  }
+
+  // 最右边的简单选择器，即匹配的目标元素
+  pub fn rightmost(&self) -> &SimpleSelector {
+    match self {
+      Selector::Simple(simple) => simple,
+      Selector::Descendant(_, right) | Selector::Child(_, right) => right,
+    }
+  }
+}
+
+impl SimpleSelector {
   pub fn specificity(&self) -> Specificity {
-    let Selector::Simple(ref simple) = *self;
-    let a = simple.id.iter().count();
-    let b = simple.class.len();
-    let c = simple.tag_name.iter().count();
+    let a = self.id.iter().count();
+    let b = self.class.len();
+    let c = self.tag_name.iter().count();
 
     (a, b, c)
   }
 }
 
-impl Value {
-  pub fn to_px(&self) -> f32 {
-    match *self {
-      Value::Length(f, Unit::Px) => f,
-      _ => 0.0,
+impl CalcType {
+  // 加减、比较时两边类型需兼容
+  fn combine(self, other: CalcType) -> Option<CalcType> {
+    match (self, other) {
+      (a, b) if a == b => Some(a),
+      (CalcType::Number, _) | (_, CalcType::Number) => None,
+      _ => Some(CalcType::LengthPercentage),
+    }
+  }
+}
+
+impl CalcNode {
+  // 类型检查，不合法时返回 None
+  pub fn calc_type(&self) -> Option<CalcType> {
+    match self {
+      CalcNode::Number(_) => Some(CalcType::Number),
+      CalcNode::Percentage(_) => Some(CalcType::Percentage),
+      CalcNode::Length(..) => Some(CalcType::Length),
+      CalcNode::Sum(a, b) | CalcNode::Difference(a, b) => a.calc_type()?.combine(b.calc_type()?),
+      // 乘法至少一边是数字
+      CalcNode::Product(a, b) => match (a.calc_type()?, b.calc_type()?) {
+        (CalcType::Number, t) | (t, CalcType::Number) => Some(t),
+        _ => None,
+      },
+      // 除数必须是非零数字
+      CalcNode::Quotient(a, b) => match (a.calc_type()?, b.calc_type()?) {
+        (_, CalcType::Number) if **b == CalcNode::Number(0.0) => None,
+        (t, CalcType::Number) => Some(t),
+        _ => None,
+      },
+      CalcNode::Min(args) | CalcNode::Max(args) => {
+        let mut args = args.iter();
+        let first = args.next()?.calc_type()?;
+        args.try_fold(first, |t, arg| t.combine(arg.calc_type()?))
+      }
+      CalcNode::Clamp(min, value, max) => min
+        .calc_type()?
+        .combine(value.calc_type()?)?
+        .combine(max.calc_type()?),
     }
   }
 }
 
 // 对外提供的解析方法
 pub fn parse(source: String) -> StyleSheet {
-  let source_helper = source::SourceHelper {
-    pos: 0,
-    input: source,
-  };
-
-  let mut parser = CSSParser {
-    source_helper: source_helper,
-  };
+  let mut parser = CSSParser::new(source);
 
   let rules = parser.parse_rules();
-  StyleSheet { rules: rules }
+  StyleSheet {
+    rules,
+    diagnostics: parser.diagnostics,
+  }
 }
 
-// 解析器
-impl CSSParser {
-  // 解析 css 规则
-  fn parse_rules(&mut self) -> Vec<Rule> {
-    let mut rules = Vec::new();
+// 解析样式表，并通过 loader 加载 @import 引用的样式表，url 为当前样式表的地址
+// loader 返回 None 时忽略该 @import
+pub fn parse_with_loader(
+  source: String,
+  url: &str,
+  loader: &dyn Fn(&str) -> Option<String>,
+) -> StyleSheet {
+  let mut stylesheet = parse(source);
+  let mut stack = vec![resolve_url("", url)];
+  resolve_imports(
+    &mut stylesheet.rules,
+    url,
+    loader,
+    &mut stack,
+    &mut stylesheet.diagnostics,
+  );
+  stylesheet
+}
 
-    loop {
-      self.source_helper.consume_whitespace();
-      if self.source_helper.eof() {
-        break;
+// 递归加载 @import，stack 为正在加载的样式表，出现循环引用时忽略该 @import
+// 被引用样式表的诊断信息合并到 diagnostics 中
+fn resolve_imports(
+  rules: &mut [CssRule],
+  base_url: &str,
+  loader: &dyn Fn(&str) -> Option<String>,
+  stack: &mut Vec<String>,
+  diagnostics: &mut Vec<String>,
+) {
+  for rule in rules {
+    match rule {
+      CssRule::Import(import) => {
+        let url = resolve_url(base_url, &import.url);
+        if stack.contains(&url) {
+          continue;
+        }
+
+        if let Some(source) = loader(&url) {
+          let stylesheet = parse(source);
+          diagnostics.extend(
+            stylesheet
+              .diagnostics
+              .into_iter()
+              .map(|message| format!("{}: {}", url, message)),
+          );
+          let mut rules = stylesheet.rules;
+          stack.push(url.clone());
+          resolve_imports(&mut rules, &url, loader, stack, diagnostics);
+          stack.pop();
+          import.rules = rules;
+        }
       }
-      rules.push(self.parse_rule());
+      CssRule::Media(MediaRule { rules, .. })
+      | CssRule::Supports(SupportsRule { rules, .. })
+      | CssRule::Container(ContainerRule { rules, .. })
+      | CssRule::Layer(LayerRule {
+        rules: Some(rules), ..
+      }) => resolve_imports(rules, base_url, loader, stack, diagnostics),
+      CssRule::Layer(_) | CssRule::Style(_) | CssRule::FontFace(_) => {}
     }
-    rules
   }
+}
 
-  //  解析单个 css 规则
-  fn parse_rule(&mut self) -> Rule {
-    Rule {
-      selectors: self.parse_selectors(),
-      declarations: self.parse_declarations(),
-    }
+// 相对地址基于当前样式表所在的目录，并去掉 . 和 ..，使同一文件得到相同的地址
+pub fn resolve_url(base_url: &str, url: &str) -> String {
+  if url.contains("://") {
+    return url.to_string();
   }
 
-  // 解析组合选择器，以","分隔，返回数组
-  fn parse_selectors(&mut self) -> Vec<Selector> {
-    let mut selectors = Vec::new();
-    loop {
-      let simple_selector = self.parse_simple_selector();
+  let path = match Path::new(base_url).parent() {
+    Some(dir) if !url.starts_with('/') => dir.join(url),
+    _ => PathBuf::from(url),
+  };
 
-      let selector = Selector::Simple(simple_selector);
-      println!("selector:{:?}", selector);
+  let mut normalized = PathBuf::new();
+  for component in path.components() {
+    match component {
+      Component::CurDir => {}
+      Component::ParentDir if normalized.file_name().is_some() => {
+        normalized.pop();
+      }
+      component => normalized.push(component),
+    }
+  }
+  normalized.to_string_lossy().into_owned()
+}
 
-      selectors.push(selector);
+// @font-face 中的字族名，带引号的字符串或空白分隔的标识符
+fn font_face_family(css: &str) -> Option<String> {
+  if css.starts_with('"') || css.starts_with('\'') {
+    let mut parser = CSSParser::new(css.to_string());
+    let family = parser.parse_string_literal();
+    parser.source_helper.consume_whitespace();
+    return if parser.source_helper.eof() {
+      Some(family)
+    } else {
+      None
+    };
+  }
+  let words: Vec<&str> = css.split_whitespace().collect();
+  if words.is_empty() || css.contains(',') {
+    return None;
+  }
+  Some(words.join(" "))
+}
 
-      self.source_helper.consume_whitespace();
+// src: url(a.woff2) format("woff2"), local("Name")
+fn font_face_sources(css: &str) -> Option<Vec<FontSource>> {
+  let mut parser = CSSParser::new(css.to_string());
+  let mut sources = Vec::new();
+  loop {
+    parser.source_helper.consume_whitespace();
+    let name = parser.parse_identifier().to_ascii_lowercase();
+    if parser.source_helper.eof() || parser.source_helper.next_char() != '(' {
+      return None;
+    }
+    let argument = parser.parse_function_argument()?;
+    parser.source_helper.consume_whitespace();
 
-      match self.source_helper.next_char() {
-        '{' => break,
-        ',' => {
-          self.source_helper.consume_char();
-          self.source_helper.consume_whitespace();
+    match &*name {
+      "url" => {
+        let mut format = None;
+        // format() 之后可能还有 tech()，不影响加载
+        while !parser.source_helper.eof() && parser.source_helper.next_char() != ',' {
+          let hint = parser.parse_identifier().to_ascii_lowercase();
+          if parser.source_helper.eof() || parser.source_helper.next_char() != '(' {
+            return None;
+          }
+          let value = parser.parse_function_argument()?;
+          if hint == "format" {
+            format = Some(value.to_ascii_lowercase());
+          }
+          parser.source_helper.consume_whitespace();
         }
-        c => panic!("Unexpected char {} in selector list!", c),
+        sources.push(FontSource::Url(argument, format));
       }
+      "local" => sources.push(FontSource::Local(argument)),
+      _ => return None,
     }
 
-    // 按优先级排序
-    selectors.sort_by(|a, b| b.specificity().cmp(&a.specificity()));
-    selectors
+    if parser.source_helper.eof() {
+      break;
+    }
+    parser.consume_expected(',')?;
   }
+  Some(sources)
+}
 
-  // 解析单个选择器
-  fn parse_simple_selector(&mut self) -> SimpleSelector {
-    let mut selector = SimpleSelector {
-      tag_name: None,
-      id: None,
-      class: Vec::new(),
+// 一个或两个值，两个值时表示范围，auto 表示不限制
+fn descriptor_range(css: &str, value: fn(&str) -> Option<f32>) -> Option<(f32, f32)> {
+  let values = css
+    .split_whitespace()
+    .map(value)
+    .collect::<Option<Vec<f32>>>()?;
+  match values[..] {
+    [value] => Some((value, value)),
+    [min, max] => Some((min.min(max), min.max(max))),
+    _ => None,
+  }
+}
+
+fn font_face_weight(css: &str) -> Option<(u16, u16)> {
+  if css.eq_ignore_ascii_case("auto") {
+    return Some((1, 1000));
+  }
+  let (min, max) = descriptor_range(css, |value| match &*value.to_ascii_lowercase() {
+    "normal" => Some(400.0),
+    "bold" => Some(700.0),
+    value => value
+      .parse::<f32>()
+      .ok()
+      .filter(|weight| (1.0..=1000.0).contains(weight)),
+  })?;
+  Some((min as u16, max as u16))
+}
+
+fn font_face_stretch(css: &str) -> Option<(f32, f32)> {
+  if css.eq_ignore_ascii_case("auto") {
+    return Some((50.0, 200.0));
+  }
+  descriptor_range(css, |value| match &*value.to_ascii_lowercase() {
+    "ultra-condensed" => Some(50.0),
+    "extra-condensed" => Some(62.5),
+    "condensed" => Some(75.0),
+    "semi-condensed" => Some(87.5),
+    "normal" => Some(100.0),
+    "semi-expanded" => Some(112.5),
+    "expanded" => Some(125.0),
+    "extra-expanded" => Some(150.0),
+    "ultra-expanded" => Some(200.0),
+    value => value
+      .strip_suffix('%')
+      .and_then(|percentage| percentage.parse::<f32>().ok()),
+  })
+}
+
+// oblique 后面的角度范围只影响倾斜的程度，匹配时不区分
+fn font_face_style(css: &str) -> Option<FontStyle> {
+  let keyword = css.split_whitespace().next()?.to_ascii_lowercase();
+  match &*keyword {
+    "normal" | "auto" => Some(FontStyle::Normal),
+    "italic" => Some(FontStyle::Italic),
+    "oblique" => Some(FontStyle::Oblique),
+    _ => None,
+  }
+}
+
+// unicode-range: U+0025-00FF, U+4??，? 为通配的十六进制位
+pub fn unicode_range(css: &str) -> Option<Vec<(u32, u32)>> {
+  let mut ranges = Vec::new();
+  for part in css.split(',') {
+    let part = part.trim();
+    let digits = part
+      .strip_prefix("U+")
+      .or_else(|| part.strip_prefix("u+"))?;
+    let hex = |digits: &str| u32::from_str_radix(digits, 16).ok();
+    let range = if digits.contains('?') {
+      (
+        hex(&digits.replace('?', "0"))?,
+        hex(&digits.replace('?', "F"))?,
+      )
+    } else if let Some((start, end)) = digits.split_once('-') {
+      (hex(start)?, hex(end)?)
+    } else {
+      let c = hex(digits)?;
+      (c, c)
     };
+    if range.0 > range.1 || range.1 > 0x10FFFF {
+      return None;
+    }
+    ranges.push(range);
+  }
+  Some(ranges)
+}
 
-    while !self.source_helper.eof() {
-      match self.source_helper.next_char() {
-        // id
-        '#' => {
-          self.source_helper.consume_char();
-          selector.id = Some(self.parse_identifier());
-        }
+// 解析属性 name 的单个值，如 16px、#ffffff、auto，不合法时返回 None
+pub fn parse_value(name: &str, source: String) -> Option<Value> {
+  let mut parser = CSSParser::new(source);
+  let value = parser.parse_value()?;
+  Some(match takes_color(name) {
+    true => resolve_color_keyword(value),
+    false => value,
+  })
+}
 
-        // class
-        '.' => {
-          self.source_helper.consume_char();
-          selector.class.push(self.parse_identifier());
-        }
+// 属性值是否可以包含颜色，包括 color、background-color、border 等简写属性
+// 颜色名只在这些属性中解析为颜色，其他属性中仍是普通关键字，如 font-family: Silver
+fn takes_color(name: &str) -> bool {
+  name == "color"
+    || name.ends_with("-color")
+    || matches!(
+      name,
+      "background" | "border" | "border-top" | "border-right" | "border-bottom" | "border-left"
+    )
+}
 
-        // 通配符
-        '*' => {
-          self.source_helper.consume_char();
-        }
+// 将颜色名转换为颜色，currentcolor 统一为小写，在计算值阶段取 color 属性的值
+fn resolve_color_keyword(value: Value) -> Value {
+  match value {
+    Value::Keyword(name) => match color::named_color(&name) {
+      Some(color) => Value::ColorValue(color),
+      None if name.eq_ignore_ascii_case("currentcolor") => {
+        Value::Keyword("currentcolor".to_string())
+      }
+      None => Value::Keyword(name),
+    },
+    value => value,
+  }
+}
 
-        // tag
-        c if valid_identifier_char(c) => {
-          selector.tag_name = Some(self.parse_identifier());
-        }
+// 是否为自定义属性，如 --main-color
+pub fn is_custom_property(name: &str) -> bool {
+  name.starts_with("--")
+}
 
-        _ => break,
+// 是否引用了自定义属性
+pub fn contains_var(css: &str) -> bool {
+  css.to_ascii_lowercase().contains("var(")
+}
+
+// 将声明的原始文本解析为一个或多个普通属性的值，简写属性会被展开
+// 自定义属性及包含 var() 的值保留原始文本，留到计算值阶段处理
+// 值不合法时返回 None，整条声明被忽略
+pub fn parse_declaration_value(name: &str, css: &str) -> Option<Vec<(String, Value)>> {
+  let css = css.trim();
+  if is_custom_property(name) {
+    let value = if shorthand::is_css_wide_keyword(css) {
+      Value::Keyword(css.to_ascii_lowercase())
+    } else {
+      Value::Unparsed {
+        css: css.to_string(),
+        shorthand: None,
       }
-    }
+    };
+    return Some(vec![(name.to_string(), value)]);
+  }
 
-    selector
+  if contains_var(css) {
+    let values = match shorthand::longhands(name) {
+      Some(longhands) => longhands
+        .into_iter()
+        .map(|longhand| {
+          let value = Value::Unparsed {
+            css: css.to_string(),
+            shorthand: Some(name.to_string()),
+          };
+          (longhand, value)
+        })
+        .collect(),
+      None => vec![(
+        name.to_string(),
+        Value::Unparsed {
+          css: css.to_string(),
+          shorthand: None,
+        },
+      )],
+    };
+    return Some(values);
   }
 
-  // 解析字母数字-_组成的符号
-  fn parse_identifier(&mut self) -> String {
-    self.source_helper.consume_while(valid_identifier_char)
+  // background 只使用其中的颜色，url() 等不支持的函数跳过
+  let mut parser = CSSParser::new(css.to_string());
+  let mut groups = parser.parse_component_groups(name == "background")?;
+  if takes_color(name) {
+    groups = groups
+      .into_iter()
+      .map(|group| group.into_iter().map(resolve_color_keyword).collect())
+      .collect();
   }
 
-  // 解析单个规则中的设置的所有属性
-  fn parse_declarations(&mut self) -> Vec<Declaration> {
-    assert_eq!(self.source_helper.consume_char(), '{');
-    let mut declarations = Vec::new();
+  if shorthand::longhands(name).is_some() {
+    return shorthand::expand(name, &groups);
+  }
+  Some(vec![(name.to_string(), combine_components(groups)?)])
+}
+
+// 解析器
+impl CSSParser {
+  fn new(source: String) -> CSSParser {
+    CSSParser {
+      source_helper: source::SourceHelper {
+        pos: 0,
+        input: source,
+      },
+      diagnostics: Vec::new(),
+    }
+  }
+
+  // 解析 css 规则，直到结尾或 @ 规则块的 "}"
+  fn parse_rules(&mut self) -> Vec<CssRule> {
+    let mut rules = Vec::new();
+
     loop {
       self.source_helper.consume_whitespace();
-      if self.source_helper.next_char() == '}' {
-        self.source_helper.consume_char();
+      if self.source_helper.eof() || self.source_helper.next_char() == '}' {
         break;
       }
+      if let Some(rule) = self.parse_rule() {
+        rules.push(rule);
+      }
+    }
+    rules
+  }
 
-      let declaration = self.parse_declaration();
-      declarations.push(declaration);
+  //  解析单个 css 规则，不支持的 @ 规则返回 None
+  fn parse_rule(&mut self) -> Option<CssRule> {
+    if self.source_helper.next_char() == '@' {
+      return self.parse_at_rule();
     }
-    declarations
+
+    Some(CssRule::Style(Rule {
+      selectors: self.parse_selectors(),
+      declarations: self.parse_declarations(),
+    }))
   }
 
-  // 解析属性，键值对，margin-top: 12px;background-color:red
-  fn parse_declaration(&mut self) -> Declaration {
-    let property_name = self.parse_identifier();
-    self.source_helper.consume_whitespace();
-    assert_eq!(self.source_helper.consume_char(), ':');
+  // 解析 @ 规则，如 @media screen { ... }
+  // 前导部分不合法时跳过整个规则，记录到诊断信息中
+  fn parse_at_rule(&mut self) -> Option<CssRule> {
+    assert_eq!(self.source_helper.consume_char(), '@');
+    let name = self.parse_identifier().to_ascii_lowercase();
     self.source_helper.consume_whitespace();
 
-    let value = self.parse_value();
+    let start = self.source_helper.pos;
+    let rule = match &*name {
+      "import" => self.parse_import_rule().map(CssRule::Import),
+      "media" => self.parse_media_rule().map(CssRule::Media),
+      "supports" => self.parse_supports_rule().map(CssRule::Supports),
+      "layer" => return self.parse_layer_rule().map(CssRule::Layer),
+      "container" => self.parse_container_rule().map(CssRule::Container),
+      "font-face" => return self.parse_font_face_rule().map(CssRule::FontFace),
+      _ => {
+        // 不支持的 @ 规则整体跳过，记录到诊断信息中
+        let start = self.source_helper.pos;
+        self.skip_at_rule();
+        self.diagnostics.push(format!(
+          "Ignored unsupported at-rule @{} at {}",
+          name, start
+        ));
+        return None;
+      }
+    };
+    if rule.is_none() {
+      self.skip_at_rule();
+      self
+        .diagnostics
+        .push(format!("Ignored invalid @{} at {}", name, start));
+    }
+    rule
+  }
+
+  // @media screen and (min-width: 600px) { ... }
+  fn parse_media_rule(&mut self) -> Option<MediaRule> {
+    let queries = self.parse_media_query_list()?;
+    Some(MediaRule {
+      queries,
+      rules: self.parse_rule_block()?,
+    })
+  }
+
+  // @supports (display: grid) and (not (display: inline-grid)) { ... }
+  fn parse_supports_rule(&mut self) -> Option<SupportsRule> {
+    let condition = self.parse_condition(CSSParser::parse_supports_feature)?;
     self.source_helper.consume_whitespace();
-    assert_eq!(self.source_helper.consume_char(), ';');
+    Some(SupportsRule {
+      condition,
+      rules: self.parse_rule_block()?,
+    })
+  }
 
-    Declaration {
-      name: property_name,
-      value: value,
+  // 跳过 @ 规则剩余的部分，直到 ";" 或与 "{" 匹配的 "}"
+  fn skip_at_rule(&mut self) {
+    let mut depth = 0;
+    while !self.source_helper.eof() {
+      match self.source_helper.consume_char() {
+        ';' if depth == 0 => break,
+        '{' => depth += 1,
+        '}' => {
+          depth -= 1;
+          if depth == 0 {
+            break;
+          }
+        }
+        quote @ ('"' | '\'') => {
+          while !self.source_helper.eof() {
+            match self.source_helper.consume_char() {
+              '\\' => {
+                self.source_helper.consume_char();
+              }
+              c if c == quote => break,
+              _ => {}
+            }
+          }
+        }
+        _ => {}
+      }
     }
   }
 
-  // 解析属性值，数字、色值、字符串
-  fn parse_value(&mut self) -> Value {
-    match self.source_helper.next_char() {
-      '0'..='9' => self.parse_length(),
-      '#' => self.parse_color(),
-      _ => Value::Keyword(self.parse_identifier()),
+  // @layer a, b.c; 或 @layer a { ... }、@layer { ... }
+  // 没有层名的声明语句、多个层名后跟规则块时忽略整个规则，记录到诊断信息中
+  fn parse_layer_rule(&mut self) -> Option<LayerRule> {
+    let start = self.source_helper.pos;
+    let mut names = Vec::new();
+    loop {
+      self.source_helper.consume_whitespace();
+      let name = self
+        .source_helper
+        .consume_while(|c| valid_identifier_char(c) || c == '.');
+      if !name.is_empty() {
+        names.push(name);
+      }
+      self.source_helper.consume_whitespace();
+      if self.consume_expected(',').is_none() {
+        break;
+      }
+    }
+
+    let valid = match self.source_helper.eof() {
+      true => false,
+      false => match self.source_helper.next_char() {
+        ';' => !names.is_empty(),
+        '{' => names.len() <= 1,
+        _ => false,
+      },
+    };
+    if !valid {
+      self.skip_at_rule();
+      self
+        .diagnostics
+        .push(format!("Ignored invalid @layer at {}", start));
+      return None;
     }
-  }
 
-  // 解析数字和单位
-  fn parse_length(&mut self) -> Value {
-    Value::Length(self.parse_float(), self.parse_unit())
+    if self.consume_expected(';').is_some() {
+      return Some(LayerRule { names, rules: None });
+    }
+    Some(LayerRule {
+      names,
+      rules: Some(self.parse_rule_block()?),
+    })
   }
 
-  // 解析浮点数
-  fn parse_float(&mut self) -> f32 {
-    let s = self.source_helper.consume_while(|c| match c {
-      '0'..='9' | '.' => true,
-      _ => false,
-    });
+  // @font-face { font-family: ...; src: ...; }，缺少 font-family 或 src 时忽略整个规则
+  // 不支持的描述符及无法解析的值记录到诊断信息中
+  fn parse_font_face_rule(&mut self) -> Option<FontFaceRule> {
+    let start = self.source_helper.pos;
+    if self.consume_expected('{').is_none() {
+      self.skip_at_rule();
+      self
+        .diagnostics
+        .push(format!("Ignored invalid @font-face at {}", start));
+      return None;
+    }
+    let mut rule = FontFaceRule {
+      family: String::new(),
+      sources: Vec::new(),
+      weight: (400, 400),
+      style: FontStyle::Normal,
+      stretch: (100.0, 100.0),
+      unicode_range: Vec::new(),
+    };
+
+    // 没有闭合的 "}" 时到结尾为止，缺少冒号的描述符被忽略
+    loop {
+      self.source_helper.consume_whitespace();
+      if self.source_helper.eof() || self.consume_expected('}').is_some() {
+        break;
+      }
+
+      let name = self.parse_identifier().to_ascii_lowercase();
+      self.source_helper.consume_whitespace();
+      if name.is_empty() || self.consume_expected(':').is_none() {
+        self.skip_declaration();
+        self.diagnostics.push(format!(
+          "Ignored invalid descriptor {} in @font-face at {}",
+          name, start
+        ));
+        continue;
+      }
+      self.source_helper.consume_whitespace();
+      let css = self.parse_raw_value();
+      self.consume_expected(';');
+
+      let css = css.trim();
+      let valid = match &*name {
+        "font-family" => font_face_family(css).map(|family| rule.family = family),
+        "src" => font_face_sources(css).map(|sources| rule.sources = sources),
+        "font-weight" => font_face_weight(css).map(|weight| rule.weight = weight),
+        "font-style" => font_face_style(css).map(|style| rule.style = style),
+        "font-stretch" => font_face_stretch(css).map(|stretch| rule.stretch = stretch),
+        "unicode-range" => unicode_range(css).map(|range| rule.unicode_range = range),
+        _ => {
+          self.diagnostics.push(format!(
+            "Ignored unsupported descriptor {} in @font-face at {}",
+            name, start
+          ));
+          continue;
+        }
+      };
+      if valid.is_none() {
+        self.diagnostics.push(format!(
+          "Ignored invalid {}: {} in @font-face at {}",
+          name, css, start
+        ));
+      }
+    }
 
-    s.parse().unwrap()
+    if rule.family.is_empty() || rule.sources.is_empty() {
+      self.diagnostics.push(format!(
+        "Ignored @font-face without font-family or src at {}",
+        start
+      ));
+      return None;
+    }
+    Some(rule)
   }
 
-  // 解析单位
-  fn parse_unit(&mut self) -> Unit {
-    // &str
-    let unit = self.parse_identifier().to_ascii_lowercase();
-    match &*unit {
-      "px" => Unit::Px,
-      _ => panic!("Unexpected unit"),
+  // 读取函数的参数，当前位置为 "("，参数为字符串或不带引号的文本
+  fn parse_function_argument(&mut self) -> Option<String> {
+    self.consume_expected('(')?;
+    self.source_helper.consume_whitespace();
+    if self.source_helper.eof() {
+      return None;
     }
+    let argument = match self.source_helper.next_char() {
+      '"' | '\'' => self.parse_string_literal(),
+      _ => self
+        .source_helper
+        .consume_while(|c| c != ')')
+        .trim_end()
+        .to_string(),
+    };
+    self.source_helper.consume_whitespace();
+    self.consume_expected(')')?;
+    Some(argument)
   }
 
-  // 解析颜色值
-  fn parse_color(&mut self) -> Value {
-    assert_eq!(self.source_helper.consume_char(), '#');
-    let color = Color {
-      r: self.parse_hex_pair(),
-      g: self.parse_hex_pair(),
-      b: self.parse_hex_pair(),
-      a: 255,
+  // @container [name] (min-width: 400px) { ... }
+  fn parse_container_rule(&mut self) -> Option<ContainerRule> {
+    let start = self.source_helper.pos;
+    let name = self.parse_identifier();
+    let name = if name.is_empty() || name.eq_ignore_ascii_case("not") {
+      self.source_helper.pos = start;
+      None
+    } else {
+      Some(name)
     };
-    Value::ColorValue(color)
+    self.source_helper.consume_whitespace();
+
+    let condition = self.parse_condition(CSSParser::parse_media_feature)?;
+    self.source_helper.consume_whitespace();
+    Some(ContainerRule {
+      name,
+      condition,
+      rules: self.parse_rule_block()?,
+    })
   }
 
-  // 解析十六进制
-  fn parse_hex_pair(&mut self) -> u8 {
-    let s = &self.source_helper.input[self.source_helper.pos..self.source_helper.pos + 2];
+  // 解析 not、and、or 组合的条件，and 与 or 不能混用，需要用括号分组
+  // 任一单项检测不合法时整个条件不合法
+  fn parse_condition<T>(
+    &mut self,
+    parse_test: fn(&mut CSSParser) -> Option<T>,
+  ) -> Option<Condition<T>> {
+    self.source_helper.consume_whitespace();
+    if self.consume_keyword("not") {
+      let condition = self.parse_condition_in_parens(parse_test)?;
+      return Some(Condition::Not(Box::new(condition)));
+    }
+
+    let first = self.parse_condition_in_parens(parse_test)?;
+    self.source_helper.consume_whitespace();
+    let combinator = if self.consume_keyword("and") {
+      "and"
+    } else if self.consume_keyword("or") {
+      "or"
+    } else {
+      return Some(first);
+    };
+
+    let mut conditions = vec![first];
+    loop {
+      conditions.push(self.parse_condition_in_parens(parse_test)?);
+      self.source_helper.consume_whitespace();
+      if !self.consume_keyword(combinator) {
+        break;
+      }
+    }
 
-    self.source_helper.pos += 2;
-    u8::from_str_radix(s, 16).unwrap()
+    match combinator {
+      "and" => Some(Condition::And(conditions)),
+      _ => Some(Condition::Or(conditions)),
+    }
   }
-}
 
-// 有效的字符
-fn valid_identifier_char(c: char) -> bool {
-  match c {
-    'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' => true,
-    _ => false,
+  // 括号中的嵌套条件，或由 parse_test 解析的单项检测
+  fn parse_condition_in_parens<T>(
+    &mut self,
+    parse_test: fn(&mut CSSParser) -> Option<T>,
+  ) -> Option<Condition<T>> {
+    let start = self.source_helper.pos;
+    if self.consume_expected('(').is_some() {
+      self.source_helper.consume_whitespace();
+      let nested = self.peek_char() == Some('(') || self.consume_keyword("not");
+      self.source_helper.pos = start;
+      if nested {
+        self.source_helper.consume_char();
+        let condition = self.parse_condition(parse_test)?;
+        self.source_helper.consume_whitespace();
+        self.consume_expected(')')?;
+        return Some(condition);
+      }
+    }
+
+    parse_test(self).map(Condition::Test)
+  }
+
+  // (display: flex) 或 selector(a > b) 等函数形式，缺少括号时返回 None
+  fn parse_supports_feature(&mut self) -> Option<SupportsFeature> {
+    if self.peek_char() != Some('(') {
+      let name = self.parse_identifier();
+      let args = self.parse_parenthesized()?;
+      return Some(SupportsFeature::Unknown(format!("{}{}", name, args)));
+    }
+
+    let args = self.parse_parenthesized()?;
+    let inner = &args[1..args.len() - 1];
+    match inner.find(':') {
+      Some(colon) => {
+        let name = inner[..colon].trim();
+        let name = if is_custom_property(name) {
+          name.to_string()
+        } else {
+          name.to_ascii_lowercase()
+        };
+        Some(SupportsFeature::Declaration(
+          name,
+          inner[colon + 1..].trim().to_string(),
+        ))
+      }
+      None => Some(SupportsFeature::Unknown(args)),
+    }
+  }
+
+  // 读取以 "(" 开始的文本，直到匹配的 ")"，包含两端括号
+  // 不以 "(" 开始或没有匹配的 ")" 时返回 None
+  fn parse_parenthesized(&mut self) -> Option<String> {
+    if self.peek_char() != Some('(') {
+      return None;
+    }
+    let mut text = String::new();
+    let mut depth = 0;
+    loop {
+      let c = self.peek_char()?;
+      self.source_helper.consume_char();
+      text.push(c);
+      match c {
+        '(' => depth += 1,
+        ')' => {
+          depth -= 1;
+          if depth == 0 {
+            break;
+          }
+        }
+        _ => {}
+      }
+    }
+    Some(text)
+  }
+
+  // 解析 @import url("a.css") screen; 引用的样式表在解析完成后加载
+  // 地址不是字符串或 url()、缺少结尾的 ";" 时返回 None
+  fn parse_import_rule(&mut self) -> Option<ImportRule> {
+    let url = match self.peek_char() {
+      Some('"' | '\'') => self.parse_string_literal(),
+      _ if self.parse_identifier().eq_ignore_ascii_case("url") => self.parse_function_argument()?,
+      _ => return None,
+    };
+
+    let queries = self.parse_media_query_list()?;
+    self.consume_expected(';')?;
+    Some(ImportRule {
+      url,
+      queries,
+      rules: Vec::new(),
+    })
+  }
+
+  // 解析 @ 规则中 {} 包裹的规则，没有闭合的 "}" 时到结尾为止
+  fn parse_rule_block(&mut self) -> Option<Vec<CssRule>> {
+    self.consume_expected('{')?;
+    let rules = self.parse_rules();
+    self.consume_expected('}');
+    Some(rules)
+  }
+
+  // 解析逗号分隔的媒体查询列表，直到 "{"、";" 或结尾
+  // 任一查询不合法时返回 None，停在出错的位置
+  fn parse_media_query_list(&mut self) -> Option<MediaQueryList> {
+    let mut queries = Vec::new();
+    loop {
+      self.source_helper.consume_whitespace();
+      if matches!(self.peek_char(), None | Some('{' | ';')) {
+        break;
+      }
+
+      queries.push(self.parse_media_query()?);
+      self.source_helper.consume_whitespace();
+      if self.consume_expected(',').is_none() && !matches!(self.peek_char(), None | Some('{' | ';'))
+      {
+        return None;
+      }
+    }
+    Some(MediaQueryList { queries })
+  }
+
+  // 解析单个媒体查询，[not | only]? 媒体类型 [and (特性)]*，或只有特性 (特性) [and (特性)]*
+  fn parse_media_query(&mut self) -> Option<MediaQuery> {
+    let mut negated = false;
+    let mut media_type = MediaType::All;
+    let mut features = Vec::new();
+
+    if self.source_helper.next_char() != '(' {
+      let mut name = self.parse_identifier().to_ascii_lowercase();
+      self.source_helper.consume_whitespace();
+      if name.is_empty() {
+        return None;
+      }
+      if name == "not" || name == "only" {
+        negated = name == "not";
+        name = self.parse_identifier().to_ascii_lowercase();
+        self.source_helper.consume_whitespace();
+      }
+
+      media_type = match &*name {
+        // not (特性) 的形式，没有媒体类型
+        "" => MediaType::All,
+        "all" => MediaType::All,
+        "screen" => MediaType::Screen,
+        "print" => MediaType::Print,
+        _ => MediaType::Unknown(name.clone()),
+      };
+      // 媒体类型后面没有 and 时，查询中没有特性
+      if !name.is_empty() && !self.consume_keyword("and") {
+        return Some(MediaQuery {
+          negated,
+          media_type,
+          features,
+        });
+      }
+    }
+
+    loop {
+      self.source_helper.consume_whitespace();
+      features.push(self.parse_media_feature()?);
+      self.source_helper.consume_whitespace();
+      if !self.consume_keyword("and") {
+        break;
+      }
+    }
+
+    Some(MediaQuery {
+      negated,
+      media_type,
+      features,
+    })
+  }
+
+  // 下一个标识符为 keyword 时消费它，否则保持位置不变
+  fn consume_keyword(&mut self, keyword: &str) -> bool {
+    let start = self.source_helper.pos;
+    if self.parse_identifier().eq_ignore_ascii_case(keyword) {
+      self.source_helper.consume_whitespace();
+      true
+    } else {
+      self.source_helper.pos = start;
+      false
+    }
+  }
+
+  // 解析括号中的媒体特性
+  // (color)、(orientation: portrait)、(min-width: 600px)、(width >= 600px)、(400px < width <= 800px)
+  // 缺少括号、特性名、比较符或值时返回 None
+  fn parse_media_feature(&mut self) -> Option<MediaFeature> {
+    self.consume_expected('(')?;
+    self.source_helper.consume_whitespace();
+
+    let feature = if self.starts_with_number() {
+      let value = self.parse_media_value()?;
+      self.source_helper.consume_whitespace();
+      let op = self.parse_range_op()?;
+      self.source_helper.consume_whitespace();
+      let name = self.parse_feature_name()?;
+
+      let mut comparisons = vec![(op.flip(), value)];
+      if self.peek_char() != Some(')') {
+        let op = self.parse_range_op()?;
+        self.source_helper.consume_whitespace();
+        comparisons.push((op, self.parse_media_value()?));
+        self.source_helper.consume_whitespace();
+      }
+      MediaFeature::Range(name, comparisons)
+    } else {
+      let name = self.parse_feature_name()?;
+      match self.peek_char()? {
+        ')' => MediaFeature::Boolean(name),
+        ':' => {
+          self.source_helper.consume_char();
+          self.source_helper.consume_whitespace();
+          let value = self.parse_media_value()?;
+          self.source_helper.consume_whitespace();
+
+          if let Some(name) = name.strip_prefix("min-") {
+            MediaFeature::Range(name.to_string(), vec![(RangeOp::Ge, value)])
+          } else if let Some(name) = name.strip_prefix("max-") {
+            MediaFeature::Range(name.to_string(), vec![(RangeOp::Le, value)])
+          } else {
+            match value {
+              Value::Keyword(keyword) => MediaFeature::Discrete(name, keyword.to_ascii_lowercase()),
+              value => MediaFeature::Range(name, vec![(RangeOp::Eq, value)]),
+            }
+          }
+        }
+        _ => {
+          let op = self.parse_range_op()?;
+          self.source_helper.consume_whitespace();
+          let value = self.parse_media_value()?;
+          self.source_helper.consume_whitespace();
+          MediaFeature::Range(name, vec![(op, value)])
+        }
+      }
+    };
+
+    self.consume_expected(')')?;
+    Some(feature)
+  }
+
+  // 媒体特性名，转换为小写，为空时返回 None
+  fn parse_feature_name(&mut self) -> Option<String> {
+    let name = self.parse_identifier().to_ascii_lowercase();
+    self.source_helper.consume_whitespace();
+    (!name.is_empty()).then_some(name)
+  }
+
+  // 媒体特性的值，无法解析时为空关键字，与任何特性都不匹配
+  // 缺少值时返回 None
+  fn parse_media_value(&mut self) -> Option<Value> {
+    if matches!(self.peek_char(), None | Some(')' | '{' | ';')) {
+      return None;
+    }
+    let start = self.source_helper.pos;
+    let value = self.parse_value().unwrap_or_else(|| {
+      self.source_helper.pos = start;
+      self
+        .source_helper
+        .consume_while(|c| !c.is_whitespace() && !matches!(c, ')' | '{' | ';'));
+      Value::Keyword(String::new())
+    });
+    Some(value)
+  }
+
+  // 解析范围比较符 <、<=、=、>=、>，其他字符返回 None
+  fn parse_range_op(&mut self) -> Option<RangeOp> {
+    let c = self.peek_char()?;
+    let or_equal =
+      self.source_helper.input[self.source_helper.pos + c.len_utf8()..].starts_with('=');
+    let op = match (c, or_equal) {
+      ('<', false) => RangeOp::Lt,
+      ('<', true) => RangeOp::Le,
+      ('>', false) => RangeOp::Gt,
+      ('>', true) => RangeOp::Ge,
+      ('=', _) => RangeOp::Eq,
+      _ => return None,
+    };
+    self.source_helper.consume_char();
+    if or_equal && c != '=' {
+      self.source_helper.consume_char();
+    }
+    Some(op)
+  }
+
+  // 解析组合选择器，以","分隔，返回数组
+  fn parse_selectors(&mut self) -> Vec<Selector> {
+    let mut selectors = Vec::new();
+    loop {
+      selectors.push(self.parse_selector());
+
+      match self.source_helper.next_char() {
+        '{' => break,
+        ',' => {
+          self.source_helper.consume_char();
+          self.source_helper.consume_whitespace();
+        }
+        c => panic!("Unexpected char {} in selector list!", c),
+      }
+    }
+
+    // 保持书写顺序，优先级由层叠阶段比较
+    selectors
+  }
+
+  // 解析以空格或 > 连接的选择器，遇到 { 或 , 结束
+  fn parse_selector(&mut self) -> Selector {
+    let mut selector = Selector::Simple(self.parse_compound_selector());
+    loop {
+      self.source_helper.consume_whitespace();
+      match self.source_helper.next_char() {
+        '{' | ',' => return selector,
+        '>' => {
+          self.source_helper.consume_char();
+          self.source_helper.consume_whitespace();
+          selector = Selector::Child(Box::new(selector), self.parse_compound_selector());
+        }
+        _ => selector = Selector::Descendant(Box::new(selector), self.parse_compound_selector()),
+      }
+    }
+  }
+
+  // 解析组合器之间的简单选择器，不能为空
+  fn parse_compound_selector(&mut self) -> SimpleSelector {
+    let start = self.source_helper.pos;
+    let selector = self.parse_simple_selector();
+    if self.source_helper.pos == start {
+      panic!(
+        "Unexpected char {} in selector!",
+        self.source_helper.next_char()
+      );
+    }
+    selector
+  }
+
+  // 解析单个选择器
+  fn parse_simple_selector(&mut self) -> SimpleSelector {
+    let mut selector = SimpleSelector {
+      tag_name: None,
+      id: None,
+      class: Vec::new(),
+    };
+
+    while !self.source_helper.eof() {
+      match self.source_helper.next_char() {
+        // id
+        '#' => {
+          self.source_helper.consume_char();
+          selector.id = Some(self.parse_identifier());
+        }
+
+        // class
+        '.' => {
+          self.source_helper.consume_char();
+          selector.class.push(self.parse_identifier());
+        }
+
+        // 通配符
+        '*' => {
+          self.source_helper.consume_char();
+        }
+
+        // tag
+        c if valid_identifier_char(c) => {
+          selector.tag_name = Some(self.parse_identifier());
+        }
+
+        _ => break,
+      }
+    }
+
+    selector
+  }
+
+  // 解析字母数字-_组成的符号
+  fn parse_identifier(&mut self) -> String {
+    self.source_helper.consume_while(valid_identifier_char)
+  }
+
+  // 解析单个规则中的设置的所有属性，简写属性展开为普通属性
+  // 没有闭合的 "}" 时到结尾为止
+  fn parse_declarations(&mut self) -> Vec<Declaration> {
+    assert_eq!(self.source_helper.consume_char(), '{');
+    let mut declarations = Vec::new();
+    loop {
+      self.source_helper.consume_whitespace();
+      if self.source_helper.eof() || self.consume_expected('}').is_some() {
+        break;
+      }
+
+      declarations.extend(self.parse_declaration());
+    }
+    declarations
+  }
+
+  // 解析属性，键值对，margin-top: 12px;background-color:red
+  // 最后一条声明的分号可省略，自定义属性名区分大小写
+  // 缺少属性名或冒号、!important 拼写错误或其后还有其他内容时，整条声明不合法
+  fn parse_declaration(&mut self) -> Vec<Declaration> {
+    let start = self.source_helper.pos;
+    let mut property_name = self.parse_identifier();
+    if !is_custom_property(&property_name) {
+      property_name = property_name.to_ascii_lowercase();
+    }
+    self.source_helper.consume_whitespace();
+    let has_colon = self.consume_expected(':').is_some();
+    self.source_helper.consume_whitespace();
+
+    let (css, important) = match has_colon && !property_name.is_empty() {
+      true => (self.parse_raw_value(), self.parse_important()),
+      false => (String::new(), None),
+    };
+    // !important 之后只能是声明的结尾
+    let terminated =
+      self.source_helper.eof() || matches!(self.source_helper.next_char(), ';' | '}');
+    self.skip_declaration();
+
+    let values = match important {
+      Some(important) if terminated => {
+        parse_declaration_value(&property_name, &css).map(|values| (values, important))
+      }
+      _ => None,
+    };
+    match values {
+      Some((values, important)) => values
+        .into_iter()
+        .map(|(name, value)| Declaration {
+          name,
+          value,
+          important,
+        })
+        .collect(),
+      None => {
+        self.diagnostics.push(format!(
+          "Ignored invalid declaration {}: {} at {}",
+          property_name,
+          css.trim(),
+          start
+        ));
+        Vec::new()
+      }
+    }
+  }
+
+  // 读取属性值的原始文本，直到最外层的 ;、}、!important 或结尾
+  // 跳过括号与字符串内部的内容
+  fn parse_raw_value(&mut self) -> String {
+    let mut css = String::new();
+    let mut depth = 0;
+    while !self.source_helper.eof() {
+      match self.source_helper.next_char() {
+        ';' | '}' | '!' if depth == 0 => break,
+        '(' | '[' | '{' => depth += 1,
+        ')' | ']' | '}' if depth > 0 => depth -= 1,
+        quote @ ('"' | '\'') => {
+          css.push(self.source_helper.consume_char());
+          while !self.source_helper.eof() {
+            let c = self.source_helper.consume_char();
+            css.push(c);
+            if c == '\\' && !self.source_helper.eof() {
+              css.push(self.source_helper.consume_char());
+            } else if c == quote {
+              break;
+            }
+          }
+          continue;
+        }
+        _ => {}
+      }
+      css.push(self.source_helper.consume_char());
+    }
+    css
+  }
+
+  // 解析由空白和逗号分隔的多个值直到结尾，如 1px solid red、bold 12px/1.5 Arial, sans-serif
+  // 逗号分隔出多组，每组内为空白分隔的值，"/" 作为单独的关键字保留
+  // skip_unknown_functions 时跳过不支持的函数，否则与其他不合法的值一样返回 None
+  fn parse_component_groups(&mut self, skip_unknown_functions: bool) -> Option<Vec<Vec<Value>>> {
+    let mut groups = vec![Vec::new()];
+    loop {
+      self.source_helper.consume_whitespace();
+      if self.source_helper.eof() {
+        break;
+      }
+      match self.source_helper.next_char() {
+        ',' => {
+          self.source_helper.consume_char();
+          groups.push(Vec::new());
+        }
+        '/' => {
+          self.source_helper.consume_char();
+          groups
+            .last_mut()
+            .unwrap()
+            .push(Value::Keyword("/".to_string()));
+        }
+        _ => {
+          let start = self.source_helper.pos;
+          match self.parse_value() {
+            Some(value) if self.source_helper.pos > start => groups.last_mut().unwrap().push(value),
+            _ if skip_unknown_functions => {
+              self.source_helper.pos = start;
+              self.skip_unknown_function()?;
+            }
+            _ => return None,
+          }
+        }
+      }
+    }
+
+    if groups.iter().any(|group| group.is_empty()) {
+      return None;
+    }
+    Some(groups)
+  }
+
+  // 跳过一个不支持的函数，如 url(a.png)、linear-gradient(...)
+  fn skip_unknown_function(&mut self) -> Option<()> {
+    let name = self.parse_identifier();
+    if name.is_empty() || SUPPORTED_FUNCTIONS.contains(&&*name.to_ascii_lowercase()) {
+      return None;
+    }
+    self.consume_expected('(')?;
+    let mut depth = 1;
+    while depth > 0 {
+      if self.source_helper.eof() {
+        return None;
+      }
+      match self.source_helper.consume_char() {
+        '(' => depth += 1,
+        ')' => depth -= 1,
+        _ => {}
+      }
+    }
+    Some(())
+  }
+
+  // 下一个字符，结尾时为 None
+  fn peek_char(&self) -> Option<char> {
+    self.source_helper.input[self.source_helper.pos..]
+      .chars()
+      .next()
+  }
+
+  // 下一个字符为 c 时消费它，否则返回 None
+  fn consume_expected(&mut self, c: char) -> Option<()> {
+    if self.source_helper.eof() || self.source_helper.next_char() != c {
+      return None;
+    }
+    self.source_helper.consume_char();
+    Some(())
+  }
+
+  // 跳过声明剩余的部分，直到最外层的 ";"（包括）、"}"（不包括）或结尾
+  fn skip_declaration(&mut self) {
+    loop {
+      self.parse_raw_value();
+      if self.consume_expected('!').is_none() {
+        break;
+      }
+    }
+    self.consume_expected(';');
+  }
+
+  // 解析值后面可选的 !important，"!" 与 important 之间可以有空白
+  // "!" 后面不是 important 时返回 None
+  fn parse_important(&mut self) -> Option<bool> {
+    if self.consume_expected('!').is_none() {
+      return Some(false);
+    }
+
+    self.source_helper.consume_whitespace();
+    if !self.parse_identifier().eq_ignore_ascii_case("important") {
+      return None;
+    }
+    self.source_helper.consume_whitespace();
+    Some(true)
+  }
+
+  // 解析属性值，数字、色值、字符串，不合法时返回 None
+  // 标识符都作为关键字，颜色名由接受颜色的属性再转换
+  fn parse_value(&mut self) -> Option<Value> {
+    let value = match self.source_helper.next_char() {
+      _ if self.starts_with_number() => return self.parse_length(),
+      '#' => return self.parse_color(),
+      '"' | '\'' => self.parse_string(),
+      _ => {
+        let name = self.parse_identifier();
+        if !self.source_helper.eof() && self.source_helper.next_char() == '(' {
+          return self.parse_function(&name);
+        } else {
+          Value::Keyword(name)
+        }
+      }
+    };
+    Some(value)
+  }
+
+  // 解析带引号的字符串，支持 \ 转义
+  fn parse_string(&mut self) -> Value {
+    Value::Str(self.parse_string_literal())
+  }
+
+  // 没有闭合的引号时到结尾为止
+  fn parse_string_literal(&mut self) -> String {
+    let quote = self.source_helper.consume_char();
+    let mut s = String::new();
+    while !self.source_helper.eof() {
+      match self.source_helper.consume_char() {
+        c if c == quote => break,
+        '\\' if !self.source_helper.eof() => s.push(self.source_helper.consume_char()),
+        c => s.push(c),
+      }
+    }
+    s
+  }
+
+  // 解析函数，如 calc(100% - 2 * 16px)，不支持的函数返回 None
+  // 数学函数类型检查不通过时（如 1px * 2px、除以 0）整个值不合法
+  fn parse_function(&mut self, name: &str) -> Option<Value> {
+    match &*name.to_ascii_lowercase() {
+      "calc" | "min" | "max" | "clamp" => {
+        let node = self.parse_math_function(name)?;
+        node.calc_type()?;
+        Some(Value::Calc(Box::new(node)))
+      }
+      "rgb" | "rgba" | "hsl" | "hsla" | "hwb" => self.parse_color_function(name),
+      _ => None,
+    }
+  }
+
+  // 解析数学函数的参数部分，当前位置为 "("
+  fn parse_math_function(&mut self, name: &str) -> Option<CalcNode> {
+    self.consume_expected('(')?;
+    let mut args = Vec::new();
+    loop {
+      self.source_helper.consume_whitespace();
+      args.push(self.parse_calc_sum()?);
+      self.source_helper.consume_whitespace();
+      if self.consume_expected(')').is_some() {
+        break;
+      }
+      self.consume_expected(',')?;
+    }
+
+    let node = match (&*name.to_ascii_lowercase(), args.len()) {
+      ("calc", 1) => args.remove(0),
+      ("min", _) => CalcNode::Min(args),
+      ("max", _) => CalcNode::Max(args),
+      ("clamp", 3) => {
+        let max = args.pop().unwrap();
+        let value = args.pop().unwrap();
+        let min = args.pop().unwrap();
+        CalcNode::Clamp(Box::new(min), Box::new(value), Box::new(max))
+      }
+      _ => return None,
+    };
+    Some(node)
+  }
+
+  // 加减，运算符两边必须有空白，以便和数字的正负号区分
+  fn parse_calc_sum(&mut self) -> Option<CalcNode> {
+    let mut node = self.parse_calc_product()?;
+    loop {
+      let start = self.source_helper.pos;
+      self.source_helper.consume_whitespace();
+      let has_whitespace = self.source_helper.pos > start;
+      if !has_whitespace
+        || !(self.source_helper.starts_with("+") || self.source_helper.starts_with("-"))
+      {
+        self.source_helper.pos = start;
+        return Some(node);
+      }
+
+      let op = self.source_helper.consume_char();
+      self.source_helper.consume_whitespace();
+      let rhs = Box::new(self.parse_calc_product()?);
+      node = match op {
+        '+' => CalcNode::Sum(Box::new(node), rhs),
+        _ => CalcNode::Difference(Box::new(node), rhs),
+      };
+    }
+  }
+
+  // 乘除
+  fn parse_calc_product(&mut self) -> Option<CalcNode> {
+    let mut node = self.parse_calc_value()?;
+    loop {
+      let start = self.source_helper.pos;
+      self.source_helper.consume_whitespace();
+      if !(self.source_helper.starts_with("*") || self.source_helper.starts_with("/")) {
+        self.source_helper.pos = start;
+        return Some(node);
+      }
+
+      let op = self.source_helper.consume_char();
+      self.source_helper.consume_whitespace();
+      let rhs = Box::new(self.parse_calc_value()?);
+      node = match op {
+        '*' => CalcNode::Product(Box::new(node), rhs),
+        _ => CalcNode::Quotient(Box::new(node), rhs),
+      };
+    }
+  }
+
+  // 数字、长度、百分比、括号或嵌套的数学函数
+  fn parse_calc_value(&mut self) -> Option<CalcNode> {
+    if self.starts_with_number() {
+      return match self.parse_length()? {
+        Value::Number(f) => Some(CalcNode::Number(f)),
+        Value::Percentage(f) => Some(CalcNode::Percentage(f)),
+        Value::Length(f, unit) => Some(CalcNode::Length(f, unit)),
+        _ => None,
+      };
+    }
+
+    if self.source_helper.starts_with("(") {
+      return self.parse_math_function("calc");
+    }
+
+    let name = self.parse_identifier();
+    match &*name.to_ascii_lowercase() {
+      "calc" | "min" | "max" | "clamp" => self.parse_math_function(&name),
+      _ => None,
+    }
+  }
+
+  // 是否以数字开头，如 12、.5、-3、+1.5
+  fn starts_with_number(&self) -> bool {
+    let mut chars = self.source_helper.input[self.source_helper.pos..].chars();
+    let first = chars.next();
+    let second = chars.next();
+    let third = chars.next();
+    matches!(
+      (first, second, third),
+      (Some('0'..='9'), _, _)
+        | (Some('.'), Some('0'..='9'), _)
+        | (Some('+' | '-'), Some('0'..='9'), _)
+        | (Some('+' | '-'), Some('.'), Some('0'..='9'))
+    )
+  }
+
+  // 解析数字和单位，没有单位时为数字，% 为百分比
+  // 不支持的单位，如 1s、1fr，返回 None
+  fn parse_length(&mut self) -> Option<Value> {
+    let f = self.parse_float()?;
+    if self.consume_expected('%').is_some() {
+      return Some(Value::Percentage(f));
+    }
+
+    // 分辨率单位统一转换为 dppx
+    let unit = self.parse_identifier().to_ascii_lowercase();
+    let value = match &*unit {
+      "" => Value::Number(f),
+      "dpi" => Value::Resolution(f / 96.0),
+      "dpcm" => Value::Resolution(f * 2.54 / 96.0),
+      "dppx" | "x" => Value::Resolution(f),
+      _ => Value::Length(f, parse_unit(&unit)?),
+    };
+    Some(value)
+  }
+
+  // 解析浮点数
+  fn parse_float(&mut self) -> Option<f32> {
+    let mut s = String::new();
+    if matches!(self.source_helper.next_char(), '+' | '-') {
+      s.push(self.source_helper.consume_char());
+    }
+    s.push_str(
+      &self
+        .source_helper
+        .consume_while(|c| matches!(c, '0'..='9' | '.')),
+    );
+
+    s.parse().ok()
+  }
+
+  // 解析颜色值
+  // #rgb、#rgba、#rrggbb、#rrggbbaa
+  fn parse_color(&mut self) -> Option<Value> {
+    assert_eq!(self.source_helper.consume_char(), '#');
+    let hex = self.source_helper.consume_while(|c| c.is_ascii_hexdigit());
+
+    // 简写形式每一位重复一次，如 #f80 -> #ff8800
+    let digits: Vec<u8> = match hex.len() {
+      3 | 4 => hex
+        .chars()
+        .map(|c| c.to_digit(16).unwrap() as u8 * 17)
+        .collect(),
+      6 | 8 => (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+        .collect(),
+      _ => return None,
+    };
+
+    let color = Color {
+      r: digits[0],
+      g: digits[1],
+      b: digits[2],
+      a: digits.get(3).cloned().unwrap_or(255),
+    };
+    Some(Value::ColorValue(color))
+  }
+
+  // 解析颜色函数 rgb()、rgba()、hsl()、hsla()、hwb()
+  // 同时支持逗号分隔的旧语法和空格分隔、"/" 指定透明度的新语法
+  fn parse_color_function(&mut self, name: &str) -> Option<Value> {
+    self.consume_expected('(')?;
+    let mut args = Vec::new();
+    let mut alpha = None;
+    loop {
+      self.source_helper.consume_whitespace();
+      if self.source_helper.eof() {
+        return None;
+      }
+      match self.source_helper.next_char() {
+        ')' => {
+          self.source_helper.consume_char();
+          break;
+        }
+        ',' => {
+          self.source_helper.consume_char();
+        }
+        '/' => {
+          self.source_helper.consume_char();
+          self.source_helper.consume_whitespace();
+          alpha = Some(self.parse_color_component()?);
+        }
+        _ => args.push(self.parse_color_component()?),
+      }
+    }
+
+    // 旧语法中透明度是第 4 个参数
+    if args.len() == 4 && alpha.is_none() {
+      alpha = args.pop();
+    }
+    if args.len() != 3 {
+      return None;
+    }
+
+    let (r, g, b) = match &*name.to_ascii_lowercase() {
+      "rgb" | "rgba" => (
+        args[0].fraction(255.0),
+        args[1].fraction(255.0),
+        args[2].fraction(255.0),
+      ),
+      "hsl" | "hsla" => color::hsl_to_rgb(
+        args[0].value(),
+        args[1].fraction(100.0),
+        args[2].fraction(100.0),
+      ),
+      _ => color::hwb_to_rgb(
+        args[0].value(),
+        args[1].fraction(100.0),
+        args[2].fraction(100.0),
+      ),
+    };
+
+    let channel = |f: f32| (f.clamp(0.0, 1.0) * 255.0).round() as u8;
+    Some(Value::ColorValue(Color {
+      r: channel(r),
+      g: channel(g),
+      b: channel(b),
+      a: channel(alpha.map_or(1.0, |alpha| alpha.fraction(1.0))),
+    }))
+  }
+
+  // 颜色函数的参数，角度统一转换为 deg
+  fn parse_color_component(&mut self) -> Option<ColorComponent> {
+    let f = self.parse_float()?;
+    if self.consume_expected('%').is_some() {
+      return Some(ColorComponent::Percentage(f));
+    }
+
+    let unit = self.parse_identifier().to_ascii_lowercase();
+    let component = match &*unit {
+      "" | "deg" => ColorComponent::Number(f),
+      "rad" => ColorComponent::Number(f.to_degrees()),
+      "grad" => ColorComponent::Number(f * 0.9),
+      "turn" => ColorComponent::Number(f * 360.0),
+      _ => return None,
+    };
+    Some(component)
+  }
+}
+
+// 颜色函数的参数
+enum ColorComponent {
+  Number(f32),
+  Percentage(f32),
+}
+
+impl ColorComponent {
+  fn value(&self) -> f32 {
+    match *self {
+      ColorComponent::Number(f) | ColorComponent::Percentage(f) => f,
+    }
+  }
+
+  // 转换为 0~1 的比例，数字相对 max，百分比相对 100%
+  fn fraction(&self, max: f32) -> f32 {
+    match *self {
+      ColorComponent::Number(f) => f / max,
+      ColorComponent::Percentage(p) => p / 100.0,
+    }
+  }
+}
+
+// 普通属性的多个值，单个值直接使用，逗号分隔的多组组成列表
+// 组内多个关键字以空格连接，如字体名 Times New Roman，其他多个值不支持，返回 None
+fn combine_components(mut groups: Vec<Vec<Value>>) -> Option<Value> {
+  let combine_group = |group: Vec<Value>| match group.len() {
+    1 => group.into_iter().next(),
+    _ if group.iter().all(|value| matches!(value, Value::Keyword(_))) => {
+      shorthand::family_name(&group)
+    }
+    _ => None,
+  };
+
+  if groups.len() == 1 {
+    combine_group(groups.pop().unwrap())
+  } else {
+    let values = groups
+      .into_iter()
+      .map(combine_group)
+      .collect::<Option<_>>()?;
+    Some(Value::List(values))
+  }
+}
+
+// 支持的函数，其余函数使声明无效
+const SUPPORTED_FUNCTIONS: [&str; 9] = [
+  "calc", "min", "max", "clamp", "rgb", "rgba", "hsl", "hsla", "hwb",
+];
+
+// 解析长度单位，不支持的单位返回 None
+fn parse_unit(unit: &str) -> Option<Unit> {
+  let unit = match unit {
+    "px" => Unit::Px,
+    "pt" => Unit::Pt,
+    "pc" => Unit::Pc,
+    "cm" => Unit::Cm,
+    "mm" => Unit::Mm,
+    "q" => Unit::Q,
+    "in" => Unit::In,
+    "em" => Unit::Em,
+    "rem" => Unit::Rem,
+    "ex" => Unit::Ex,
+    "ch" => Unit::Ch,
+    "vw" => Unit::Vw,
+    "vh" => Unit::Vh,
+    "vmin" => Unit::Vmin,
+    "vmax" => Unit::Vmax,
+    _ => return None,
+  };
+  Some(unit)
+}
+
+fn valid_identifier_char(c: char) -> bool {
+  matches!(c, 'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_')
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::media::MediaEnvironment;
+
+  // 解析单条规则，返回其中展开后的声明及诊断信息
+  fn parse_declarations(css: &str) -> (Vec<(String, Value)>, Vec<String>) {
+    let sheet = parse(format!("p {{ {} }}", css));
+    let declarations = match &sheet.rules[..] {
+      [CssRule::Style(rule)] => rule
+        .declarations
+        .iter()
+        .map(|declaration| (declaration.name.clone(), declaration.value.clone()))
+        .collect(),
+      rules => panic!("Expected a single style rule, got {:?}", rules),
+    };
+    (declarations, sheet.diagnostics)
+  }
+
+  fn color(name: &str) -> Value {
+    Value::ColorValue(color::named_color(name).unwrap())
+  }
+
+  // 无效的声明被忽略并记录诊断信息，同一规则中后面的声明不受影响
+  fn assert_dropped(css: &str) {
+    let (declarations, diagnostics) = parse_declarations(&format!("{}; color: blue", css));
+    assert_eq!(
+      declarations,
+      vec![("color".to_string(), color("blue"))],
+      "{}",
+      css
+    );
+    assert_eq!(diagnostics.len(), 1, "{}", css);
+  }
+
+  // 规则中各声明的属性名与是否为 !important
+  fn importance(css: &str) -> Vec<(String, bool)> {
+    match &parse(format!("p {{ {} }}", css)).rules[..] {
+      [CssRule::Style(rule)] => rule
+        .declarations
+        .iter()
+        .map(|declaration| (declaration.name.clone(), declaration.important))
+        .collect(),
+      rules => panic!("Expected a single style rule, got {:?}", rules),
+    }
+  }
+
+  #[test]
+  fn parses_important() {
+    for css in [
+      "color: red !important",
+      "color: red!important;",
+      "color: red ! important",
+      "color: red !IMPORTANT ;",
+    ] {
+      assert_eq!(
+        importance(css),
+        vec![("color".to_string(), true)],
+        "{}",
+        css
+      );
+    }
+    assert_eq!(
+      importance("color: red; width: 1px !important"),
+      vec![("color".to_string(), false), ("width".to_string(), true)]
+    );
+  }
+
+  #[test]
+  fn drops_invalid_declarations() {
+    assert_dropped("color: red !imporant");
+    assert_dropped("color: red !important foo");
+    assert_dropped("color: red !");
+    assert_dropped("color");
+    assert_dropped("color red");
+    assert_dropped(": red");
+
+    // 没有闭合的规则到结尾为止
+    let sheet = parse("p { color: red; width".to_string());
+    assert_eq!(sheet.diagnostics.len(), 1);
+    assert_eq!(
+      importance("color: red; width: 1px !impo"),
+      vec![("color".to_string(), false)]
+    );
+  }
+
+  #[test]
+  fn color_names_are_colors_only_in_color_properties() {
+    let keyword = |s: &str| Value::Keyword(s.to_string());
+    let (declarations, _) =
+      parse_declarations("font-family: Silver, Dark Red; border-top: 1px solid silver");
+    assert_eq!(
+      declarations,
+      vec![
+        (
+          "font-family".to_string(),
+          Value::List(vec![keyword("Silver"), keyword("Dark Red")])
+        ),
+        ("border-top-width".to_string(), Value::Length(1.0, Unit::Px)),
+        ("border-top-style".to_string(), keyword("solid")),
+        ("border-top-color".to_string(), color("silver")),
+      ]
+    );
+
+    let (declarations, _) = parse_declarations("font: 12px Silver; background-color: Silver");
+    assert_eq!(
+      declarations.last(),
+      Some(&("background-color".to_string(), color("silver")))
+    );
+    assert!(declarations.contains(&(
+      "font-family".to_string(),
+      Value::List(vec![keyword("Silver")])
+    )));
+  }
+
+  #[test]
+  fn drops_malformed_font_face_sources() {
+    for src in [
+      r#"local("A") url(x.ttf)"#,
+      r#"local("A"),"#,
+      r#"url("x.ttf" y)"#,
+      r#"url(x.woff2) format("woff2") tech"#,
+    ] {
+      // src 无效，没有 src 的规则也被忽略
+      let sheet = parse(format!("@font-face {{ font-family: A; src: {} }}", src));
+      assert!(sheet.rules.is_empty(), "{}", src);
+      assert_eq!(sheet.diagnostics.len(), 2, "{}", src);
+    }
+
+    // 截断的值
+    for src in ["url(x.ttf", "url(", "url( ", r#"url(x.woff2) format("#] {
+      assert!(font_face_sources(src).is_none(), "{}", src);
+    }
+
+    let sheet = parse(
+      r#"@font-face { font-family: A; src: local("A"), url(x.woff2) format("woff2") }"#.to_string(),
+    );
+    match &sheet.rules[..] {
+      [CssRule::FontFace(rule)] => assert_eq!(rule.sources.len(), 2),
+      rules => panic!("{:?}", rules),
+    }
+  }
+
+  #[test]
+  fn drops_malformed_font_face_descriptors() {
+    // 截断的规则缺少 font-family 和 src，整个规则被忽略
+    for css in [
+      "@font-face { font-family",
+      "@font-face { font-family:",
+      "@font-face { : A; src: url(a.ttf) }",
+    ] {
+      let sheet = parse(css.to_string());
+      assert!(sheet.rules.is_empty(), "{}", css);
+      assert_eq!(sheet.diagnostics.len(), 2, "{}", css);
+    }
+    for css in ["@font-face", "@font-face; p { color: red }"] {
+      let sheet = parse(css.to_string());
+      assert!(
+        sheet
+          .rules
+          .iter()
+          .all(|rule| matches!(rule, CssRule::Style(_))),
+        "{}",
+        css
+      );
+      assert_eq!(sheet.diagnostics.len(), 1, "{}", css);
+    }
+
+    // 缺少冒号的描述符被忽略，其他描述符不受影响
+    let sheet = parse(
+      "@font-face { font-family: A; font-weight bold; src: url(a.ttf); font-style: italic"
+        .to_string(),
+    );
+    match &sheet.rules[..] {
+      [CssRule::FontFace(rule)] => {
+        assert_eq!(rule.family, "A");
+        assert_eq!(rule.weight, (400, 400));
+        assert_eq!(rule.style, FontStyle::Italic);
+      }
+      rules => panic!("{:?}", rules),
+    }
+    assert_eq!(sheet.diagnostics.len(), 1);
+  }
+
+  #[test]
+  fn reports_invalid_layer_rules() {
+    let sheet =
+      parse("@layer; @layer a, b { p { color: red } } @layer c; p { color: blue }".to_string());
+    assert_eq!(sheet.diagnostics.len(), 2);
+    assert!(matches!(
+      &sheet.rules[..],
+      [
+        CssRule::Layer(LayerRule { rules: None, .. }),
+        CssRule::Style(_)
+      ]
+    ));
+  }
+
+  #[test]
+  fn background_skips_unsupported_functions() {
+    let (declarations, diagnostics) = parse_declarations("background: url(x.png) no-repeat red");
+    assert_eq!(
+      declarations,
+      vec![("background-color".to_string(), color("red"))]
+    );
+    assert!(diagnostics.is_empty());
+  }
+
+  #[test]
+  fn drops_unsupported_functions_outside_background() {
+    assert_dropped("width: attr(data-width)");
+    assert_dropped("color: url(x.png)");
+  }
+
+  #[test]
+  fn drops_multiple_values_for_longhands() {
+    assert_dropped("box-shadow: 1px 1px red");
+    assert_dropped("width: 1px 2px");
+  }
+
+  #[test]
+  fn drops_invalid_shorthands() {
+    assert_dropped("margin: 1px 2px 3px 4px 5px");
+    assert_dropped("margin: 1px, 2px");
+    assert_dropped("border: 1px 2px solid");
+    assert_dropped("border-top: 1px solid red wavy");
+    assert_dropped("font: bold");
+    assert_dropped("font: 12px/");
+    assert_dropped("flex: 1 2 3px 4");
+
+    // 各部分的值不符合对应普通属性的语法
+    for css in [
+      "margin: red",
+      "margin: 1px inherit",
+      "padding: auto",
+      "inset: 5",
+      "border-width: 10%",
+      "border-style: solid red",
+      "border-color: 1px",
+      "border: 5 solid",
+      "border-top: 1px solid 2px",
+      "flex: red",
+      "flex: 1 red",
+      "flex: -1",
+      "flex: 1 1 5",
+      "font: bold foo Arial",
+      "font: 12px/red Arial",
+      "font: 0 12px Arial",
+    ] {
+      assert_dropped(css);
+    }
+  }
+
+  #[test]
+  fn accepts_valid_shorthand_components() {
+    for css in [
+      "margin: 0 auto -5% calc(1em + 2px)",
+      "padding: 0 1px",
+      "border-width: thin 0 2px",
+      "border-style: solid none",
+      "border-color: red currentcolor",
+      "border: 0",
+      "border: thick dashed",
+      "flex: 1 0 content",
+      "flex: 2 calc(10% + 5px)",
+      "font: italic 700 larger/1.2 serif",
+      "font: 80%/normal Arial",
+    ] {
+      let (declarations, diagnostics) = parse_declarations(css);
+      assert!(!declarations.is_empty(), "{}", css);
+      assert!(diagnostics.is_empty(), "{}", css);
+    }
+  }
+
+  #[test]
+  fn drops_unsupported_units() {
+    assert_dropped("transition: opacity 1s");
+    assert_dropped("grid-template-columns: 1fr 1fr");
+    assert_dropped("width: 3s");
+  }
+
+  #[test]
+  fn parses_lengths_and_percentages() {
+    let (declarations, _) = parse_declarations("width: 2.5em; height: 50%; line-height: 1.5");
+    let values: Vec<Value> = declarations.into_iter().map(|(_, value)| value).collect();
+    assert_eq!(
+      values,
+      vec![
+        Value::Length(2.5, Unit::Em),
+        Value::Percentage(50.0),
+        Value::Number(1.5)
+      ]
+    );
+  }
+
+  #[test]
+  fn drops_invalid_math_expressions() {
+    assert_dropped("width: calc(10px / 0)");
+    assert_dropped("width: calc(1px * 2px)");
+    assert_dropped("width: calc(1px + 2)");
+    assert_dropped("width: calc(1px +)");
+    assert_dropped("width: calc(1px 2px)");
+    assert_dropped("width: clamp(1px, 2px)");
+    assert_dropped("width: calc(1s)");
+    assert_dropped("width: calc(foo)");
+  }
+
+  #[test]
+  fn parses_math_expressions() {
+    let (declarations, diagnostics) = parse_declarations("width: calc(100% - 2 * (1em + 4px))");
+    assert!(diagnostics.is_empty());
+    let expected = CalcNode::Difference(
+      Box::new(CalcNode::Percentage(100.0)),
+      Box::new(CalcNode::Product(
+        Box::new(CalcNode::Number(2.0)),
+        Box::new(CalcNode::Sum(
+          Box::new(CalcNode::Length(1.0, Unit::Em)),
+          Box::new(CalcNode::Length(4.0, Unit::Px)),
+        )),
+      )),
+    );
+    assert_eq!(
+      declarations,
+      vec![("width".to_string(), Value::Calc(Box::new(expected)))]
+    );
+  }
+
+  #[test]
+  fn expands_valid_shorthands() {
+    let (declarations, _) = parse_declarations("margin: 1px 2px");
+    let values: Vec<Value> = declarations.into_iter().map(|(_, value)| value).collect();
+    let px = |f| Value::Length(f, Unit::Px);
+    assert_eq!(values, vec![px(1.0), px(2.0), px(1.0), px(2.0)]);
+  }
+
+  // 用内存中的文件作为 @import 的 loader
+  fn parse_files(files: &[(&str, &str)]) -> StyleSheet {
+    let files: std::collections::HashMap<String, String> = files
+      .iter()
+      .map(|(url, source)| (url.to_string(), source.to_string()))
+      .collect();
+    let (url, source) = files.get_key_value("css/main.css").unwrap();
+    parse_with_loader(source.clone(), url, &|url| files.get(url).cloned())
+  }
+
+  fn import(rule: &CssRule) -> &ImportRule {
+    match rule {
+      CssRule::Import(import) => import,
+      rule => panic!("Expected @import, got {:?}", rule),
+    }
+  }
+
+  #[test]
+  fn loads_imports_relative_to_the_importing_sheet() {
+    let sheet = parse_files(&[
+      (
+        "css/main.css",
+        "@import \"base.css\"; @import url(../theme/dark.css); @import 'missing.css';",
+      ),
+      (
+        "css/base.css",
+        "@import url('./reset.css'); p { color: red }",
+      ),
+      ("css/reset.css", "* { margin: 0 }"),
+      ("theme/dark.css", "p { color: white"),
+    ]);
+    assert!(sheet.diagnostics.is_empty(), "{:?}", sheet.diagnostics);
+    let [base, dark, missing] = &sheet.rules[..] else {
+      panic!("Expected three imports, got {:?}", sheet.rules);
+    };
+
+    let base = import(base);
+    assert_eq!(base.url, "base.css");
+    assert!(
+      matches!(&base.rules[..], [CssRule::Import(reset), CssRule::Style(_)] if reset.rules.len() == 1)
+    );
+    assert_eq!(import(dark).rules.len(), 1);
+    // 加载失败的样式表没有规则
+    assert!(import(missing).rules.is_empty());
+  }
+
+  #[test]
+  fn keeps_media_queries_of_imports() {
+    let sheet = parse_files(&[
+      (
+        "css/main.css",
+        "@import \"print.css\" print; @import url(\"wide.css\") screen and (min-width: 600px);",
+      ),
+      ("css/print.css", "p { color: black }"),
+      ("css/wide.css", "p { color: blue }"),
+    ]);
+    let viewport = |width| crate::layout::Rect {
+      width,
+      height: 600.0,
+      ..Default::default()
+    };
+    let narrow = MediaEnvironment::new(viewport(400.0));
+    let wide = MediaEnvironment::new(viewport(800.0));
+
+    let print = import(&sheet.rules[0]);
+    assert_eq!(print.queries.queries[0].media_type, MediaType::Print);
+    assert!(!print.queries.matches(&wide));
+    let screen = import(&sheet.rules[1]);
+    assert!(!screen.queries.matches(&narrow));
+    assert!(screen.queries.matches(&wide));
+    assert_eq!(screen.rules.len(), 1);
+  }
+
+  #[test]
+  fn ignores_cyclic_imports() {
+    let sheet = parse_files(&[
+      ("css/main.css", "@import \"a.css\"; p { color: red }"),
+      ("css/a.css", "@import \"b.css\"; @import \"a.css\";"),
+      (
+        "css/b.css",
+        "@import \"../css/main.css\"; @import \"a.css\"; em { color: blue }",
+      ),
+    ]);
+    let a = import(&sheet.rules[0]);
+    let b = import(&a.rules[0]);
+    // 正在加载的样式表不再重复加载
+    assert!(import(&a.rules[1]).rules.is_empty());
+    assert!(import(&b.rules[0]).rules.is_empty());
+    assert!(import(&b.rules[1]).rules.is_empty());
+    assert!(matches!(b.rules[2], CssRule::Style(_)));
+  }
+
+  #[test]
+  fn drops_malformed_imports() {
+    for css in [
+      "@import url(x.css) screen",
+      "@import \"x.css\"",
+      "@import url(x.css; p { color: red }",
+      "@import x.css; p { color: red }",
+      "@import \"x.css\" screen { p { color: red } } p { color: red }",
+      "@import \"x.css\" (min-width: 600px; p { color: red }",
+      "@import \"x.css",
+    ] {
+      let sheet = parse(css.to_string());
+      assert!(
+        sheet
+          .rules
+          .iter()
+          .all(|rule| matches!(rule, CssRule::Style(_))),
+        "{}: {:?}",
+        css,
+        sheet.rules
+      );
+      assert_eq!(sheet.diagnostics.len(), 1, "{}", css);
+    }
+  }
+
+  // 解析 @supports 规则并求值，规则被忽略时返回 None
+  fn supports(prelude: &str) -> Option<bool> {
+    let sheet = parse(format!("@supports {} {{ p {{ color: red }} }}", prelude));
+    match &sheet.rules[..] {
+      [CssRule::Supports(rule)] => {
+        assert_eq!(rule.rules.len(), 1, "{}", prelude);
+        Some(rule.condition.evaluate(&|feature| feature.is_supported()))
+      }
+      [] => {
+        assert_eq!(sheet.diagnostics.len(), 1, "{}", prelude);
+        None
+      }
+      rules => panic!("Unexpected rules for {}: {:?}", prelude, rules),
+    }
+  }
+
+  #[test]
+  fn parses_supports_conditions() {
+    let sheet = parse("@supports not ((display: flex) or (--x: 1)) {}".to_string());
+    let [CssRule::Supports(rule)] = &sheet.rules[..] else {
+      panic!("Expected @supports, got {:?}", sheet.rules);
+    };
+    let declaration = |name: &str, value: &str| {
+      Condition::Test(SupportsFeature::Declaration(
+        name.to_string(),
+        value.to_string(),
+      ))
+    };
+    assert_eq!(
+      rule.condition,
+      Condition::Not(Box::new(Condition::Or(vec![
+        declaration("display", "flex"),
+        declaration("--x", "1"),
+      ])))
+    );
+  }
+
+  #[test]
+  fn evaluates_supports_conditions() {
+    assert_eq!(supports("(display: block)"), Some(true));
+    assert_eq!(supports("(DISPLAY: Table)"), Some(true));
+    assert_eq!(supports("(display: flex)"), Some(false));
+    assert_eq!(supports("(colour: red)"), Some(false));
+    assert_eq!(supports("(--anything: 1)"), Some(true));
+    assert_eq!(supports("not (display: flex)"), Some(true));
+    assert_eq!(supports("(display: flex) or (display: block)"), Some(true));
+    assert_eq!(supports("(display: block) and (color: red)"), Some(true));
+    assert_eq!(
+      supports("(display: block) and (display: flex)"),
+      Some(false)
+    );
+    assert_eq!(
+      supports("((display: block) and (not (display: flex)))"),
+      Some(true)
+    );
+    // 无法识别的形式总是不满足
+    assert_eq!(supports("selector(a > b)"), Some(false));
+    assert_eq!(supports("(display)"), Some(false));
+  }
+
+  #[test]
+  fn drops_malformed_supports_rules() {
+    assert_eq!(supports("display: flex"), None);
+    assert_eq!(supports("(display: flex"), None);
+    assert_eq!(supports("((display: flex)"), None);
+    assert_eq!(supports("(display: flex) and"), None);
+    assert_eq!(supports("not"), None);
+    assert_eq!(supports(""), None);
+    // and 与 or 混用时需要括号
+    assert_eq!(
+      supports("(display: flex) and (color: red) or (width: 1px)"),
+      None
+    );
+
+    for css in [
+      "a { color: blue } @supports",
+      "a { color: blue } @supports (display: flex)",
+      "a { color: blue } @supports (display: flex) and (",
+      "@supports (display: flex); a { color: blue }",
+    ] {
+      let sheet = parse(css.to_string());
+      assert!(matches!(&sheet.rules[..], [CssRule::Style(_)]), "{}", css);
+      assert_eq!(sheet.diagnostics.len(), 1, "{}", css);
+    }
+  }
+
+  #[test]
+  fn drops_malformed_container_rules() {
+    for css in [
+      "@container { p { color: red } } a { color: blue }",
+      "@container sidebar { p { color: red } } a { color: blue }",
+      "@container (min-width: 400px { p { color: red } } a { color: blue }",
+      "@container (min-width: 400px) and { p { color: red } } a { color: blue }",
+      "@container not { p { color: red } } a { color: blue }",
+      "a { color: blue } @container sidebar (width",
+    ] {
+      let sheet = parse(css.to_string());
+      assert!(
+        matches!(&sheet.rules[..], [CssRule::Style(_)]),
+        "{}: {:?}",
+        css,
+        sheet.rules
+      );
+      assert_eq!(sheet.diagnostics.len(), 1, "{}", css);
+    }
+
+    // 缺少规则块时，前导部分延续到下一个规则块
+    let sheet = parse("@container sidebar (width > 400px) a { color: blue }".to_string());
+    assert!(sheet.rules.is_empty());
+    assert_eq!(sheet.diagnostics.len(), 1);
+
+    // 没有闭合的规则块到结尾为止
+    let sheet = parse("@container sidebar (width > 400px) { p { color: red }".to_string());
+    let [CssRule::Container(rule)] = &sheet.rules[..] else {
+      panic!("Expected @container, got {:?}", sheet.rules);
+    };
+    assert_eq!(rule.name.as_deref(), Some("sidebar"));
+    assert_eq!(rule.rules.len(), 1);
   }
 }
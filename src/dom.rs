@@ -1,10 +1,44 @@
+use crate::style_sharing::SharedStyle;
 use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicUsize, Ordering};
 pub type AttrMap = HashMap<String, String>;
 
 #[derive(Debug)]
 pub struct Node {
   pub node_type: NodeType,
   pub children: Vec<Node>,
+  // 增量计算样式时使用，由 style 模块维护
+  pub style_data: StyleData,
+  node_id: usize,
+}
+
+// 节点创建时分配的编号，进程内唯一
+static NEXT_NODE_ID: AtomicUsize = AtomicUsize::new(0);
+
+fn next_node_id() -> usize {
+  NEXT_NODE_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+// 需要重新计算样式的范围，按影响范围从小到大排列
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum Damage {
+  #[default]
+  None,
+  // 重新匹配自身，子节点只在继承的值变化时重新计算
+  Element,
+  // 重新匹配自身和所有子孙节点
+  Subtree,
+}
+
+// 节点上一次计算的样式，以及之后发生的、可能影响选择器匹配的变化
+#[derive(Debug, Clone, Default)]
+pub struct StyleData {
+  // 从未计算过样式的节点为 None
+  pub style: Option<SharedStyle>,
+  pub damage: Damage,
+  // 增加或删除的 id、class，重新计算时根据样式表中用到的 id、class 确定 damage
+  pub changed_ids: Vec<String>,
+  pub changed_classes: Vec<String>,
 }
 
 #[derive(Debug)]
@@ -23,6 +57,8 @@ pub fn text(data: String) -> Node {
   Node {
     node_type: NodeType::Text(data),
     children: vec![],
+    style_data: StyleData::default(),
+    node_id: next_node_id(),
   }
 }
 
@@ -32,19 +68,85 @@ pub fn elem(name: String, attrs: AttrMap, children: Vec<Node>) -> Node {
       tag_name: name,
       attributes: attrs,
     }),
-    children: children,
+    children,
+    style_data: StyleData::default(),
+    node_id: next_node_id(),
+  }
+}
+
+impl Node {
+  // 节点的标识，插入、删除兄弟节点后保持不变，可以在两次样式计算、布局之间关联同一节点
+  pub fn node_id(&self) -> usize {
+    self.node_id
+  }
+
+  // 设置属性，记录变化的 id、class，文本节点没有属性
+  pub fn set_attribute(&mut self, name: &str, value: &str) {
+    self.change_attribute(name, Some(value));
+  }
+
+  pub fn remove_attribute(&mut self, name: &str) {
+    self.change_attribute(name, None);
+  }
+
+  fn change_attribute(&mut self, name: &str, value: Option<&str>) {
+    let elem = match &mut self.node_type {
+      NodeType::Element(elem) => elem,
+      NodeType::Text(_) => panic!("Cannot set attribute {} on a text node", name),
+    };
+    let old = match value {
+      Some(value) => elem.attributes.insert(name.to_string(), value.to_string()),
+      None => elem.attributes.remove(name),
+    };
+
+    // 选择器只用到 id 和 class，其他属性不影响匹配
+    let data = &mut self.style_data;
+    match name {
+      "id" => {
+        data.changed_ids.extend(old);
+        data.changed_ids.extend(value.map(String::from));
+      }
+      "class" => {
+        let old: HashSet<&str> = old.as_deref().map_or(HashSet::new(), split_classes);
+        let new: HashSet<&str> = value.map_or(HashSet::new(), split_classes);
+        data.changed_classes.extend(
+          old
+            .symmetric_difference(&new)
+            .map(|class| class.to_string()),
+        );
+      }
+      _ => {}
+    }
+  }
+
+  // 插入子节点，新节点没有样式，下次计算时会计算它的样式
+  pub fn insert_child(&mut self, index: usize, child: Node) {
+    self.children.insert(index, child);
+  }
+
+  pub fn append_child(&mut self, child: Node) {
+    self.children.push(child);
+  }
+
+  // 选择器不依赖兄弟节点，删除子节点不影响其他节点的样式
+  pub fn remove_child(&mut self, index: usize) -> Node {
+    self.children.remove(index)
   }
 }
 
 impl ElementData {
   pub fn id(&self) -> Option<&String> {
-    return self.attributes.get("id");
+    self.attributes.get("id")
   }
 
   pub fn classes(&self) -> HashSet<&str> {
     match self.attributes.get("class") {
-      Some(classlist) => classlist.split(' ').collect(),
+      Some(classlist) => split_classes(classlist),
       None => HashSet::new(),
     }
   }
 }
+
+fn split_classes(classlist: &str) -> HashSet<&str> {
+  classlist.split(' ').collect()
+}
@@ -0,0 +1,1263 @@
+use crate::computed::{ComputedStyle, FontStyle, FontUnits};
+use crate::css::{self, CssRule, FontFaceRule, FontSource, StyleSheet};
+use crate::woff2;
+use ab_glyph_rasterizer::{point, Point, Rasterizer};
+use rustybuzz::ttf_parser::{self, GlyphId, OutlineBuilder};
+use rustybuzz::{Face, UnicodeBuffer};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock, RwLock};
+
+// 随程序附带的默认字体，没有其他可用字体时使用
+const DEFAULT_FONT: &[u8] = include_bytes!("../fonts/DejaVuSans.ttf");
+
+// 已加载的字体在 FontContext 中的序号
+pub type FontId = usize;
+
+// 一个字体文件中的字体，只保存匹配和排版需要的信息，字形数据在整形、光栅化时才加载
+pub struct Font {
+  // @font-face 引用本地字体时与原字体共用
+  face: Arc<LazyFace>,
+  pub family: String,
+  // 字体中记录的所有字族名，包括其他语言的名字，匹配时都可以使用
+  family_names: Vec<String>,
+  // 完整名称和 PostScript 名称，用于 @font-face 中的 local()
+  local_names: Vec<String>,
+  // 粗细、宽度支持的范围，普通字体两端相同，@font-face 可以声明一个范围
+  pub weight: (u16, u16),
+  pub style: FontStyle,
+  // 相对正常宽度的百分比
+  pub stretch: (f32, f32),
+  // 由 @font-face 声明的字体，字族名相同时优先于已安装的字体
+  pub declared: bool,
+  // 字体支持的字符，按码位排序的闭区间
+  coverage: Vec<(u32, u32)>,
+  units_per_em: f32,
+  ascender: f32,
+  descender: f32,
+  line_gap: f32,
+}
+
+// 字体数据的来源，文件中的字体建立索引后不保留数据，使用时再读取
+#[derive(Debug, Clone)]
+enum FontData {
+  Static(&'static [u8]),
+  Memory(Arc<Vec<u8>>),
+  File(Arc<FontFile>),
+}
+
+// 字体文件，字体集合中的各个字体共用读取的数据
+#[derive(Debug)]
+struct FontFile {
+  path: PathBuf,
+  data: OnceLock<Option<Arc<Vec<u8>>>>,
+}
+
+impl FontFile {
+  fn read(&self) -> Option<Arc<Vec<u8>>> {
+    self
+      .data
+      .get_or_init(|| std::fs::read(&self.path).ok().map(Arc::new))
+      .clone()
+  }
+}
+
+// 字体数据及解析后的字体，第一次使用时加载并缓存，index 为字体集合（ttc）中的序号
+struct LazyFace {
+  data: FontData,
+  index: u32,
+  parsed: OnceLock<Option<ParsedFace>>,
+}
+
+impl LazyFace {
+  fn new(data: FontData, index: u32) -> LazyFace {
+    LazyFace {
+      data,
+      index,
+      parsed: OnceLock::new(),
+    }
+  }
+
+  // 建立索引后字体文件无法读取时返回 None
+  fn get(&self) -> Option<&Face<'_>> {
+    let parsed = self.parsed.get_or_init(|| {
+      let data = match &self.data {
+        FontData::Static(bytes) => {
+          let face = Face::from_slice(bytes, self.index)?;
+          return Some(ParsedFace { face, _data: None });
+        }
+        FontData::Memory(data) => data.clone(),
+        FontData::File(file) => file.read()?,
+      };
+      ParsedFace::parse(data, self.index)
+    });
+    parsed.as_ref().map(|parsed| &parsed.face)
+  }
+}
+
+// 解析一次后缓存的字体，整形和光栅化时不再重新解析
+// face 引用 _data 的内容，字段按声明顺序释放，face 先于 _data 释放
+struct ParsedFace {
+  face: Face<'static>,
+  _data: Option<Arc<Vec<u8>>>,
+}
+
+impl ParsedFace {
+  fn parse(data: Arc<Vec<u8>>, index: u32) -> Option<ParsedFace> {
+    // SAFETY: 切片指向 Arc 中 Vec 的堆内存，Vec 不会再被修改，也不会随 ParsedFace 移动
+    // 该内存由 _data 持有，直到 face 释放之后才可能释放，face 也不会被复制到 ParsedFace 之外
+    let bytes: &'static [u8] = unsafe { std::slice::from_raw_parts(data.as_ptr(), data.len()) };
+    let face = Face::from_slice(bytes, index)?;
+    Some(ParsedFace {
+      face,
+      _data: Some(data),
+    })
+  }
+}
+// 字体本身没有所需的粗细、倾斜时，绘制时加粗、倾斜字形来模拟
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Synthesis {
+  pub bold: bool,
+  pub oblique: bool,
+}
+
+// 模拟倾斜时的水平偏移与高度之比，约 11 度
+const OBLIQUE_SKEW: f32 = 0.2;
+
+impl Synthesis {
+  // 模拟粗体时字形向右加粗的宽度，字形的步进宽度也相应增加
+  pub fn embolden(&self, font_size: f32) -> f32 {
+    if self.bold {
+      font_size / 24.0
+    } else {
+      0.0
+    }
+  }
+}
+
+// 按字号换算后的纵向度量，descent 为基线以下的距离，取正值
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FontMetrics {
+  pub ascent: f32,
+  pub descent: f32,
+  pub line_gap: f32,
+}
+
+// 整形后的单个字形，长度单位为 px，cluster 为对应文字在文本中的字节偏移
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ShapedGlyph {
+  pub id: u16,
+  pub cluster: usize,
+  pub x_advance: f32,
+  pub x_offset: f32,
+  pub y_offset: f32,
+}
+
+// 一段文字整形的结果，字形按视觉顺序排列
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShapedRun {
+  pub glyphs: Vec<ShapedGlyph>,
+  pub width: f32,
+}
+
+// 字形的覆盖率位图，left、top 为位图左上角在画布中的像素坐标
+#[derive(Debug, Clone)]
+pub struct GlyphBitmap {
+  pub left: i32,
+  pub top: i32,
+  pub width: usize,
+  pub height: usize,
+  // 每个像素被字形覆盖的比例，0 到 1
+  pub coverage: Vec<f32>,
+}
+
+impl Font {
+  // 解析内存中的字体数据，不是有效的 TrueType/OpenType 字体时返回 None
+  pub fn from_data(data: Arc<Vec<u8>>, index: u32) -> Option<Font> {
+    Font::read(&data.clone(), FontData::Memory(data), index)
+  }
+
+  // 读取字体的名称、粗细、倾斜、宽度、支持的字符和纵向度量，bytes 为 data 的内容
+  fn read(bytes: &[u8], data: FontData, index: u32) -> Option<Font> {
+    let face = &ttf_parser::Face::parse(bytes, index).ok()?;
+    // 优先使用不区分粗细、宽度的字族名，如 Noto Sans CJK SC Bold 的字族为 Noto Sans CJK SC
+    let names = |id: u16| -> Vec<String> {
+      face
+        .names()
+        .into_iter()
+        .filter(|name| name.name_id == id)
+        .filter_map(|name| name.to_string())
+        .collect()
+    };
+    let mut family_names = names(ttf_parser::name_id::TYPOGRAPHIC_FAMILY);
+    family_names.extend(names(ttf_parser::name_id::FAMILY));
+    family_names.dedup();
+    let family = family_names.first().cloned().unwrap_or_default();
+    let mut local_names = names(ttf_parser::name_id::FULL_NAME);
+    local_names.extend(names(ttf_parser::name_id::POST_SCRIPT_NAME));
+
+    let weight = face.weight().to_number();
+    let style = match face.style() {
+      ttf_parser::Style::Normal => FontStyle::Normal,
+      ttf_parser::Style::Italic => FontStyle::Italic,
+      ttf_parser::Style::Oblique => FontStyle::Oblique,
+    };
+    let stretch = match face.width().to_number() {
+      1 => 50.0,
+      2 => 62.5,
+      3 => 75.0,
+      4 => 87.5,
+      6 => 112.5,
+      7 => 125.0,
+      8 => 150.0,
+      9 => 200.0,
+      _ => 100.0,
+    };
+    let coverage = coverage(face);
+    let units_per_em = face.units_per_em() as f32;
+    let ascender = face.ascender() as f32;
+    let descender = face.descender() as f32;
+    let line_gap = face.line_gap() as f32;
+
+    Some(Font {
+      face: Arc::new(LazyFace::new(data, index)),
+      family,
+      family_names,
+      local_names,
+      weight: (weight, weight),
+      style,
+      stretch: (stretch, stretch),
+      declared: false,
+      coverage,
+      units_per_em,
+      ascender,
+      descender,
+      line_gap,
+    })
+  }
+
+  fn face(&self) -> Option<&Face<'_>> {
+    self.face.get()
+  }
+
+  pub fn metrics(&self, font_size: f32) -> FontMetrics {
+    let scale = font_size / self.units_per_em;
+    FontMetrics {
+      ascent: self.ascender * scale,
+      descent: -self.descender * scale,
+      line_gap: self.line_gap * scale,
+    }
+  }
+
+  // x 的高度，单位为 em，OS/2 表中没有时取 x 字形的高度，都没有时返回 None
+  pub fn x_height(&self) -> Option<f32> {
+    let face = self.face()?;
+    let height = match face.x_height() {
+      Some(height) if height > 0 => height,
+      _ => face.glyph_bounding_box(face.glyph_index('x')?)?.y_max,
+    };
+    (height > 0).then(|| height as f32 / self.units_per_em)
+  }
+
+  // 字符的步进宽度，单位为 em，字体中没有该字符时返回 None
+  pub fn advance(&self, c: char) -> Option<f32> {
+    let face = self.face()?;
+    let advance = face.glyph_hor_advance(face.glyph_index(c)?)?;
+    Some(advance as f32 / self.units_per_em)
+  }
+
+  // 使用该字体显示指定粗细、倾斜的文字时需要的模拟
+  pub fn synthesis(&self, weight: u16, style: FontStyle) -> Synthesis {
+    Synthesis {
+      bold: weight >= 600 && self.weight.1 < 600,
+      oblique: style != FontStyle::Normal && self.style == FontStyle::Normal,
+    }
+  }
+
+  // 字体中是否有该字符的字形
+  pub fn has_glyph(&self, c: char) -> bool {
+    let c = c as u32;
+    match self.coverage.binary_search_by(|&(start, _)| start.cmp(&c)) {
+      Ok(_) => true,
+      Err(0) => false,
+      Err(i) => c <= self.coverage[i - 1].1,
+    }
+  }
+
+  // 字族名是否相同，不区分大小写
+  pub fn has_family(&self, family: &str) -> bool {
+    self
+      .family_names
+      .iter()
+      .any(|name| name.eq_ignore_ascii_case(family))
+  }
+
+  fn has_local_name(&self, name: &str) -> bool {
+    self
+      .local_names
+      .iter()
+      .any(|local| local.eq_ignore_ascii_case(name))
+  }
+
+  // 按 @font-face 的描述符改写字族、粗细、倾斜和宽度，并只保留 unicode-range 中的字符
+  fn with_face(&self, rule: &FontFaceRule) -> Font {
+    let coverage = if rule.unicode_range.is_empty() {
+      self.coverage.clone()
+    } else {
+      intersect_ranges(&self.coverage, &rule.unicode_range)
+    };
+    Font {
+      face: self.face.clone(),
+      family: rule.family.clone(),
+      family_names: vec![rule.family.clone()],
+      local_names: self.local_names.clone(),
+      weight: rule.weight,
+      style: rule.style,
+      stretch: rule.stretch,
+      declared: true,
+      coverage,
+      units_per_em: self.units_per_em,
+      ascender: self.ascender,
+      descender: self.descender,
+      line_gap: self.line_gap,
+    }
+  }
+
+  // 整形：根据文字推断书写方向和文种，应用字距调整、连字和复杂文种的字形替换
+  // 字体数据无法加载时没有字形
+  pub fn shape(&self, text: &str, font_size: f32, synthesis: Synthesis) -> ShapedRun {
+    let Some(face) = self.face() else {
+      return ShapedRun {
+        glyphs: Vec::new(),
+        width: 0.0,
+      };
+    };
+    let mut buffer = UnicodeBuffer::new();
+    buffer.push_str(text);
+    buffer.guess_segment_properties();
+    let output = rustybuzz::shape(face, &[], buffer);
+
+    let scale = font_size / self.units_per_em;
+    let embolden = synthesis.embolden(font_size);
+    let glyphs: Vec<ShapedGlyph> = output
+      .glyph_infos()
+      .iter()
+      .zip(output.glyph_positions())
+      .map(|(info, position)| ShapedGlyph {
+        id: info.glyph_id as u16,
+        cluster: info.cluster as usize,
+        x_advance: position.x_advance as f32 * scale + embolden,
+        x_offset: position.x_offset as f32 * scale,
+        y_offset: position.y_offset as f32 * scale,
+      })
+      .collect();
+    let width = glyphs.iter().map(|glyph| glyph.x_advance).sum();
+
+    ShapedRun { glyphs, width }
+  }
+
+  // 光栅化字形，(x, y) 为字形原点（基线上）在画布中的位置，空白字形返回 None
+  pub fn rasterize(
+    &self,
+    glyph: u16,
+    font_size: f32,
+    x: f32,
+    y: f32,
+    synthesis: Synthesis,
+  ) -> Option<GlyphBitmap> {
+    let face = self.face()?;
+    let glyph = GlyphId(glyph);
+    let bbox = face.glyph_bounding_box(glyph)?;
+    let scale = font_size / self.units_per_em;
+    let skew = if synthesis.oblique { OBLIQUE_SKEW } else { 0.0 };
+    let embolden = synthesis.embolden(font_size);
+
+    // 倾斜后基线以上的部分右移，基线以下的部分左移
+    let x_min = (bbox.x_min as f32 + bbox.y_min.min(0) as f32 * skew) * scale;
+    let x_max = (bbox.x_max as f32 + bbox.y_max.max(0) as f32 * skew) * scale + embolden;
+    let left = (x + x_min).floor() as i32;
+    let right = (x + x_max).ceil() as i32;
+    let top = (y - bbox.y_max as f32 * scale).floor() as i32;
+    let bottom = (y - bbox.y_min as f32 * scale).ceil() as i32;
+    let width = (right - left).max(0) as usize;
+    let height = (bottom - top).max(0) as usize;
+    if width == 0 || height == 0 {
+      return None;
+    }
+
+    // 字体坐标 y 轴向上，转换为位图内 y 轴向下的坐标
+    // 模拟粗体时在右侧再画一遍轮廓，重叠部分的覆盖率不超过 1
+    let mut rasterizer = Rasterizer::new(width, height);
+    let offsets: &[f32] = if synthesis.bold {
+      &[0.0, embolden]
+    } else {
+      &[0.0]
+    };
+    for &offset in offsets {
+      let mut builder = OutlineRasterizer {
+        rasterizer,
+        transform: move |fx: f32, fy: f32| {
+          point(
+            x + offset + (fx + fy * skew) * scale - left as f32,
+            y - fy * scale - top as f32,
+          )
+        },
+        start: point(0.0, 0.0),
+        last: point(0.0, 0.0),
+      };
+      face.outline_glyph(glyph, &mut builder)?;
+      rasterizer = builder.rasterizer;
+    }
+
+    let mut coverage = vec![0.0; width * height];
+    rasterizer.for_each_pixel(|index, alpha| coverage[index] = alpha.min(1.0));
+
+    Some(GlyphBitmap {
+      left,
+      top,
+      width,
+      height,
+      coverage,
+    })
+  }
+}
+
+// 读取 cmap 中的 unicode 码位，合并为连续的区间
+fn coverage(face: &ttf_parser::Face) -> Vec<(u32, u32)> {
+  let mut codepoints = Vec::new();
+  if let Some(cmap) = face.tables().cmap {
+    for subtable in cmap.subtables {
+      if subtable.is_unicode() {
+        subtable.codepoints(|c| codepoints.push(c));
+      }
+    }
+  }
+  codepoints.sort_unstable();
+  codepoints.dedup();
+
+  let mut ranges: Vec<(u32, u32)> = Vec::new();
+  for c in codepoints {
+    match ranges.last_mut() {
+      Some((_, end)) if *end + 1 == c => *end = c,
+      _ => ranges.push((c, c)),
+    }
+  }
+  ranges
+}
+
+// 将字形轮廓转换为位图坐标后交给光栅化器
+struct OutlineRasterizer<F: Fn(f32, f32) -> Point> {
+  rasterizer: Rasterizer,
+  transform: F,
+  start: Point,
+  last: Point,
+}
+
+impl<F: Fn(f32, f32) -> Point> OutlineBuilder for OutlineRasterizer<F> {
+  fn move_to(&mut self, x: f32, y: f32) {
+    self.start = (self.transform)(x, y);
+    self.last = self.start;
+  }
+
+  fn line_to(&mut self, x: f32, y: f32) {
+    let p = (self.transform)(x, y);
+    self.rasterizer.draw_line(self.last, p);
+    self.last = p;
+  }
+
+  fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+    let p1 = (self.transform)(x1, y1);
+    let p = (self.transform)(x, y);
+    self.rasterizer.draw_quad(self.last, p1, p);
+    self.last = p;
+  }
+
+  fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+    let p1 = (self.transform)(x1, y1);
+    let p2 = (self.transform)(x2, y2);
+    let p = (self.transform)(x, y);
+    self.rasterizer.draw_cubic(self.last, p1, p2, p);
+    self.last = p;
+  }
+
+  fn close(&mut self) {
+    if self.last != self.start {
+      self.rasterizer.draw_line(self.last, self.start);
+    }
+    self.last = self.start;
+  }
+}
+
+// 通用字族默认对应的字族，按顺序使用第一个已加载的
+const GENERIC_FAMILIES: &[(&str, &[&str])] = &[
+  (
+    "serif",
+    &[
+      "DejaVu Serif",
+      "Noto Serif",
+      "Liberation Serif",
+      "Times New Roman",
+      "Times",
+      "Noto Serif CJK SC",
+      "Songti SC",
+      "SimSun",
+    ],
+  ),
+  (
+    "sans-serif",
+    &[
+      "DejaVu Sans",
+      "Noto Sans",
+      "Liberation Sans",
+      "Arial",
+      "Helvetica",
+      "Noto Sans CJK SC",
+      "PingFang SC",
+      "Microsoft YaHei",
+    ],
+  ),
+  (
+    "monospace",
+    &[
+      "DejaVu Sans Mono",
+      "Noto Sans Mono",
+      "Liberation Mono",
+      "Courier New",
+      "Menlo",
+      "Consolas",
+    ],
+  ),
+  ("cursive", &["Comic Sans MS", "Apple Chancery"]),
+  ("fantasy", &["Impact", "Papyrus"]),
+  (
+    "system-ui",
+    &["DejaVu Sans", "Noto Sans", "San Francisco", "Segoe UI"],
+  ),
+];
+
+// 扫描字体的系统目录，不存在的目录会被跳过
+const SYSTEM_FONT_DIRECTORIES: &[&str] = &[
+  "/usr/share/fonts",
+  "/usr/local/share/fonts",
+  "/System/Library/Fonts",
+  "/Library/Fonts",
+  "C:\\Windows\\Fonts",
+];
+
+// 两组按码位排序的闭区间的交集，结果合并相邻的区间
+fn intersect_ranges(coverage: &[(u32, u32)], ranges: &[(u32, u32)]) -> Vec<(u32, u32)> {
+  let mut result: Vec<(u32, u32)> = Vec::new();
+  for &(start, end) in coverage {
+    for &(range_start, range_end) in ranges {
+      if start.max(range_start) <= end.min(range_end) {
+        result.push((start.max(range_start), end.min(range_end)));
+      }
+    }
+  }
+  result.sort();
+
+  let mut merged: Vec<(u32, u32)> = Vec::new();
+  for (start, end) in result {
+    match merged.last_mut() {
+      Some(last) if start <= last.1.saturating_add(1) => last.1 = last.1.max(end),
+      _ => merged.push((start, end)),
+    }
+  }
+  merged
+}
+
+// @font-face 的 src 中可以加载的格式，没有 format() 时根据数据判断
+const SUPPORTED_FORMATS: &[&str] = &["truetype", "opentype", "woff2", "collection"];
+
+// 排版和绘制共用的字体集合，第 0 个字体总是附带的默认字体
+// 可以从目录中加载字体，按 CSS 规则匹配字族、粗细、倾斜和宽度，并为字体中没有的字符选择后备字体
+pub struct FontContext {
+  fonts: Vec<Font>,
+  generic_families: HashMap<String, Vec<String>>,
+  // font-family 列表匹配到的字体，先按粗细、倾斜、宽度，再按字族列表查找，查找时不需要复制字族列表
+  // 加载新字体后清空
+  matches: RwLock<HashMap<FontQuery, FamilyMatches>>,
+}
+
+// 字族列表匹配到的字体
+type FamilyMatches = HashMap<Vec<String>, Arc<[FontId]>>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct FontQuery {
+  weight: u16,
+  style: FontStyle,
+  // 百分比乘以 10，便于作为键
+  stretch: u32,
+}
+
+impl FontContext {
+  pub fn new() -> FontContext {
+    let default_font = Font::read(DEFAULT_FONT, FontData::Static(DEFAULT_FONT), 0)
+      .expect("Bundled font should be valid");
+    let generic_families = GENERIC_FAMILIES
+      .iter()
+      .map(|(generic, families)| {
+        let families = families.iter().map(|family| family.to_string()).collect();
+        (generic.to_string(), families)
+      })
+      .collect();
+    FontContext {
+      fonts: vec![default_font],
+      generic_families,
+      matches: RwLock::new(HashMap::new()),
+    }
+  }
+
+  // 为本地的 TTF/OTF 文件建立索引，字体集合中的每个字体都会加入，返回第一个字体的序号
+  // 索引只保存匹配和排版需要的信息，字形数据在字体第一次被使用时再读取
+  pub fn load_file(&mut self, path: &str) -> Option<FontId> {
+    let bytes = std::fs::read(path).ok()?;
+    let file = FontFile {
+      path: PathBuf::from(path),
+      data: OnceLock::new(),
+    };
+    self.add_fonts(&bytes, FontData::File(Arc::new(file)))
+  }
+
+  // 加载内存中的字体数据
+  pub fn load_data(&mut self, data: Vec<u8>) -> Option<FontId> {
+    let data = Arc::new(data);
+    self.add_fonts(&data.clone(), FontData::Memory(data))
+  }
+
+  fn add_fonts(&mut self, bytes: &[u8], data: FontData) -> Option<FontId> {
+    let count = ttf_parser::fonts_in_collection(bytes).unwrap_or(1);
+    let first = self.fonts.len();
+    for index in 0..count {
+      if let Some(font) = Font::read(bytes, data.clone(), index) {
+        self.fonts.push(font);
+      }
+    }
+    self.matches.get_mut().unwrap().clear();
+    if self.fonts.len() > first {
+      Some(first)
+    } else {
+      None
+    }
+  }
+
+  // 加载样式表（包括 @import 的样式表）中 @font-face 声明的字体，src 相对于声明所在的样式表
+  // 依次尝试 src 中的各项，使用第一个可以加载的，返回注册的字体数量
+  pub fn load_font_faces(
+    &mut self,
+    stylesheet: &StyleSheet,
+    url: &str,
+    loader: &dyn Fn(&str) -> Option<Vec<u8>>,
+  ) -> usize {
+    let before = self.fonts.len();
+    self.load_font_face_rules(&stylesheet.rules, url, loader);
+    self.matches.get_mut().unwrap().clear();
+    self.fonts.len() - before
+  }
+
+  // @media 等条件规则中的 @font-face 不考虑条件，都会加载
+  fn load_font_face_rules(
+    &mut self,
+    rules: &[CssRule],
+    url: &str,
+    loader: &dyn Fn(&str) -> Option<Vec<u8>>,
+  ) {
+    for rule in rules {
+      match rule {
+        CssRule::FontFace(rule) => {
+          if let Some(font) = self.load_font_face(rule, url, loader) {
+            self.fonts.push(font);
+          }
+        }
+        CssRule::Import(import) => {
+          let url = css::resolve_url(url, &import.url);
+          self.load_font_face_rules(&import.rules, &url, loader);
+        }
+        CssRule::Media(media) => self.load_font_face_rules(&media.rules, url, loader),
+        CssRule::Supports(supports) => self.load_font_face_rules(&supports.rules, url, loader),
+        CssRule::Container(container) => self.load_font_face_rules(&container.rules, url, loader),
+        CssRule::Layer(layer) => {
+          if let Some(rules) = &layer.rules {
+            self.load_font_face_rules(rules, url, loader);
+          }
+        }
+        CssRule::Style(_) => {}
+      }
+    }
+  }
+
+  fn load_font_face(
+    &self,
+    rule: &FontFaceRule,
+    url: &str,
+    loader: &dyn Fn(&str) -> Option<Vec<u8>>,
+  ) -> Option<Font> {
+    for source in &rule.sources {
+      let font = match source {
+        FontSource::Local(name) => self
+          .fonts
+          .iter()
+          .find(|font| !font.declared && font.has_local_name(name))
+          .map(|font| font.with_face(rule)),
+        FontSource::Url(_, Some(format)) if !SUPPORTED_FORMATS.contains(&format.as_str()) => None,
+        FontSource::Url(source, _) => loader(&css::resolve_url(url, source))
+          .and_then(|data| match woff2::is_woff2(&data) {
+            true => woff2::decode(&data),
+            false => Some(data),
+          })
+          .and_then(|data| Font::from_data(Arc::new(data), 0))
+          .map(|font| font.with_face(rule)),
+      };
+      if font.is_some() {
+        return font;
+      }
+    }
+    None
+  }
+
+  // 递归加载目录中的 ttf、otf、ttc 文件，按路径排序，返回加载的字体数量
+  pub fn load_directory(&mut self, path: &str) -> usize {
+    let mut files = Vec::new();
+    collect_font_files(Path::new(path), &mut files);
+    files.sort();
+
+    let before = self.fonts.len();
+    for file in files {
+      if let Some(file) = file.to_str() {
+        self.load_file(file);
+      }
+    }
+    self.fonts.len() - before
+  }
+
+  // 加载系统字体目录和用户字体目录
+  pub fn load_system_fonts(&mut self) -> usize {
+    let mut directories: Vec<String> = SYSTEM_FONT_DIRECTORIES
+      .iter()
+      .map(|directory| directory.to_string())
+      .collect();
+    if let Ok(home) = std::env::var("HOME") {
+      directories.push(format!("{}/.fonts", home));
+      directories.push(format!("{}/.local/share/fonts", home));
+      directories.push(format!("{}/Library/Fonts", home));
+    }
+    directories
+      .iter()
+      .map(|directory| self.load_directory(directory))
+      .sum()
+  }
+
+  // 设置通用字族对应的字族列表，如 sans-serif
+  pub fn set_generic_family(&mut self, generic: &str, families: Vec<String>) {
+    self
+      .generic_families
+      .insert(generic.to_ascii_lowercase(), families);
+    self.matches.get_mut().unwrap().clear();
+  }
+
+  pub fn default_font(&self) -> FontId {
+    0
+  }
+
+  pub fn font(&self, id: FontId) -> &Font {
+    &self.fonts[id]
+  }
+
+  // 在字族的所有字体中按 CSS 字体匹配规则选择最接近的，依次比较宽度、倾斜和粗细
+  pub fn match_family(
+    &self,
+    family: &str,
+    weight: u16,
+    style: FontStyle,
+    stretch: f32,
+  ) -> Option<FontId> {
+    let mut candidates: Vec<FontId> = (0..self.fonts.len())
+      .filter(|&id| self.fonts[id].has_family(family))
+      .collect();
+    if candidates.iter().any(|&id| self.fonts[id].declared) {
+      candidates.retain(|&id| self.fonts[id].declared);
+    }
+    self.closest(candidates, weight, style, stretch)
+  }
+
+  // @font-face 声明的同一字族中描述符相同的字体按 unicode-range 组合为一个字体，后声明的优先
+  fn composite(&self, id: FontId) -> Vec<FontId> {
+    let font = &self.fonts[id];
+    if !font.declared {
+      return vec![id];
+    }
+    (0..self.fonts.len())
+      .rev()
+      .filter(|&other| {
+        let other = &self.fonts[other];
+        other.declared
+          && other.family == font.family
+          && other.weight == font.weight
+          && other.style == font.style
+          && other.stretch == font.stretch
+      })
+      .collect()
+  }
+
+  // 为文字中的字符选择字体：依次使用 font-family 中第一个含有该字符的字体
+  // 都没有时在所有已加载的字体中查找，仍然没有时使用第一个字族的字体显示缺字符号
+  pub fn select(&self, style: &ComputedStyle, c: char) -> FontId {
+    self.select_from(&self.match_families(style), style, c)
+  }
+
+  // 与 select 相同，fonts 为 match_families 的结果，一段文字只需匹配一次字族
+  pub fn select_from(&self, fonts: &[FontId], style: &ComputedStyle, c: char) -> FontId {
+    if let Some(&id) = fonts.iter().find(|&&id| self.fonts[id].has_glyph(c)) {
+      return id;
+    }
+
+    let fallback = self.fonts.iter().find(|font| font.has_glyph(c));
+    if let Some(family) = fallback.map(|font| font.family.as_str()) {
+      let candidates = (0..self.fonts.len())
+        .filter(|&id| self.fonts[id].has_family(family) && self.fonts[id].has_glyph(c))
+        .collect();
+      if let Some(id) = self.closest(
+        candidates,
+        style.font_weight,
+        style.font_style,
+        style.font_stretch,
+      ) {
+        return id;
+      }
+    }
+
+    fonts
+      .first()
+      .cloned()
+      .unwrap_or_else(|| self.default_font())
+  }
+
+  // 解析 ex、ch 所用的度量，分别取第一个含有 x、0 的字体，字体中没有对应数据时取近似值
+  pub fn font_units(&self, style: &ComputedStyle) -> FontUnits {
+    let fonts = self.match_families(style);
+    let font = |c| self.font(self.select_from(&fonts, style, c));
+    let default = FontUnits::default();
+    FontUnits {
+      ex: font('x').x_height().unwrap_or(default.ex),
+      ch: font('0').advance('0').unwrap_or(default.ch),
+    }
+  }
+
+  // font-family 中各字族匹配到的字体，通用字族展开为对应的字族
+  pub fn match_families(&self, style: &ComputedStyle) -> Arc<[FontId]> {
+    let query = FontQuery {
+      weight: style.font_weight,
+      style: style.font_style,
+      stretch: (style.font_stretch * 10.0) as u32,
+    };
+    let matches = self.matches.read().unwrap();
+    if let Some(fonts) = matches
+      .get(&query)
+      .and_then(|families| families.get(&style.font_family[..]))
+    {
+      return fonts.clone();
+    }
+    drop(matches);
+
+    let mut fonts = Vec::new();
+    for family in &style.font_family {
+      let generic = self.generic_families.get(&family.to_ascii_lowercase());
+      let families = match generic {
+        Some(families) => families.clone(),
+        None => vec![family.clone()],
+      };
+      for family in families {
+        let matched = self.match_family(
+          &family,
+          style.font_weight,
+          style.font_style,
+          style.font_stretch,
+        );
+        if let Some(id) = matched {
+          for id in self.composite(id) {
+            if !fonts.contains(&id) {
+              fonts.push(id);
+            }
+          }
+          // 通用字族只使用第一个存在的字族
+          if generic.is_some() {
+            break;
+          }
+        }
+      }
+    }
+
+    let fonts: Arc<[FontId]> = fonts.into();
+    self
+      .matches
+      .write()
+      .unwrap()
+      .entry(query)
+      .or_default()
+      .insert(style.font_family.clone(), fonts.clone());
+    fonts
+  }
+
+  fn closest(
+    &self,
+    mut candidates: Vec<FontId>,
+    weight: u16,
+    style: FontStyle,
+    stretch: f32,
+  ) -> Option<FontId> {
+    // 每一步只保留最接近的字体，再比较下一项
+    let mut keep_best = |rank: &dyn Fn(&Font) -> (u8, f32)| {
+      let best = candidates
+        .iter()
+        .map(|&id| rank(&self.fonts[id]))
+        .min_by(|a, b| a.partial_cmp(b).unwrap());
+      if let Some(best) = best {
+        candidates.retain(|&id| rank(&self.fonts[id]) == best);
+      }
+    };
+    // 在字体支持的范围内时距离为 0，否则比较范围中最接近的值
+    keep_best(&|font| stretch_rank(stretch, stretch.clamp(font.stretch.0, font.stretch.1)));
+    keep_best(&|font| style_rank(style, font.style));
+    keep_best(&|font| {
+      let (min, max) = font.weight;
+      weight_rank(weight as f32, weight.clamp(min, max) as f32)
+    });
+    candidates.first().cloned()
+  }
+}
+
+// 宽度不大于 100% 时优先选择更窄的，否则优先选择更宽的
+fn stretch_rank(desired: f32, value: f32) -> (u8, f32) {
+  let preferred = if desired <= 100.0 {
+    value <= desired
+  } else {
+    value >= desired
+  };
+  if preferred {
+    (0, (desired - value).abs())
+  } else {
+    (1, (desired - value).abs())
+  }
+}
+
+// italic 依次选择 italic、oblique、normal，oblique 依次选择 oblique、italic、normal
+fn style_rank(desired: FontStyle, value: FontStyle) -> (u8, f32) {
+  let order = match desired {
+    FontStyle::Italic => [FontStyle::Italic, FontStyle::Oblique, FontStyle::Normal],
+    FontStyle::Oblique => [FontStyle::Oblique, FontStyle::Italic, FontStyle::Normal],
+    FontStyle::Normal => [FontStyle::Normal, FontStyle::Oblique, FontStyle::Italic],
+  };
+  let rank = order.iter().position(|&style| style == value).unwrap();
+  (rank as u8, 0.0)
+}
+
+// 400 到 500 之间先向上找到 500，再向下找，最后找大于 500 的
+// 小于 400 时先向下找再向上找，大于 500 时先向上找再向下找
+fn weight_rank(desired: f32, value: f32) -> (u8, f32) {
+  let distance = (desired - value).abs();
+  if (400.0..=500.0).contains(&desired) {
+    if value >= desired && value <= 500.0 {
+      (0, distance)
+    } else if value < desired {
+      (1, distance)
+    } else {
+      (2, distance)
+    }
+  } else if desired < 400.0 {
+    if value <= desired {
+      (0, distance)
+    } else {
+      (1, distance)
+    }
+  } else if value >= desired {
+    (0, distance)
+  } else {
+    (1, distance)
+  }
+}
+
+fn collect_font_files(directory: &Path, files: &mut Vec<std::path::PathBuf>) {
+  let entries = match std::fs::read_dir(directory) {
+    Ok(entries) => entries,
+    Err(_) => return,
+  };
+  for entry in entries.flatten() {
+    let path = entry.path();
+    if path.is_dir() {
+      collect_font_files(&path, files);
+    } else {
+      let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| extension.to_ascii_lowercase());
+      if let Some("ttf" | "otf" | "ttc" | "otc") = extension.as_deref() {
+        files.push(path);
+      }
+    }
+  }
+}
+
+impl Default for FontContext {
+  fn default() -> Self {
+    FontContext::new()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::style::PropertyMap;
+
+  // 用附带的字体作为 @font-face 中所有 url() 的数据
+  fn declare(css: &str) -> FontContext {
+    let mut fonts = FontContext::new();
+    let stylesheet = css::parse(css.to_string());
+    let loaded = fonts.load_font_faces(&stylesheet, "test.css", &|_| Some(DEFAULT_FONT.to_vec()));
+    assert_eq!(loaded, stylesheet.rules.len());
+    fonts
+  }
+
+  fn style(families: &[&str], weight: u16, font_style: FontStyle, stretch: f32) -> ComputedStyle {
+    let mut style = ComputedStyle::compute(&PropertyMap::new());
+    style.font_family = families.iter().map(|family| family.to_string()).collect();
+    style.font_weight = weight;
+    style.font_style = font_style;
+    style.font_stretch = stretch;
+    style
+  }
+
+  fn face_rule(family: &str, descriptors: &str) -> String {
+    format!(
+      "@font-face {{ font-family: {}; src: url(a.ttf); {} }}",
+      family, descriptors
+    )
+  }
+
+  #[test]
+  fn matches_weight_style_and_stretch() {
+    let css: String = [
+      face_rule("W", "font-weight: 300"),
+      face_rule("W", "font-weight: 500 600"),
+      face_rule("W", "font-weight: 700"),
+      face_rule("S", ""),
+      face_rule("S", "font-style: oblique"),
+      face_rule("X", "font-stretch: 75%; font-weight: 700"),
+      face_rule("X", "font-stretch: 125%; font-weight: 700"),
+      face_rule("X", "font-weight: 400"),
+    ]
+    .concat();
+    let fonts = declare(&css);
+    let matched = |family: &str, weight: u16, style: FontStyle, stretch: f32| {
+      let id = fonts.match_family(family, weight, style, stretch).unwrap();
+      let font = fonts.font(id);
+      (font.weight, font.style, font.stretch)
+    };
+    let weight = |desired: u16| matched("W", desired, FontStyle::Normal, 100.0).0;
+
+    // 400 到 500 之间先向上找到 500，小于 400 时先向下找，大于 500 时先向上找
+    assert_eq!(weight(400), (500, 600));
+    assert_eq!(weight(550), (500, 600));
+    assert_eq!(weight(200), (300, 300));
+    assert_eq!(weight(350), (300, 300));
+    assert_eq!(weight(650), (700, 700));
+    assert_eq!(weight(900), (700, 700));
+
+    // 没有 italic 时使用 oblique，没有倾斜的字体时使用 normal
+    assert_eq!(
+      matched("S", 400, FontStyle::Italic, 100.0).1,
+      FontStyle::Oblique
+    );
+    assert_eq!(
+      matched("S", 400, FontStyle::Normal, 100.0).1,
+      FontStyle::Normal
+    );
+    assert_eq!(
+      matched("W", 400, FontStyle::Italic, 100.0).1,
+      FontStyle::Normal
+    );
+
+    // 宽度先于粗细比较，不大于 100% 时优先更窄的
+    assert_eq!(
+      matched("X", 700, FontStyle::Normal, 100.0).2,
+      (100.0, 100.0)
+    );
+    assert_eq!(matched("X", 700, FontStyle::Normal, 90.0).2, (75.0, 75.0));
+    assert_eq!(
+      matched("X", 400, FontStyle::Normal, 110.0).2,
+      (125.0, 125.0)
+    );
+    assert!(fonts
+      .match_family("missing", 400, FontStyle::Normal, 100.0)
+      .is_none());
+  }
+
+  #[test]
+  fn maps_generic_families() {
+    let mut fonts = declare(&[face_rule("A", ""), face_rule("B", "")].concat());
+    // 默认的 sans-serif 为附带的 DejaVu Sans
+    let sans = style(&["missing", "sans-serif"], 400, FontStyle::Normal, 100.0);
+    assert_eq!(fonts.select(&sans, 'a'), fonts.default_font());
+
+    // 通用字族只使用第一个存在的字族，字族名不区分大小写
+    fonts.set_generic_family(
+      "Serif",
+      vec!["missing".to_string(), "B".to_string(), "A".to_string()],
+    );
+    let serif = style(&["serif"], 400, FontStyle::Normal, 100.0);
+    let matched = fonts.match_families(&serif);
+    assert_eq!(matched.len(), 1);
+    assert_eq!(fonts.font(matched[0]).family, "B");
+    assert_eq!(fonts.select(&serif, 'a'), matched[0]);
+  }
+
+  #[test]
+  fn falls_back_per_character() {
+    let fonts = declare(
+      &[
+        face_rule("Caps", "unicode-range: U+41-4D"),
+        face_rule("Caps", "unicode-range: U+4E-5A"),
+      ]
+      .concat(),
+    );
+    let caps = style(&["Caps"], 400, FontStyle::Normal, 100.0);
+    let matched = fonts.match_families(&caps);
+    assert_eq!(matched.len(), 2);
+
+    // unicode-range 不同的同一字体组合使用，后声明的优先
+    assert_eq!(fonts.select(&caps, 'Z'), matched[0]);
+    assert_eq!(fonts.select(&caps, 'A'), matched[1]);
+    // 字族中没有的字符在所有字体中查找
+    assert_eq!(fonts.select(&caps, 'a'), fonts.default_font());
+    // 都没有时使用第一个字族的字体
+    assert_eq!(fonts.select(&caps, '\u{10FFFD}'), matched[0]);
+  }
+
+  #[test]
+  fn indexes_font_files_and_loads_them_when_used() {
+    let directory = std::env::temp_dir().join(format!("font-index-{}", std::process::id()));
+    std::fs::create_dir_all(directory.join("sub")).unwrap();
+    std::fs::write(directory.join("sub/Test.TTF"), DEFAULT_FONT).unwrap();
+    std::fs::write(directory.join("notes.txt"), "not a font").unwrap();
+
+    let mut fonts = FontContext::new();
+    let loaded = fonts.load_directory(directory.to_str().unwrap());
+    std::fs::remove_dir_all(&directory).unwrap();
+    assert_eq!(loaded, 1);
+
+    let font = fonts.font(1);
+    assert_eq!(font.family, "DejaVu Sans");
+    assert!(font.has_glyph('a'));
+    assert!(font.face.parsed.get().is_none());
+    // 索引之后文件被删除，字体没有字形
+    assert!(font
+      .shape("a", 16.0, Synthesis::default())
+      .glyphs
+      .is_empty());
+    assert!(font.face.parsed.get().is_some());
+  }
+
+  fn shape(text: &str, synthesis: Synthesis) -> ShapedRun {
+    FontContext::new().font(0).shape(text, 32.0, synthesis)
+  }
+
+  #[test]
+  fn shaped_advances_come_from_the_font() {
+    let fonts = FontContext::new();
+    let font = fonts.font(0);
+    let run = font.shape("il", 32.0, Synthesis::default());
+    let scale = 32.0 / font.units_per_em;
+    assert_eq!(run.glyphs.len(), 2);
+    assert_eq!(
+      run
+        .glyphs
+        .iter()
+        .map(|glyph| glyph.cluster)
+        .collect::<Vec<_>>(),
+      vec![0, 1]
+    );
+    for (glyph, c) in run.glyphs.iter().zip(['i', 'l']) {
+      let face = font.face().unwrap();
+      let id = face.glyph_index(c).unwrap();
+      assert_eq!(glyph.id, id.0);
+      let advance = face.glyph_hor_advance(id).unwrap() as f32 * scale;
+      assert_eq!(glyph.x_advance, advance);
+    }
+    assert_eq!(run.width, run.glyphs[0].x_advance + run.glyphs[1].x_advance);
+
+    // 模拟粗体时每个字形的步进宽度增加
+    let bold = Synthesis {
+      bold: true,
+      oblique: false,
+    };
+    let embolden = bold.embolden(32.0);
+    assert!(embolden > 0.0);
+    assert_eq!(
+      font.shape("il", 32.0, bold).width,
+      run.width + 2.0 * embolden
+    );
+  }
+
+  #[test]
+  fn shaping_applies_kerning() {
+    let separate = shape("A", Synthesis::default()).width + shape("V", Synthesis::default()).width;
+    let kerned = shape("AV", Synthesis::default());
+    assert!(kerned.width < separate, "{} >= {}", kerned.width, separate);
+    assert!(kerned.glyphs[0].x_advance < shape("A", Synthesis::default()).width);
+  }
+
+  #[test]
+  fn rasterizes_glyph_outlines() {
+    let fonts = FontContext::new();
+    let font = fonts.font(0);
+    let glyph = font.shape("l", 32.0, Synthesis::default()).glyphs[0];
+    let bitmap = font
+      .rasterize(glyph.id, 32.0, 10.0, 40.0, Synthesis::default())
+      .unwrap();
+
+    // 字形在基线以上，位图覆盖竖线的全部高度
+    let ascent = font.metrics(32.0).ascent;
+    assert!(bitmap.left >= 10 && bitmap.left < 20);
+    assert!(bitmap.top >= (40.0 - ascent) as i32 && bitmap.top < 20);
+    assert_eq!(bitmap.top + bitmap.height as i32, 40);
+    assert_eq!(bitmap.coverage.len(), bitmap.width * bitmap.height);
+    assert!(bitmap
+      .coverage
+      .iter()
+      .all(|&alpha| (0.0..=1.0).contains(&alpha)));
+    // 竖线中间的像素完全被覆盖，位图边缘的像素没有被完全覆盖
+    let middle = bitmap.height / 2 * bitmap.width;
+    let row = &bitmap.coverage[middle..middle + bitmap.width];
+    assert!(row.iter().any(|&alpha| alpha > 0.99));
+    assert!(row[0] < 1.0 && row[bitmap.width - 1] < 1.0);
+
+    // 模拟倾斜时位图变宽，空白字形没有位图
+    let oblique = Synthesis {
+      bold: false,
+      oblique: true,
+    };
+    let skewed = font.rasterize(glyph.id, 32.0, 10.0, 40.0, oblique).unwrap();
+    assert!(skewed.width > bitmap.width);
+    let space = font.shape(" ", 32.0, Synthesis::default()).glyphs[0];
+    assert!(font
+      .rasterize(space.id, 32.0, 10.0, 40.0, Synthesis::default())
+      .is_none());
+  }
+
+  #[test]
+  fn font_units_come_from_font_metrics() {
+    let face = ttf_parser::Face::parse(DEFAULT_FONT, 0).unwrap();
+    let units_per_em = face.units_per_em() as f32;
+    // 附带的字体 OS/2 表中没有 x 高度，取 x 字形的高度
+    assert_eq!(face.x_height(), None);
+    let x = face.glyph_index('x').unwrap();
+    let x_height = face.glyph_bounding_box(x).unwrap().y_max as f32 / units_per_em;
+    let zero = face.glyph_index('0').unwrap();
+    let zero_advance = face.glyph_hor_advance(zero).unwrap() as f32 / units_per_em;
+
+    let fonts = FontContext::new();
+    let units = fonts.font_units(&style(&["DejaVu Sans"], 400, FontStyle::Normal, 100.0));
+    assert_eq!(units.ex, x_height);
+    assert_eq!(units.ch, zero_advance);
+    assert_ne!(units, FontUnits::default());
+  }
+}
@@ -8,9 +8,7 @@ pub fn parse(source: String) -> dom::Node {
     input: source,
   };
 
-  let mut parser = HtmlParser {
-    source_helper: source_helper,
-  };
+  let mut parser = HtmlParser { source_helper };
 
   let mut nodes = parser.parse_nodes();
 
@@ -62,10 +60,9 @@ impl HtmlParser {
   // 解析标签名字
   fn parse_tag_name(&mut self) -> String {
     // 标签名字，a-z,A-Z,0-9 的组合
-    self.source_helper.consume_while(|c| match c {
-      'a'..='z' | 'A'..='Z' | '0'..='9' => true,
-      _ => false,
-    })
+    self
+      .source_helper
+      .consume_while(|c| matches!(c, 'a'..='z' | 'A'..='Z' | '0'..='9'))
   }
 
   // 解析节点
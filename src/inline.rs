@@ -0,0 +1,458 @@
+use crate::computed::{ComputedStyle, LineHeight, TextAlign, WhiteSpace};
+use crate::dom::NodeType;
+use crate::font::{FontContext, FontId, FontMetrics, ShapedRun, Synthesis};
+use crate::layout::{AnonymousBlock, BlockNode, InlineNode, LayoutBox, Rect};
+use crate::style::StyleNode;
+use unicode_properties::{GeneralCategory, GeneralCategoryGroup, UnicodeGeneralCategory};
+
+// 行框，fragments 为行内的文本片段，baseline 为基线的纵坐标
+#[derive(Debug)]
+pub struct LineBox<'a> {
+  pub rect: Rect,
+  pub baseline: f32,
+  pub fragments: Vec<TextFragment<'a>>,
+}
+
+// 同一个文本节点在一行中的连续文字
+#[derive(Debug)]
+pub struct TextFragment<'a> {
+  pub style_node: &'a StyleNode<'a>,
+  pub text: String,
+  pub rect: Rect,
+  pub font: FontId,
+  pub synthesis: Synthesis,
+  pub glyphs: Vec<PositionedGlyph>,
+}
+
+// 已定位的字形，(x, y) 为字形原点在基线上的位置
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PositionedGlyph {
+  pub id: u16,
+  pub x: f32,
+  pub y: f32,
+}
+
+// 行内格式化上下文中的最小单位
+#[derive(Debug)]
+enum Item<'a> {
+  Text {
+    style_node: &'a StyleNode<'a>,
+    text: String,
+    font: FontId,
+    synthesis: Synthesis,
+    run: ShapedRun,
+    kind: TextKind,
+    // 所在文本是否允许自动换行
+    wrap: bool,
+  },
+  // 行内元素左右两侧的外边距、边框、内边距
+  Edge(f32),
+  // white-space: pre 中的换行符，强制结束所在的行
+  Newline,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TextKind {
+  Word,
+  // 可折叠的空白
+  Space,
+  // 汉字等表意文字，每个字之间都可以换行
+  Ideograph,
+}
+
+// 对匿名块中的行内盒进行排版，content 为匿名块的内容区，高度由行框决定
+// style 为匿名块所在块的样式，决定行内的对齐方式以及空行的高度
+pub fn layout_lines<'a>(
+  boxes: &[LayoutBox<'a>],
+  content: Rect,
+  style: &ComputedStyle,
+  fonts: &FontContext,
+) -> Vec<LineBox<'a>> {
+  let mut builder = ItemBuilder {
+    items: Vec::new(),
+    fonts,
+    basis: content.width,
+    // 块开头的空白总是被折叠掉
+    after_space: true,
+  };
+  for layout_box in boxes {
+    builder.add_box(layout_box);
+  }
+
+  let mut lines = Vec::new();
+  let mut y = content.y;
+  for line_items in break_lines(&builder.items, content.width) {
+    if let Some(line) = position_line(line_items, content, y, style, fonts) {
+      y += line.rect.height;
+      lines.push(line);
+    }
+  }
+  lines
+}
+
+struct ItemBuilder<'a, 'f> {
+  items: Vec<Item<'a>>,
+  fonts: &'f FontContext,
+  // 水平方向百分比的参照宽度
+  basis: f32,
+  // 上一个文字是否为空白，用于折叠相邻文本节点之间的空白
+  after_space: bool,
+}
+
+impl<'a, 'f> ItemBuilder<'a, 'f> {
+  fn add_box(&mut self, layout_box: &LayoutBox<'a>) {
+    let style_node = match layout_box.box_type {
+      InlineNode(style_node) => style_node,
+      // 行内元素中的块级盒尚不支持，忽略
+      BlockNode(_) | AnonymousBlock(_) => return,
+    };
+
+    match &style_node.node.node_type {
+      NodeType::Text(text) => self.add_text(style_node, text),
+      NodeType::Element(_) => {
+        let style = &style_node.computed_style;
+        let left = style.margin.left.resolve(self.basis).to_px()
+          + style.border_width.left
+          + style.padding.left.resolve(self.basis).to_px();
+        let right = style.margin.right.resolve(self.basis).to_px()
+          + style.border_width.right
+          + style.padding.right.resolve(self.basis).to_px();
+
+        self.items.push(Item::Edge(left));
+        for child in &layout_box.children {
+          self.add_box(child);
+        }
+        self.items.push(Item::Edge(right));
+      }
+    }
+  }
+
+  // 按 white-space 处理空白，将文本切分为单词、空白和表意文字
+  fn add_text(&mut self, style_node: &'a StyleNode<'a>, text: &str) {
+    let white_space = style_node.computed_style.white_space;
+    let preserve = white_space == WhiteSpace::Pre;
+    let wrap = white_space == WhiteSpace::Normal;
+
+    let mut word = String::new();
+    for c in text.chars() {
+      if c == '\n' && preserve {
+        self.push_word(style_node, &mut word, wrap);
+        self.items.push(Item::Newline);
+      } else if c.is_whitespace() {
+        self.push_word(style_node, &mut word, wrap);
+        // 非 pre 时连续的空白折叠为一个空格，pre 中的空白与普通文字一样保留
+        if preserve || !self.after_space {
+          let kind = if preserve {
+            TextKind::Word
+          } else {
+            TextKind::Space
+          };
+          self.push_text(style_node, " ".to_string(), kind, wrap);
+        }
+        self.after_space = !preserve;
+      } else if is_ideograph(c) {
+        self.push_word(style_node, &mut word, wrap);
+        self.push_text(style_node, c.to_string(), TextKind::Ideograph, wrap);
+        self.after_space = false;
+      } else {
+        word.push(c);
+        self.after_space = false;
+      }
+    }
+    self.push_word(style_node, &mut word, wrap);
+  }
+
+  fn push_word(&mut self, style_node: &'a StyleNode<'a>, word: &mut String, wrap: bool) {
+    if word.is_empty() {
+      return;
+    }
+    let text = std::mem::take(word);
+    self.push_text(style_node, text, TextKind::Word, wrap);
+  }
+
+  // 按字符选择的字体分段，整形后加入，同一字体的部分整体整形以应用字距调整和连字
+  // 字族只匹配一次，逐字符只检查匹配到的字体中是否含有该字符
+  fn push_text(&mut self, style_node: &'a StyleNode<'a>, text: String, kind: TextKind, wrap: bool) {
+    let style = &style_node.computed_style;
+    let fonts = self.fonts.match_families(style);
+    let mut segments: Vec<(FontId, String)> = Vec::new();
+    for c in text.chars() {
+      let font = self.fonts.select_from(&fonts, style, c);
+      match segments.last_mut() {
+        // 组合字符尽量与前面的字符使用同一字体
+        Some((current, segment))
+          if *current == font || (is_combining(c) && self.fonts.font(*current).has_glyph(c)) =>
+        {
+          segment.push(c)
+        }
+        _ => segments.push((font, c.to_string())),
+      }
+    }
+
+    for (font, text) in segments {
+      let synthesis = self
+        .fonts
+        .font(font)
+        .synthesis(style.font_weight, style.font_style);
+      let run = self
+        .fonts
+        .font(font)
+        .shape(&text, style.font_size, synthesis);
+      self.items.push(Item::Text {
+        style_node,
+        text,
+        font,
+        synthesis,
+        run,
+        kind,
+        wrap,
+      });
+    }
+  }
+}
+
+// 第 i 个单位前能否换行：空白之后，或与表意文字相邻
+fn can_break_before(items: &[Item], i: usize) -> bool {
+  let (previous, current) = match (&items[i - 1], &items[i]) {
+    (
+      Item::Text {
+        kind: previous,
+        wrap: true,
+        ..
+      },
+      Item::Text {
+        kind: current,
+        wrap: true,
+        ..
+      },
+    ) => (*previous, *current),
+    (
+      Item::Text {
+        kind: TextKind::Space,
+        wrap: true,
+        ..
+      },
+      Item::Edge(_),
+    ) => return true,
+    _ => return false,
+  };
+
+  match (previous, current) {
+    (TextKind::Space, TextKind::Space) => false,
+    (TextKind::Space, _) => true,
+    (_, TextKind::Space) => false,
+    (TextKind::Ideograph, _) | (_, TextKind::Ideograph) => true,
+    _ => false,
+  }
+}
+
+// 贪心断行：放不下的片段移到下一行，一行中只有一个过长的片段时允许溢出
+fn break_lines<'b, 'a>(items: &'b [Item<'a>], available: f32) -> Vec<&'b [Item<'a>]> {
+  let mut lines = Vec::new();
+  let mut line_start = 0;
+  let mut line_width = 0.0;
+  let mut i = 0;
+
+  while i < items.len() {
+    // 换行符留在行尾，标记该行由换行符结束
+    if let Item::Newline = items[i] {
+      lines.push(&items[line_start..=i]);
+      line_start = i + 1;
+      line_width = 0.0;
+      i += 1;
+      continue;
+    }
+
+    // 找到下一个换行机会，中间的部分不能拆开
+    let mut end = i + 1;
+    while end < items.len() && !matches!(items[end], Item::Newline) && !can_break_before(items, end)
+    {
+      end += 1;
+    }
+    let chunk = &items[i..end];
+    // 行尾的空白不占宽度
+    let trailing_space = match chunk.last() {
+      Some(Item::Text {
+        kind: TextKind::Space,
+        run,
+        ..
+      }) => run.width,
+      _ => 0.0,
+    };
+    let width = chunk.iter().map(item_width).sum::<f32>();
+
+    if i > line_start && line_width + width - trailing_space > available {
+      lines.push(&items[line_start..i]);
+      line_start = i;
+      line_width = 0.0;
+    }
+    line_width += width;
+    i = end;
+  }
+  if line_start < items.len() {
+    lines.push(&items[line_start..]);
+  }
+
+  lines
+}
+
+fn item_width(item: &Item) -> f32 {
+  match item {
+    Item::Text { run, .. } => run.width,
+    Item::Edge(width) => *width,
+    Item::Newline => 0.0,
+  }
+}
+
+fn is_space(item: &Item) -> bool {
+  matches!(
+    item,
+    Item::Text {
+      kind: TextKind::Space,
+      ..
+    }
+  )
+}
+
+// 计算行内各片段的位置，行首行尾可折叠的空白被去掉，只有空白的行不生成行框
+// 换行符结束的空行仍生成行框，高度取自所在块的字体和行高
+fn position_line<'a>(
+  items: &[Item<'a>],
+  content: Rect,
+  y: f32,
+  style: &ComputedStyle,
+  fonts: &FontContext,
+) -> Option<LineBox<'a>> {
+  let start = items.iter().position(|item| !is_space(item))?;
+  let end = items.iter().rposition(|item| !is_space(item))? + 1;
+  let items = &items[start..end];
+  let has_text = items.iter().any(|item| matches!(item, Item::Text { .. }));
+  if !has_text && !matches!(items.last(), Some(Item::Newline)) {
+    return None;
+  }
+
+  // 基线以上、以下的最大高度，包括行高带来的上下半行距
+  let mut above: f32 = 0.0;
+  let mut below: f32 = 0.0;
+  if !has_text {
+    let (ascent, descent, half_leading) = vertical_metrics(style, fonts.select(style, ' '), fonts);
+    above = ascent + half_leading;
+    below = descent + half_leading;
+  }
+  for item in items {
+    if let Item::Text {
+      style_node, font, ..
+    } = item
+    {
+      let (ascent, descent, half_leading) =
+        vertical_metrics(&style_node.computed_style, *font, fonts);
+      above = above.max(ascent + half_leading);
+      below = below.max(descent + half_leading);
+    }
+  }
+  let baseline = y + above;
+
+  let width = items.iter().map(item_width).sum::<f32>();
+  let offset = match style.text_align {
+    TextAlign::Right => content.width - width,
+    TextAlign::Center => (content.width - width) / 2.0,
+    TextAlign::Left => 0.0,
+  };
+
+  let mut fragments: Vec<TextFragment<'a>> = Vec::new();
+  let mut x = content.x + offset;
+  for (i, item) in items.iter().enumerate() {
+    if let Item::Text {
+      style_node,
+      text,
+      font,
+      synthesis,
+      run,
+      ..
+    } = item
+    {
+      let mut pen = x;
+      let glyphs = run.glyphs.iter().map(|glyph| {
+        let positioned = PositionedGlyph {
+          id: glyph.id,
+          x: pen + glyph.x_offset,
+          y: baseline - glyph.y_offset,
+        };
+        pen += glyph.x_advance;
+        positioned
+      });
+
+      // 同一文本节点中使用同一字体的相邻文字合并为一个片段
+      let continues = i > 0
+        && matches!(&items[i - 1], Item::Text { style_node: previous, font: previous_font, .. }
+          if std::ptr::eq(*previous, *style_node) && previous_font == font);
+      match fragments.last_mut() {
+        Some(last) if continues => {
+          last.text.push_str(text);
+          last.rect.width += run.width;
+          last.glyphs.extend(glyphs);
+        }
+        _ => {
+          let (ascent, descent, _) = vertical_metrics(&style_node.computed_style, *font, fonts);
+          fragments.push(TextFragment {
+            style_node,
+            text: text.clone(),
+            rect: Rect {
+              x,
+              y: baseline - ascent,
+              width: run.width,
+              height: ascent + descent,
+            },
+            font: *font,
+            synthesis: *synthesis,
+            glyphs: glyphs.collect(),
+          });
+        }
+      }
+    }
+    x += item_width(item);
+  }
+
+  Some(LineBox {
+    rect: Rect {
+      x: content.x,
+      y,
+      width: content.width,
+      height: above + below,
+    },
+    baseline,
+    fragments,
+  })
+}
+
+// 上伸部、下伸部取自字体，行高超出的部分平分在上下两侧
+fn vertical_metrics(style: &ComputedStyle, font: FontId, fonts: &FontContext) -> (f32, f32, f32) {
+  let font_size = style.font_size;
+  let FontMetrics {
+    ascent,
+    descent,
+    line_gap,
+  } = fonts.font(font).metrics(font_size);
+  let line_height = match style.line_height {
+    LineHeight::Normal => ascent + descent + line_gap,
+    LineHeight::Number(n) => font_size * n,
+    LineHeight::Px(px) => px,
+  };
+  (ascent, descent, (line_height - ascent - descent) / 2.0)
+}
+
+// 组合用字符和零宽连接符等格式字符，需要与前一个字符一起整形
+fn is_combining(c: char) -> bool {
+  c.general_category_group() == GeneralCategoryGroup::Mark
+    || c.general_category() == GeneralCategory::Format
+}
+
+// 中日韩表意文字及全角标点
+fn is_ideograph(c: char) -> bool {
+  matches!(c,
+    '\u{2E80}'..='\u{9FFF}'
+    | '\u{AC00}'..='\u{D7AF}'
+    | '\u{F900}'..='\u{FAFF}'
+    | '\u{FF00}'..='\u{FFEF}'
+    | '\u{20000}'..='\u{2FFFF}')
+}
@@ -0,0 +1,320 @@
+use crate::css::{CssRule, Selector, SimpleSelector, StyleSheet};
+use crate::dom::{Damage, ElementData, Node, NodeType};
+use std::collections::HashSet;
+
+// 样式表中选择器用到的 id、class、tag，用于判断 dom 或样式表的变化会影响哪些元素
+// 出现在选择器最右边的只影响元素自身，出现在组合器左边的还会影响子孙元素
+#[derive(Debug, Default)]
+pub struct InvalidationSet {
+  ids: HashSet<String>,
+  classes: HashSet<String>,
+  tags: HashSet<String>,
+  ancestor_ids: HashSet<String>,
+  ancestor_classes: HashSet<String>,
+  ancestor_tags: HashSet<String>,
+  // 最右边为通配符的选择器对所有元素都有影响
+  universal: bool,
+  // 级联层的顺序变化会影响所有层中的规则
+  all: bool,
+}
+
+impl InvalidationSet {
+  pub fn new() -> InvalidationSet {
+    InvalidationSet::default()
+  }
+
+  // 收集规则中所有的选择器，包括条件不满足的 @media 等规则中的选择器
+  pub fn add_rules(&mut self, rules: &[CssRule]) {
+    for rule in rules {
+      match rule {
+        CssRule::Style(rule) => {
+          for selector in &rule.selectors {
+            self.add_selector(selector);
+          }
+        }
+        CssRule::Media(media) => self.add_rules(&media.rules),
+        CssRule::Import(import) => self.add_rules(&import.rules),
+        CssRule::Supports(supports) => self.add_rules(&supports.rules),
+        CssRule::Container(container) => self.add_rules(&container.rules),
+        CssRule::Layer(layer) => {
+          self.all = true;
+          if let Some(rules) = &layer.rules {
+            self.add_rules(rules);
+          }
+        }
+        // 字体的变化由 FontContext 处理，不影响选择器匹配
+        CssRule::FontFace(_) => {}
+      }
+    }
+  }
+
+  fn add_selector(&mut self, selector: &Selector) {
+    let rightmost = selector.rightmost();
+    if rightmost.id.is_none() && rightmost.class.is_empty() && rightmost.tag_name.is_none() {
+      self.universal = true;
+    }
+    add_simple_selector(rightmost, &mut self.ids, &mut self.classes, &mut self.tags);
+
+    let mut current = selector;
+    while let Selector::Descendant(left, _) | Selector::Child(left, _) = current {
+      add_simple_selector(
+        left.rightmost(),
+        &mut self.ancestor_ids,
+        &mut self.ancestor_classes,
+        &mut self.ancestor_tags,
+      );
+      current = left;
+    }
+  }
+
+  // 元素的 id、class 变化后需要重新计算的范围
+  pub fn damage_for_change(&self, ids: &[String], classes: &[String]) -> Damage {
+    if ids.iter().any(|id| self.ancestor_ids.contains(id))
+      || classes
+        .iter()
+        .any(|class| self.ancestor_classes.contains(class))
+    {
+      Damage::Subtree
+    } else if ids.iter().any(|id| self.ids.contains(id))
+      || classes.iter().any(|class| self.classes.contains(class))
+    {
+      Damage::Element
+    } else {
+      Damage::None
+    }
+  }
+
+  // 规则变化后元素需要重新计算的范围
+  pub fn damage_for_element(&self, elem: &ElementData) -> Damage {
+    let id = elem.id().into_iter();
+    let classes = elem.classes();
+    if self.all
+      || id.clone().any(|id| self.ancestor_ids.contains(id))
+      || classes
+        .iter()
+        .any(|class| self.ancestor_classes.contains(*class))
+      || self.ancestor_tags.contains(&elem.tag_name)
+    {
+      Damage::Subtree
+    } else if self.universal
+      || id.clone().any(|id| self.ids.contains(id))
+      || classes.iter().any(|class| self.classes.contains(*class))
+      || self.tags.contains(&elem.tag_name)
+    {
+      Damage::Element
+    } else {
+      Damage::None
+    }
+  }
+}
+
+fn add_simple_selector(
+  selector: &SimpleSelector,
+  ids: &mut HashSet<String>,
+  classes: &mut HashSet<String>,
+  tags: &mut HashSet<String>,
+) {
+  ids.extend(selector.id.iter().cloned());
+  classes.extend(selector.class.iter().cloned());
+  tags.extend(selector.tag_name.iter().cloned());
+}
+
+// 根据样式表中用到的 id、class 处理 dom 中记录的变化，确定每个节点需要重新计算的范围
+pub fn resolve_dom_changes(node: &mut Node, features: &InvalidationSet) {
+  let data = &mut node.style_data;
+  if !data.changed_ids.is_empty() || !data.changed_classes.is_empty() {
+    let damage = features.damage_for_change(&data.changed_ids, &data.changed_classes);
+    data.damage = data.damage.max(damage);
+    data.changed_ids.clear();
+    data.changed_classes.clear();
+  }
+  for child in &mut node.children {
+    resolve_dom_changes(child, features);
+  }
+}
+
+// 样式表变化后标记受影响的元素，调用方随后需要在 StyleContext 中换上新的样式表
+// 前后相同的规则不影响匹配，只有中间变化的部分需要处理
+pub fn stylesheet_changed(root: &mut Node, old: &StyleSheet, new: &StyleSheet) {
+  let old_rules: Vec<String> = old.rules.iter().map(|rule| format!("{:#}", rule)).collect();
+  let new_rules: Vec<String> = new.rules.iter().map(|rule| format!("{:#}", rule)).collect();
+
+  let prefix = old_rules
+    .iter()
+    .zip(&new_rules)
+    .take_while(|(a, b)| a == b)
+    .count();
+  let suffix = old_rules[prefix..]
+    .iter()
+    .rev()
+    .zip(new_rules[prefix..].iter().rev())
+    .take_while(|(a, b)| a == b)
+    .count();
+
+  let mut features = InvalidationSet::new();
+  features.add_rules(&old.rules[prefix..old.rules.len() - suffix]);
+  features.add_rules(&new.rules[prefix..new.rules.len() - suffix]);
+  mark_elements(root, &features);
+}
+
+fn mark_elements(node: &mut Node, features: &InvalidationSet) {
+  if let NodeType::Element(elem) = &node.node_type {
+    let damage = features.damage_for_element(elem);
+    node.style_data.damage = node.style_data.damage.max(damage);
+  }
+  for child in &mut node.children {
+    mark_elements(child, features);
+  }
+}
+
+// 视口、媒体环境或容器尺寸变化时，所有节点都需要重新计算
+pub fn invalidate_all(root: &mut Node) {
+  root.style_data.damage = Damage::Subtree;
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::computed::ComputedStyle;
+  use crate::css::{self, Value};
+  use crate::dom::{self, AttrMap};
+  use crate::html;
+  use crate::layout::Rect;
+  use crate::media::MediaEnvironment;
+  use crate::style::{self, PropertyMap, RestyleStats, StyleContext, StyleNode};
+  use std::sync::Arc;
+
+  type Snapshot = Vec<(Arc<PropertyMap>, Arc<ComputedStyle>)>;
+
+  // 按先序遍历记录样式树中每个节点的值
+  fn snapshot(node: &StyleNode, out: &mut Snapshot) {
+    out.push((node.specified_values.clone(), node.computed_style.clone()));
+    for child in &node.children {
+      snapshot(child, out);
+    }
+  }
+
+  fn context(stylesheet: &StyleSheet) -> StyleContext<'_> {
+    let viewport = Rect {
+      width: 800.0,
+      height: 600.0,
+      ..Default::default()
+    };
+    StyleContext::new(stylesheet, viewport, MediaEnvironment::new(viewport))
+  }
+
+  // 增量计算样式，并检查结果与完整计算一致
+  fn restyle(root: &mut Node, context: &StyleContext) -> (Snapshot, RestyleStats) {
+    let mut incremental = Vec::new();
+    let (style_tree, stats) = style::incremental_style_tree(root, context);
+    snapshot(&style_tree, &mut incremental);
+
+    let mut full = Vec::new();
+    snapshot(&style::style_tree(root, context), &mut full);
+    assert!(
+      incremental == full,
+      "incremental restyle differs from a full restyle"
+    );
+    (incremental, stats)
+  }
+
+  fn color(snapshot: &Snapshot, index: usize) -> Option<Value> {
+    snapshot[index].0.get("color").cloned()
+  }
+
+  fn named(name: &str) -> Option<Value> {
+    css::parse_value("color", name.to_string())
+  }
+
+  const DOCUMENT: &str =
+    "<div><section><p>a</p></section><section><p>b</p><em>c</em></section></div>";
+
+  #[test]
+  fn class_change_under_descendant_selector() {
+    let stylesheet = css::parse(
+      ".dark p { color: white } .dark { background: black } p { color: blue }".to_string(),
+    );
+    let context = context(&stylesheet);
+    let mut root = html::parse(DOCUMENT.to_string());
+    let (_, stats) = restyle(&mut root, &context);
+    assert_eq!(stats.reused, 0);
+
+    root.children[1].set_attribute("class", "dark");
+    let (values, stats) = restyle(&mut root, &context);
+    // 第二个 section 的子树重新计算，其余节点沿用上一次的样式
+    assert_eq!(color(&values, 5), named("white"));
+    assert!(stats.reused > 0);
+
+    root.children[1].set_attribute("class", "light");
+    let (values, _) = restyle(&mut root, &context);
+    assert_eq!(color(&values, 5), named("blue"));
+
+    // 与选择器无关的 class 不需要重新计算
+    root.children[0].set_attribute("class", "unused");
+    let (_, stats) = restyle(&mut root, &context);
+    assert_eq!(stats.restyled, 0);
+  }
+
+  #[test]
+  fn id_change() {
+    let stylesheet = css::parse(
+      "#main { color: red } #main em { font-style: italic } em { color: green }".to_string(),
+    );
+    let context = context(&stylesheet);
+    let mut root = html::parse(DOCUMENT.to_string());
+    restyle(&mut root, &context);
+
+    root.children[1].set_attribute("id", "main");
+    let (values, _) = restyle(&mut root, &context);
+    assert_eq!(color(&values, 4), named("red"));
+
+    root.children[1].remove_attribute("id");
+    let (values, _) = restyle(&mut root, &context);
+    assert_eq!(color(&values, 4), None);
+  }
+
+  #[test]
+  fn inserted_and_removed_children() {
+    let stylesheet = css::parse("section p { color: red } .note { color: blue }".to_string());
+    let context = context(&stylesheet);
+    let mut root = html::parse(DOCUMENT.to_string());
+    restyle(&mut root, &context);
+
+    let mut attributes = AttrMap::new();
+    attributes.insert("class".to_string(), "note".to_string());
+    let note = dom::elem(
+      "p".to_string(),
+      attributes,
+      vec![dom::text("d".to_string())],
+    );
+    root.children[1].insert_child(0, note);
+    root.append_child(dom::elem("p".to_string(), AttrMap::new(), vec![]));
+    let (values, stats) = restyle(&mut root, &context);
+    // 只计算新插入的节点
+    assert_eq!(stats.restyled, 3);
+    assert_eq!(color(&values, 5), named("blue"));
+
+    root.children[1].remove_child(0);
+    root.children[0].remove_child(0);
+    let (_, stats) = restyle(&mut root, &context);
+    assert_eq!(stats.restyled, 0);
+  }
+
+  #[test]
+  fn changed_stylesheet() {
+    let old = css::parse("p { color: red } em { color: green }".to_string());
+    let new = css::parse("p { color: red } section > em { color: blue }".to_string());
+    let mut root = html::parse(DOCUMENT.to_string());
+    restyle(&mut root, &context(&old));
+
+    stylesheet_changed(&mut root, &old, &new);
+    let (values, stats) = restyle(&mut root, &context(&new));
+    assert_eq!(color(&values, 7), named("blue"));
+    assert!(stats.reused > 0);
+
+    // 样式表没有变化时不需要重新计算
+    stylesheet_changed(&mut root, &new, &new);
+    let (_, stats) = restyle(&mut root, &context(&new));
+    assert_eq!(stats.restyled, 0);
+  }
+}
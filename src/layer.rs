@@ -0,0 +1,77 @@
+// 级联层，按首次声明的顺序排列，后声明的层优先级更高
+// 根节点表示不属于任何层的规则，优先级高于所有层
+pub struct LayerTree {
+  nodes: Vec<LayerNode>,
+}
+
+struct LayerNode {
+  // 匿名层没有名字
+  name: Option<String>,
+  children: Vec<usize>,
+}
+
+pub const ROOT_LAYER: usize = 0;
+
+impl LayerTree {
+  pub fn new() -> LayerTree {
+    LayerTree {
+      nodes: vec![LayerNode {
+        name: None,
+        children: Vec::new(),
+      }],
+    }
+  }
+
+  // 在 parent 下声明层，name 可以是 a.b 形式，已存在时返回原有的层
+  pub fn declare(&mut self, parent: usize, name: &str) -> usize {
+    name.split('.').fold(parent, |parent, part| {
+      let existing = self.nodes[parent]
+        .children
+        .iter()
+        .copied()
+        .find(|&child| self.nodes[child].name.as_deref() == Some(part));
+      match existing {
+        Some(child) => child,
+        None => self.push(parent, Some(part.to_string())),
+      }
+    })
+  }
+
+  // 匿名层每次都是新的层
+  pub fn anonymous(&mut self, parent: usize) -> usize {
+    self.push(parent, None)
+  }
+
+  fn push(&mut self, parent: usize, name: Option<String>) -> usize {
+    let id = self.nodes.len();
+    self.nodes.push(LayerNode {
+      name,
+      children: Vec::new(),
+    });
+    self.nodes[parent].children.push(id);
+    id
+  }
+
+  // 每个层的优先级，值越大优先级越高
+  // 子层按声明顺序排列，父层中不属于子层的规则排在所有子层之后
+  pub fn ranks(&self) -> Vec<usize> {
+    let mut ranks = vec![0; self.nodes.len()];
+    let mut next = 0;
+    self.assign_ranks(ROOT_LAYER, &mut ranks, &mut next);
+    ranks
+  }
+
+  fn assign_ranks(&self, id: usize, ranks: &mut Vec<usize>, next: &mut usize) {
+    for &child in &self.nodes[id].children {
+      self.assign_ranks(child, ranks, next);
+    }
+    ranks[id] = *next;
+    *next += 1;
+  }
+}
+
+impl Default for LayerTree {
+  fn default() -> Self {
+    LayerTree::new()
+  }
+}
@@ -1,6 +1,9 @@
-use crate::css::Unit::Px;
-use crate::css::Value::{Keyword, Length};
-use crate::style::{Display, StyleNode};
+use crate::computed::Size::Px;
+use crate::computed::{ContainerType, Overflow};
+use crate::dom::Node;
+use crate::font::FontContext;
+use crate::inline::{self, LineBox};
+use crate::style::{self, ContainerSizes, Display, StyleContext, StyleNode};
 use std::default::Default;
 
 pub use self::BoxType::{AnonymousBlock, BlockNode, InlineNode};
@@ -29,59 +32,127 @@ pub struct EdgeSizes {
   pub bottom: f32,
 }
 
+// 相邻的竖直外边距合并后的结果：正值取最大的，负值取绝对值最大的，两者相加
+#[derive(Debug, Clone, Default, Copy)]
+struct CollapsedMargin {
+  positive: f32,
+  negative: f32,
+}
+
+impl CollapsedMargin {
+  fn adjoin(&mut self, margin: f32) {
+    self.positive = self.positive.max(margin);
+    self.negative = self.negative.min(margin);
+  }
+
+  fn value(self) -> f32 {
+    self.positive + self.negative
+  }
+}
+
 #[derive(Debug)]
 pub struct LayoutBox<'a> {
   pub dimensions: Dimensions,
   pub box_type: BoxType<'a>,
   pub children: Vec<LayoutBox<'a>>,
+  // 匿名块中排版后的行框
+  pub lines: Vec<LineBox<'a>>,
 }
 
 #[derive(Debug)]
 pub enum BoxType<'a> {
   BlockNode(&'a StyleNode<'a>),
   InlineNode(&'a StyleNode<'a>),
-  AnonymousBlock,
+  // 匿名块的样式继承自所在的块，这里记录该块的样式节点
+  AnonymousBlock(&'a StyleNode<'a>),
 }
 
 impl<'a> LayoutBox<'a> {
   fn new(box_type: BoxType) -> LayoutBox {
     LayoutBox {
-      box_type: box_type,
+      box_type,
       dimensions: Default::default(),
       children: Vec::new(),
+      lines: Vec::new(),
     }
   }
 
   fn get_style_node(&self) -> &'a StyleNode<'a> {
     match self.box_type {
       BlockNode(node) | InlineNode(node) => node,
-      AnonymousBlock => panic!("AnonymousBlock block box has no style node!"),
+      AnonymousBlock(_) => panic!("AnonymousBlock block box has no style node!"),
     }
   }
 }
 
-pub fn layout_tree<'a>(node: &'a StyleNode<'a>, mut containing_block: Dimensions) -> LayoutBox<'a> {
+pub fn layout_tree<'a>(
+  node: &'a StyleNode<'a>,
+  mut containing_block: Dimensions,
+  fonts: &FontContext,
+) -> LayoutBox<'a> {
   containing_block.content.height = 0.0;
   let mut root_box = build_layout_tree(node);
-  root_box.layout(containing_block);
+  let mut margin = CollapsedMargin::default();
+  match root_box.box_type {
+    // 根元素建立块格式化上下文，外边距不与子节点合并
+    BlockNode(_) => root_box.layout_block(containing_block, fonts, &mut margin, true),
+    _ => root_box.layout(containing_block, fonts, &mut margin),
+  };
   root_box
 }
 
+// 收集布局后查询容器的内容区宽高，用于重新计算 @container 规则
+// 生成用于布局的样式树，@container 规则依赖容器布局后的尺寸，
+// 存在查询容器时先布局一次，记录容器尺寸后重新计算样式
+pub fn style_tree_with_containers<'a>(
+  root: &'a Node,
+  context: &mut StyleContext,
+  containing_block: Dimensions,
+  fonts: &FontContext,
+) -> StyleNode<'a> {
+  let style_tree = style::style_tree(root, context);
+  let sizes = container_sizes(&layout_tree(&style_tree, containing_block, fonts));
+  if sizes.is_empty() {
+    return style_tree;
+  }
+  context.container_sizes = sizes;
+  style::style_tree(root, context)
+}
+
+pub fn container_sizes(layout_box: &LayoutBox) -> ContainerSizes {
+  let mut sizes = ContainerSizes::new();
+  collect_container_sizes(layout_box, &mut sizes);
+  sizes
+}
+
+fn collect_container_sizes(layout_box: &LayoutBox, sizes: &mut ContainerSizes) {
+  if let BlockNode(style_node) = layout_box.box_type {
+    if style_node.computed_style.container_type != ContainerType::Normal {
+      let content = layout_box.dimensions.content;
+      sizes.insert(style_node.node.node_id(), (content.width, content.height));
+    }
+  }
+  for child in &layout_box.children {
+    collect_container_sizes(child, sizes);
+  }
+}
+
 fn build_layout_tree<'a>(style_node: &'a StyleNode<'a>) -> LayoutBox<'a> {
+  // 列表项、表格相关的盒子尚无专门的布局，按块级盒处理
   let mut root = LayoutBox::new(match style_node.display() {
-    Display::Block => BlockNode(style_node),
     Display::Inline => InlineNode(style_node),
     Display::None => panic!("Root node has display: none"),
+    _ => BlockNode(style_node),
   });
 
   for child in &style_node.children {
     match child.display() {
-      Display::Block => root.children.push(build_layout_tree(child)),
       Display::Inline => root
         .get_inline_container()
         .children
         .push(build_layout_tree(child)),
       Display::None => {}
+      _ => root.children.push(build_layout_tree(child)),
     }
   }
 
@@ -89,107 +160,200 @@ fn build_layout_tree<'a>(style_node: &'a StyleNode<'a>) -> LayoutBox<'a> {
 }
 
 impl<'a> LayoutBox<'a> {
-  fn layout(&mut self, containing_block: Dimensions) {
+  // 包含块内容区的底部为上一个盒子的结束位置，margin 为其后尚未确定的外边距
+  // 返回外边距是否穿过该盒子合并，即盒子为空，之后 margin 为该盒子下方的外边距
+  fn layout(
+    &mut self,
+    containing_block: Dimensions,
+    fonts: &FontContext,
+    margin: &mut CollapsedMargin,
+  ) -> bool {
     match self.box_type {
-      BlockNode(_) => self.layout_block(containing_block),
-      InlineNode(_) | AnonymousBlock => {}
+      BlockNode(_) => self.layout_block(containing_block, fonts, margin, false),
+      AnonymousBlock(parent) => {
+        self.layout_anonymous_block(parent, containing_block, fonts, margin)
+      }
+      // 行内盒由所在的匿名块排版
+      InlineNode(_) => true,
+    }
+  }
+
+  // 匿名块占满包含块的宽度，其中的行内盒排列为行框，高度为所有行框高度之和
+  // 没有行框时不影响外边距的合并
+  fn layout_anonymous_block(
+    &mut self,
+    parent: &StyleNode,
+    containing_block: Dimensions,
+    fonts: &FontContext,
+    margin: &mut CollapsedMargin,
+  ) -> bool {
+    let d = &mut self.dimensions;
+    d.content.x = containing_block.content.x;
+    d.content.y = containing_block.content.y + containing_block.content.height + margin.value();
+    d.content.width = containing_block.content.width;
+
+    self.lines = inline::layout_lines(&self.children, d.content, &parent.computed_style, fonts);
+    if self.lines.is_empty() {
+      d.content.y -= margin.value();
+      return true;
     }
+    d.content.height = self.lines.iter().map(|line| line.rect.height).sum();
+    *margin = CollapsedMargin::default();
+    false
   }
 
-  fn layout_block(&mut self, containing_block: Dimensions) {
+  // root 为根元素，与表格、单元格以及 overflow 不为 visible 的盒子一样建立新的块格式化上下文
+  fn layout_block(
+    &mut self,
+    containing_block: Dimensions,
+    fonts: &FontContext,
+    margin: &mut CollapsedMargin,
+    root: bool,
+  ) -> bool {
     // 根据 containing_block 计算宽度
     self.calculate_block_width(containing_block);
 
     // 计算位置
     self.calculate_block_position(containing_block);
 
+    // 上外边距与第一个子节点的上外边距合并，除非有上边框、上内边距或建立新的块格式化上下文
+    // 下外边距同理，且要求高度为 auto
+    let style = &self.get_style_node().computed_style;
+    let new_context = root
+      || style.overflow != Overflow::Visible
+      || matches!(
+        self.get_style_node().display(),
+        Display::Table | Display::TableCell | Display::TableCaption
+      );
+    let d = &mut self.dimensions;
+    let collapse_top = !new_context && d.border.top == 0.0 && d.padding.top == 0.0;
+    let collapse_bottom = !new_context
+      && d.border.bottom == 0.0
+      && d.padding.bottom == 0.0
+      && !matches!(style.height, Px(_));
+
+    margin.adjoin(d.margin.top);
+    let mut children_margin = if collapse_top {
+      *margin
+    } else {
+      d.content.y += margin.value();
+      CollapsedMargin::default()
+    };
+
     // 对子节点布局
-    self.layout_block_children();
+    let has_content = self.layout_block_children(fonts, &mut children_margin, collapse_top);
+
+    let d = &mut self.dimensions;
+    if collapse_top && !has_content {
+      // 空的盒子，上下外边距与子节点的外边距合并在一起
+      if collapse_bottom {
+        children_margin.adjoin(d.margin.bottom);
+        *margin = children_margin;
+        return true;
+      }
+      d.content.y += children_margin.value();
+      children_margin = CollapsedMargin::default();
+    }
+
+    if collapse_bottom {
+      children_margin.adjoin(d.margin.bottom);
+      *margin = children_margin;
+    } else {
+      // 最后一个子节点的下外边距在内容区之内
+      d.content.height = (d.content.height + children_margin.value()).max(0.0);
+      *margin = CollapsedMargin::default();
+      margin.adjoin(d.margin.bottom);
+    }
 
     // 计算整体高度
     self.calculate_block_height();
+    false
   }
 
   // 根据父容器宽度计算节点 x 方向的布局数据
   fn calculate_block_width(&mut self, containing_block: Dimensions) {
-    let style = self.get_style_node();
+    let style = &self.get_style_node().computed_style;
 
-    let auto = Keyword("auto".to_string());
-    let mut width = style.value("width").unwrap_or(auto.clone());
+    // 水平方向的百分比相对包含块宽度
+    let basis = containing_block.content.width;
 
-    let zero = Length(0.0, Px);
+    let mut width = style.width.resolve(basis);
 
-    let mut margin_left = style.lookup("margin-left", "margin", &zero);
-    let mut margin_right = style.lookup("margin-right", "margin", &zero);
+    let mut margin_left = style.margin.left.resolve(basis);
+    let mut margin_right = style.margin.right.resolve(basis);
 
-    let border_left = style.lookup("border-left-width", "border-width", &zero);
-    let border_right = style.lookup("border-right-width", "border-width", &zero);
+    let border_left = style.border_width.left;
+    let border_right = style.border_width.right;
 
-    let padding_left = style.lookup("padding-left", "padding", &zero);
-    let padding_right = style.lookup("padding-right", "padding", &zero);
+    let padding_left = style.padding.left.resolve(basis).to_px();
+    let padding_right = style.padding.right.resolve(basis).to_px();
 
     let total = sum(
       [
-        &margin_left,
-        &margin_right,
-        &border_left,
-        &border_right,
-        &width,
-        &padding_left,
-        &padding_right,
+        margin_left.to_px(),
+        margin_right.to_px(),
+        border_left,
+        border_right,
+        width.to_px(),
+        padding_left,
+        padding_right,
       ]
       .iter()
-      .map(|v| v.to_px()),
+      .cloned(),
     );
 
     // 大于父容器宽度，修改 margin-left，margin-right
-    if width != auto && total > containing_block.content.width {
-      if margin_left == auto {
-        margin_left = Length(0.0, Px);
+    if !width.is_auto() && total > containing_block.content.width {
+      if margin_left.is_auto() {
+        margin_left = Px(0.0);
       }
 
-      if margin_right == auto {
-        margin_right = Length(0.0, Px);
+      if margin_right.is_auto() {
+        margin_right = Px(0.0);
       }
     }
 
     let underflow = containing_block.content.width - total;
-    match (width == auto, margin_left == auto, margin_right == auto) {
+    match (
+      width.is_auto(),
+      margin_left.is_auto(),
+      margin_right.is_auto(),
+    ) {
       // 全部都不为 auto
       (false, false, false) => {
         // 修改 margin-right
-        margin_right = Length(margin_right.to_px() + underflow, Px)
+        margin_right = Px(margin_right.to_px() + underflow)
       }
       // margin-right 为 auto，设置为剩余空间
-      (false, false, true) => margin_right = Length(underflow, Px),
+      (false, false, true) => margin_right = Px(underflow),
 
       // margin-left 为 auto，设置为剩余空间
-      (false, true, false) => margin_left = Length(underflow, Px),
+      (false, true, false) => margin_left = Px(underflow),
 
       // width 为 auto，自适应 width
       (true, _, _) => {
-        if margin_left == auto {
-          margin_left = Length(0.0, Px);
+        if margin_left.is_auto() {
+          margin_left = Px(0.0);
         }
 
-        if margin_right == auto {
-          margin_right = Length(0.0, Px);
+        if margin_right.is_auto() {
+          margin_right = Px(0.0);
         }
 
         if underflow >= 0.0 {
           // 设置宽度为剩余空间
-          width = Length(underflow, Px)
+          width = Px(underflow)
         } else {
           // 超出宽度，减小 margin-right
-          width = Length(0.0, Px);
-          margin_right = Length(margin_right.to_px() + underflow, Px);
+          width = Px(0.0);
+          margin_right = Px(margin_right.to_px() + underflow);
         }
       }
 
       // margin-left，margin-right 平分剩余空间
       (false, true, true) => {
-        margin_left = Length(underflow / 2.0, Px);
-        margin_right = Length(underflow / 2.0, Px);
+        margin_left = Px(underflow / 2.0);
+        margin_right = Px(underflow / 2.0);
       }
     }
 
@@ -197,11 +361,11 @@ impl<'a> LayoutBox<'a> {
     let d = &mut self.dimensions;
     d.content.width = width.to_px();
 
-    d.padding.left = padding_left.to_px();
-    d.padding.right = padding_right.to_px();
+    d.padding.left = padding_left;
+    d.padding.right = padding_right;
 
-    d.border.left = border_left.to_px();
-    d.border.right = border_right.to_px();
+    d.border.left = border_left;
+    d.border.right = border_right;
 
     d.margin.left = margin_left.to_px();
     d.margin.right = margin_right.to_px();
@@ -209,44 +373,56 @@ impl<'a> LayoutBox<'a> {
 
   fn calculate_block_position(&mut self, containing_block: Dimensions) {
     // 计算 x，y，竖直方向间距
-    let style = self.get_style_node();
+    let style = &self.get_style_node().computed_style;
     let d = &mut self.dimensions;
 
-    let zero = Length(0.0, Px);
+    // 竖直方向的 margin、padding 百分比同样相对包含块宽度
+    let basis = containing_block.content.width;
 
-    d.margin.top = style.lookup("margin-top", "margin", &zero).to_px();
-    d.margin.bottom = style.lookup("margin-bottom", "margin", &zero).to_px();
+    d.margin.top = style.margin.top.resolve(basis).to_px();
+    d.margin.bottom = style.margin.bottom.resolve(basis).to_px();
 
-    d.padding.top = style.lookup("padding-top", "padding", &zero).to_px();
-    d.padding.bottom = style.lookup("padding-bottom", "padding", &zero).to_px();
+    d.padding.top = style.padding.top.resolve(basis).to_px();
+    d.padding.bottom = style.padding.bottom.resolve(basis).to_px();
 
-    d.border.top = style
-      .lookup("border-top-width", "border-width", &zero)
-      .to_px();
-    d.border.bottom = style
-      .lookup("border-bottom-width", "border-width", &zero)
-      .to_px();
+    d.border.top = style.border_width.top;
+    d.border.bottom = style.border_width.bottom;
 
+    // 上外边距合并后才能确定，由 layout_block 加上
     d.content.x = containing_block.content.x + d.margin.left + d.border.left + d.padding.left;
-    d.content.y = containing_block.content.y
-      + containing_block.content.height
-      + d.margin.top
-      + d.border.top
-      + d.padding.top;
+    d.content.y =
+      containing_block.content.y + containing_block.content.height + d.border.top + d.padding.top;
   }
 
-  fn layout_block_children(&mut self) {
+  // 依次排列子节点，子节点之间的外边距合并，返回是否有不为空的子节点
+  // collapse_top 时内容区的顶部移到第一个不为空的子节点处，它之前的外边距属于该盒子的上外边距
+  fn layout_block_children(
+    &mut self,
+    fonts: &FontContext,
+    margin: &mut CollapsedMargin,
+    collapse_top: bool,
+  ) -> bool {
     let d = &mut self.dimensions;
+    let mut has_content = false;
     for child in &mut self.children {
-      child.layout(*d);
+      if child.layout(*d, fonts, margin) {
+        continue;
+      }
 
-      d.content.height += child.dimensions.margin_box().height;
+      let border_box = child.dimensions.border_box();
+      if collapse_top && !has_content {
+        d.content.y = border_box.y;
+      }
+      has_content = true;
+      d.content.height = border_box.y + border_box.height - d.content.y;
     }
+    has_content
   }
 
   // 如果设置了 height，则取该值
+  // 包含块的高度由内容决定，百分比高度按 auto 处理
   fn calculate_block_height(&mut self) {
-    if let Some(Length(h, Px)) = self.get_style_node().value("height") {
+    if let Px(h) = self.get_style_node().computed_style.height {
       self.dimensions.content.height = h
     }
   }
@@ -254,14 +430,14 @@ impl<'a> LayoutBox<'a> {
   // if a block node has inline child，simply create a anonymous block wrapping the inline node
   fn get_inline_container(&mut self) -> &mut LayoutBox<'a> {
     match self.box_type {
-      InlineNode(_) | AnonymousBlock => self,
-      BlockNode(_) => {
+      InlineNode(_) | AnonymousBlock(_) => self,
+      BlockNode(node) => {
         match self.children.last() {
           Some(&LayoutBox {
-            box_type: AnonymousBlock,
+            box_type: AnonymousBlock(_),
             ..
           }) => {}
-          _ => self.children.push(LayoutBox::new(AnonymousBlock)),
+          _ => self.children.push(LayoutBox::new(AnonymousBlock(node))),
         }
 
         self.children.last_mut().unwrap()
@@ -303,3 +479,200 @@ impl Dimensions {
     self.border_box().expanded_by(self.margin)
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::css;
+  use crate::dom;
+  use crate::html;
+  use crate::media::MediaEnvironment;
+
+  fn viewport() -> Dimensions {
+    let mut viewport: Dimensions = Default::default();
+    viewport.content.width = 800.0;
+    viewport.content.height = 600.0;
+    viewport
+  }
+
+  // 对文档排版后把根节点的布局盒交给 f
+  fn with_layout<R>(html: &str, css: &str, f: impl FnOnce(&LayoutBox) -> R) -> R {
+    let root = html::parse(html.to_string());
+    let sheet = css::parse(css.to_string());
+    let viewport = viewport();
+    let environment = MediaEnvironment::new(viewport.content);
+    let context = StyleContext::new(&sheet, viewport.content, environment);
+    let style_tree = style::style_tree(&root, &context);
+    let fonts = FontContext::new();
+    f(&layout_tree(&style_tree, viewport, &fonts))
+  }
+
+  #[test]
+  fn container_queries_use_laid_out_sizes() {
+    let root = html::parse(r#"<div class="c"><p></p></div>"#.to_string());
+    let sheet = css::parse(
+      ".c { container-type: inline-size; width: 300px } \
+       @container (max-width: 400px) { p { color: blue } }"
+        .to_string(),
+    );
+    let viewport = viewport();
+    let environment = MediaEnvironment::new(viewport.content);
+    let mut context = StyleContext::new(&sheet, viewport.content, environment);
+    let fonts = FontContext::new();
+
+    let style_tree = style_tree_with_containers(&root, &mut context, viewport, &fonts);
+    assert_eq!(
+      style_tree.children[0].value("color"),
+      css::parse_value("color", "blue".to_string())
+    );
+  }
+
+  #[test]
+  fn container_sizes_survive_dom_mutations() {
+    let mut root = html::parse(r#"<main><div class="c"><p></p></div></main>"#.to_string());
+    let sheet = css::parse(
+      ".c { container-type: inline-size; width: 300px } \
+       @container (max-width: 400px) { p { color: blue } }"
+        .to_string(),
+    );
+    let viewport = viewport();
+    let environment = MediaEnvironment::new(viewport.content);
+    let mut context = StyleContext::new(&sheet, viewport.content, environment);
+    let fonts = FontContext::new();
+    style_tree_with_containers(&root, &mut context, viewport, &fonts);
+
+    // 插入兄弟节点后容器在内存中的位置变化，上一次布局的尺寸仍然对应同一节点
+    root.insert_child(
+      0,
+      dom::elem("span".to_string(), dom::AttrMap::new(), vec![]),
+    );
+    root.append_child(dom::elem("span".to_string(), dom::AttrMap::new(), vec![]));
+    let style_tree = style::style_tree(&root, &context);
+    assert_eq!(
+      style_tree.children[1].children[0].value("color"),
+      css::parse_value("color", "blue".to_string())
+    );
+  }
+
+  #[test]
+  fn wraps_text_into_stacked_lines() {
+    let word = with_layout("<div>wrap</div>", "", |root| {
+      root.children[0].lines[0].fragments[0].rect.width
+    });
+
+    // 宽度只够放下一个单词，每个单词一行
+    let css = format!("div {{ width: {}px }}", word * 1.5);
+    with_layout("<div>wrap wrap wrap wrap wrap</div>", &css, |root| {
+      let block = &root.children[0];
+      let lines = &block.lines;
+      assert_eq!(lines.len(), 5);
+      for (previous, line) in lines.iter().zip(&lines[1..]) {
+        assert_eq!(line.rect.y, previous.rect.y + previous.rect.height);
+      }
+      for line in lines {
+        assert_eq!(line.fragments.len(), 1);
+        assert!(line.fragments[0].rect.width <= word * 1.5);
+      }
+
+      let height: f32 = lines.iter().map(|line| line.rect.height).sum();
+      assert!(height > 0.0);
+      assert_eq!(block.dimensions.content.height, height);
+      assert_eq!(root.dimensions.content.height, height);
+    });
+  }
+
+  #[test]
+  fn preformatted_text_keeps_empty_lines() {
+    with_layout("<pre>a\n\nb</pre>", "", |root| {
+      let lines = &root.children[0].lines;
+      assert_eq!(lines.len(), 3);
+      assert!(lines[1].fragments.is_empty());
+      assert_eq!(lines[1].rect.height, lines[0].rect.height);
+      assert_eq!(lines[2].rect.y, lines[1].rect.y + lines[1].rect.height);
+    });
+  }
+
+  #[test]
+  fn text_align_comes_from_the_containing_block() {
+    let css = ".c { width: 200px; text-align: center } .r { text-align: right }";
+    with_layout(
+      r#"<div class="c"><b class="r">x</b> y</div>"#,
+      css,
+      |root| {
+        let line = &root.children[0].lines[0];
+        let first = line.fragments.first().unwrap().rect;
+        let last = line.fragments.last().unwrap().rect;
+        let left = first.x - line.rect.x;
+        let right = line.rect.x + line.rect.width - (last.x + last.width);
+        assert!(left > 0.0);
+        assert!((left - right).abs() < 0.01);
+      },
+    );
+  }
+
+  // 根节点各子盒子边框盒的纵坐标
+  fn children_y(html: &str, css: &str) -> Vec<f32> {
+    with_layout(html, css, |root| {
+      root
+        .children
+        .iter()
+        .map(|child| child.dimensions.border_box().y)
+        .collect()
+    })
+  }
+
+  #[test]
+  fn sibling_margins_collapse() {
+    let html = r#"<div><div class="a"></div><div class="b"></div></div>"#;
+    let css = ".a { height: 10px; margin-bottom: 20px } .b { height: 10px; margin-top: 30px }";
+    assert_eq!(children_y(html, css), vec![0.0, 40.0]);
+  }
+
+  #[test]
+  fn parent_and_child_margins_collapse_unless_overflow_is_set() {
+    let html = r#"<div><div class="p"><div class="c"></div></div></div>"#;
+    let css = ".p { margin-top: 10px } .c { margin-top: 30px; height: 10px }";
+    with_layout(html, css, |root| {
+      let parent = &root.children[0];
+      assert_eq!(parent.dimensions.content.y, 30.0);
+      assert_eq!(parent.children[0].dimensions.content.y, 30.0);
+    });
+
+    for overflow in ["hidden", "auto", "scroll"] {
+      let css = format!(
+        ".p {{ margin-top: 10px; overflow: {} }} \
+         .c {{ margin-top: 30px; margin-bottom: 20px; height: 10px }}",
+        overflow
+      );
+      with_layout(html, &css, |root| {
+        let parent = &root.children[0];
+        assert_eq!(parent.dimensions.content.y, 10.0);
+        assert_eq!(parent.children[0].dimensions.content.y, 40.0);
+        // 子节点的下外边距留在父节点内部
+        assert_eq!(parent.dimensions.content.height, 60.0);
+      });
+    }
+  }
+
+  #[test]
+  fn margins_collapse_through_empty_blocks() {
+    let html = r#"<div><div class="a"></div><div class="e"></div><div class="b"></div></div>"#;
+    let css = ".a { height: 10px; margin-bottom: 10px } \
+               .e { margin-top: 20px; margin-bottom: 5px } \
+               .b { height: 10px; margin-top: 15px }";
+    assert_eq!(children_y(html, css)[2], 30.0);
+
+    // 建立块格式化上下文的空盒子不让外边距穿过
+    let css = format!("{} .e {{ overflow: hidden }}", css);
+    assert_eq!(children_y(html, &css)[2], 45.0);
+  }
+
+  #[test]
+  fn negative_margins_collapse() {
+    let html = r#"<div><div class="a"></div><div class="b"></div></div>"#;
+    let css = ".a { height: 10px; margin-bottom: 30px } .b { height: 10px; margin-top: -10px }";
+    assert_eq!(children_y(html, css)[1], 30.0);
+    let css = ".a { height: 10px; margin-bottom: -10px } .b { height: 10px; margin-top: -20px }";
+    assert_eq!(children_y(html, css)[1], -10.0);
+  }
+}
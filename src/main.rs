@@ -4,15 +4,37 @@ use std::default::Default;
 use std::fs::File;
 use std::io::{BufWriter, Read};
 
+pub mod bench;
+pub mod color;
+pub mod computed;
 pub mod css;
 pub mod dom;
+pub mod font;
 pub mod html;
+pub mod inline;
+pub mod invalidation;
+pub mod layer;
 pub mod layout;
+pub mod media;
 pub mod painting;
+pub mod properties;
+pub mod resolved;
+pub mod rule_index;
+pub mod serialize;
+pub mod shorthand;
 pub mod source;
 pub mod style;
+pub mod style_sharing;
+pub mod variables;
+pub mod woff2;
 
 fn main() {
+    // 样式计算的性能测试
+    if std::env::args().any(|arg| arg == "--bench") {
+        bench::run();
+        return;
+    }
+
     // 解析 dom
     let html = read_source("example/test.html".to_string());
     let root = html::parse(html.to_string());
@@ -20,27 +42,42 @@ fn main() {
 
     // 解析 css
     let css = read_source("example/test.css".to_string());
-    let stylesheet = css::parse(css.to_string());
-    println!("{:?}", stylesheet);
+    let stylesheet = css::parse_with_loader(css.to_string(), "example/test.css", &|path| {
+        std::fs::read_to_string(path).ok()
+    });
+    for diagnostic in &stylesheet.diagnostics {
+        println!("warning: {}", diagnostic);
+    }
+    println!("{}", stylesheet);
+
+    // 定义默认视口，800*600
+    let mut viewport: layout::Dimensions = Default::default();
+    viewport.content.width = 800.0;
+    viewport.content.height = 600.0;
+
+    // 样式计算、排版和绘制使用的字体，除附带的默认字体外还加载系统字体
+    let mut fonts = font::FontContext::new();
+    fonts.load_system_fonts();
+    fonts.load_font_faces(&stylesheet, "example/test.css", &|path| std::fs::read(path).ok());
 
-    // 生成样式树
-    let style_tree = style::style_tree(&root, &stylesheet);
+    // 样式计算的上下文，ex、ch 按字体的度量解析
+    let environment = media::MediaEnvironment::new(viewport.content);
+    let mut context = style::StyleContext::new(&stylesheet, viewport.content, environment);
+    context.fonts = Some(&fonts);
+
+    // 生成样式树，存在查询容器时会先排版一次得到容器尺寸
+    let style_tree = layout::style_tree_with_containers(&root, &mut context, viewport, &fonts);
     println!("{:?}", style_tree);
 
     // 生成布局树
-    let layout_tree = layout::layout_tree(&style_tree, Default::default());
+    let layout_tree = layout::layout_tree(&style_tree, viewport, &fonts);
     println!("{:?}", layout_tree);
 
     let filename = "output.png";
-    let mut file = BufWriter::new(File::create(&filename).unwrap());
-
-    // 定义默认视口，800*600
-    let mut viewport: layout::Dimensions = Default::default();
-    viewport.content.width = 800.0;
-    viewport.content.height = 600.0;
+    let mut file = BufWriter::new(File::create(filename).unwrap());
 
     // 光栅化，生成像素点
-    let canvas = painting::paint(&layout_tree, viewport.content);
+    let canvas = painting::paint(&layout_tree, viewport.content, &fonts);
     let (w, h) = (canvas.width as u32, canvas.height as u32);
 
     // 根据像素点，生成图片
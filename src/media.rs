@@ -0,0 +1,379 @@
+use crate::computed::{FontUnits, LengthContext, MEDIUM_FONT_SIZE};
+use crate::css::Value;
+use crate::layout::Rect;
+
+// 媒体类型，未知类型永远不匹配
+#[derive(Debug, Clone, PartialEq)]
+pub enum MediaType {
+  All,
+  Screen,
+  Print,
+  Unknown(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorScheme {
+  Light,
+  Dark,
+}
+
+// 范围比较，统一为 "特性 op 值" 的形式，如 width >= 600px
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RangeOp {
+  Lt,
+  Le,
+  Eq,
+  Ge,
+  Gt,
+}
+
+// 媒体特性，min-width 等前缀形式在解析时转换为范围比较
+#[derive(Debug, Clone, PartialEq)]
+pub enum MediaFeature {
+  // (color)、(orientation)，特性有效即匹配
+  Boolean(String),
+  // (orientation: landscape)、(prefers-color-scheme: dark)
+  Discrete(String, String),
+  // (min-width: 600px)、(400px <= width < 800px)
+  Range(String, Vec<(RangeOp, Value)>),
+}
+
+// 单个媒体查询，如 not screen and (min-width: 600px)
+#[derive(Debug, Clone, PartialEq)]
+pub struct MediaQuery {
+  pub negated: bool,
+  pub media_type: MediaType,
+  pub features: Vec<MediaFeature>,
+}
+
+// 逗号分隔的媒体查询列表，任意一个匹配即匹配，空列表总是匹配
+#[derive(Debug, Clone, PartialEq)]
+pub struct MediaQueryList {
+  pub queries: Vec<MediaQuery>,
+}
+
+// 媒体查询所依据的环境，由调用方与视口一起传入
+#[derive(Debug, Clone)]
+pub struct MediaEnvironment {
+  pub media_type: MediaType,
+  pub width: f32,
+  pub height: f32,
+  // 每个 css 像素对应的设备像素数，即 dppx
+  pub resolution: f32,
+  pub color_scheme: ColorScheme,
+}
+
+impl MediaEnvironment {
+  // 默认为屏幕设备，宽高与视口一致
+  pub fn new(viewport: Rect) -> MediaEnvironment {
+    MediaEnvironment {
+      media_type: MediaType::Screen,
+      width: viewport.width,
+      height: viewport.height,
+      resolution: 1.0,
+      color_scheme: ColorScheme::Light,
+    }
+  }
+}
+
+// 媒体查询和容器查询中的 em 相对于初始字号，ex、ch 没有对应的字体，取近似值
+fn length_context(width: f32, height: f32) -> LengthContext {
+  LengthContext {
+    font_size: MEDIUM_FONT_SIZE,
+    root_font_size: MEDIUM_FONT_SIZE,
+    viewport: Rect {
+      x: 0.0,
+      y: 0.0,
+      width,
+      height,
+    },
+    font_units: FontUnits::default(),
+  }
+}
+
+impl MediaQueryList {
+  pub fn matches(&self, env: &MediaEnvironment) -> bool {
+    self.queries.is_empty() || self.queries.iter().any(|query| query.matches(env))
+  }
+}
+
+impl MediaQuery {
+  pub fn matches(&self, env: &MediaEnvironment) -> bool {
+    let type_matches = match &self.media_type {
+      MediaType::All => true,
+      MediaType::Unknown(_) => false,
+      media_type => *media_type == env.media_type,
+    };
+    let matches = type_matches && self.features.iter().all(|feature| feature.matches(env));
+    matches != self.negated
+  }
+}
+
+impl MediaFeature {
+  pub fn matches(&self, env: &MediaEnvironment) -> bool {
+    match self {
+      MediaFeature::Boolean(name) => match &**name {
+        "width" => env.width != 0.0,
+        "height" => env.height != 0.0,
+        "resolution" => env.resolution != 0.0,
+        "orientation" | "prefers-color-scheme" | "color" => true,
+        _ => false,
+      },
+      MediaFeature::Discrete(name, value) => match &**name {
+        "orientation" => value == orientation(env.width, env.height),
+        "prefers-color-scheme" => match env.color_scheme {
+          ColorScheme::Light => value == "light",
+          ColorScheme::Dark => value == "dark",
+        },
+        _ => false,
+      },
+      MediaFeature::Range(name, comparisons) => {
+        let context = length_context(env.width, env.height);
+        match &**name {
+          "width" => compare_lengths(env.width, comparisons, &context),
+          "height" => compare_lengths(env.height, comparisons, &context),
+          "resolution" => comparisons.iter().all(|(op, value)| match value {
+            Value::Resolution(dppx) => compare(env.resolution, *op, *dppx),
+            _ => false,
+          }),
+          _ => false,
+        }
+      }
+    }
+  }
+
+  // 容器查询，使用容器内容区的尺寸，inline-size 容器的 height 未知
+  pub fn matches_container(&self, width: f32, height: Option<f32>) -> bool {
+    let size = |name: &str| match name {
+      "width" | "inline-size" => Some(width),
+      "height" | "block-size" => height,
+      _ => None,
+    };
+
+    match self {
+      MediaFeature::Boolean(name) if name == "orientation" => height.is_some(),
+      MediaFeature::Boolean(name) => size(name).is_some_and(|f| f != 0.0),
+      MediaFeature::Discrete(name, value) => match (&**name, height) {
+        ("orientation", Some(height)) => value == orientation(width, height),
+        _ => false,
+      },
+      MediaFeature::Range(name, comparisons) => match size(name) {
+        Some(actual) => {
+          let context = length_context(width, height.unwrap_or(0.0));
+          compare_lengths(actual, comparisons, &context)
+        }
+        None => false,
+      },
+    }
+  }
+}
+
+// 比较长度，值只能是带单位的长度或 0
+fn compare_lengths(actual: f32, comparisons: &[(RangeOp, Value)], context: &LengthContext) -> bool {
+  comparisons.iter().all(|(op, value)| {
+    let expected = match value {
+      Value::Length(f, unit) => context.to_px(*f, *unit),
+      Value::Number(f) if *f == 0.0 => 0.0,
+      _ => return false,
+    };
+    compare(actual, *op, expected)
+  })
+}
+
+// 高度不小于宽度时为竖屏
+fn orientation(width: f32, height: f32) -> &'static str {
+  if height >= width {
+    "portrait"
+  } else {
+    "landscape"
+  }
+}
+
+fn compare(actual: f32, op: RangeOp, expected: f32) -> bool {
+  match op {
+    RangeOp::Lt => actual < expected,
+    RangeOp::Le => actual <= expected,
+    RangeOp::Eq => actual == expected,
+    RangeOp::Ge => actual >= expected,
+    RangeOp::Gt => actual > expected,
+  }
+}
+
+impl RangeOp {
+  // 值写在特性左边时，比较方向反转，如 400px < width 即 width > 400px
+  pub fn flip(self) -> RangeOp {
+    match self {
+      RangeOp::Lt => RangeOp::Gt,
+      RangeOp::Le => RangeOp::Ge,
+      RangeOp::Eq => RangeOp::Eq,
+      RangeOp::Ge => RangeOp::Le,
+      RangeOp::Gt => RangeOp::Lt,
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::css::{self, CssRule};
+
+  // 解析 @media 规则的媒体查询，规则被忽略时返回 None
+  fn parse_queries(prelude: &str) -> Option<MediaQueryList> {
+    let sheet = css::parse(format!(
+      "@media {} {{ p {{ color: red }} }} a {{ color: blue }}",
+      prelude
+    ));
+    match &sheet.rules[..] {
+      [CssRule::Media(rule), CssRule::Style(_)] => Some(rule.queries.clone()),
+      [CssRule::Style(_)] => {
+        assert_eq!(sheet.diagnostics.len(), 1, "{}", prelude);
+        None
+      }
+      rules => panic!("Unexpected rules for {}: {:?}", prelude, rules),
+    }
+  }
+
+  fn env(width: f32, height: f32) -> MediaEnvironment {
+    MediaEnvironment::new(Rect {
+      x: 0.0,
+      y: 0.0,
+      width,
+      height,
+    })
+  }
+
+  fn matches(prelude: &str, env: &MediaEnvironment) -> bool {
+    parse_queries(prelude).unwrap().matches(env)
+  }
+
+  #[test]
+  fn parses_min_and_max_as_ranges() {
+    let queries = parse_queries("screen and (min-width: 600px) and (max-width: 50em)").unwrap();
+    assert_eq!(
+      queries.queries,
+      vec![MediaQuery {
+        negated: false,
+        media_type: MediaType::Screen,
+        features: vec![
+          MediaFeature::Range(
+            "width".to_string(),
+            vec![(RangeOp::Ge, Value::Length(600.0, css::Unit::Px))]
+          ),
+          MediaFeature::Range(
+            "width".to_string(),
+            vec![(RangeOp::Le, Value::Length(50.0, css::Unit::Em))]
+          ),
+        ],
+      }]
+    );
+
+    let prelude = "(min-width: 600px) and (max-width: 50em)";
+    assert!(!matches(prelude, &env(599.0, 600.0)));
+    assert!(matches(prelude, &env(600.0, 600.0)));
+    assert!(matches(prelude, &env(800.0, 600.0)));
+    assert!(!matches(prelude, &env(801.0, 600.0)));
+  }
+
+  #[test]
+  fn matches_range_syntax() {
+    assert!(matches("(width >= 600px)", &env(600.0, 400.0)));
+    assert!(!matches("(width > 600px)", &env(600.0, 400.0)));
+    assert!(matches("(600px = width)", &env(600.0, 400.0)));
+
+    // 值在左边时比较方向反转
+    let prelude = "(400px < width <= 800px)";
+    assert!(!matches(prelude, &env(400.0, 300.0)));
+    assert!(matches(prelude, &env(401.0, 300.0)));
+    assert!(matches(prelude, &env(800.0, 300.0)));
+    assert!(!matches(prelude, &env(801.0, 300.0)));
+    assert!(matches("(height < 20em)", &env(800.0, 300.0)));
+  }
+
+  #[test]
+  fn matches_media_types_with_not_and_only() {
+    let screen = env(800.0, 600.0);
+    let print = MediaEnvironment {
+      media_type: MediaType::Print,
+      ..env(800.0, 600.0)
+    };
+
+    assert!(matches("screen", &screen));
+    assert!(!matches("screen", &print));
+    assert!(matches("only screen and (min-width: 600px)", &screen));
+    assert!(!matches("not screen", &screen));
+    assert!(matches("not screen", &print));
+    // not 作用于整个查询
+    assert!(matches("not screen and (max-width: 600px)", &screen));
+    assert!(!matches("not all and (min-width: 600px)", &screen));
+    // 未知媒体类型永远不匹配，取反后总是匹配
+    assert!(!matches("tv", &screen));
+    assert!(matches("not tv", &screen));
+    // 任意一个查询匹配即匹配
+    assert!(matches("print, (min-width: 600px)", &screen));
+    assert!(!matches("print, (min-width: 900px)", &screen));
+  }
+
+  #[test]
+  fn matches_orientation_resolution_and_color_scheme() {
+    let landscape = env(800.0, 600.0);
+    let portrait = env(600.0, 800.0);
+    assert!(matches("(orientation: landscape)", &landscape));
+    assert!(!matches("(orientation: landscape)", &portrait));
+    assert!(matches("(orientation: portrait)", &portrait));
+    assert!(matches("(orientation)", &portrait));
+
+    let retina = MediaEnvironment {
+      resolution: 2.0,
+      ..landscape.clone()
+    };
+    assert!(!matches("(min-resolution: 2dppx)", &landscape));
+    assert!(matches("(min-resolution: 2dppx)", &retina));
+    assert!(matches("(min-resolution: 192dpi)", &retina));
+    assert!(matches("(resolution: 2x)", &retina));
+    assert!(!matches("(max-resolution: 1.5x)", &retina));
+
+    let dark = MediaEnvironment {
+      color_scheme: ColorScheme::Dark,
+      ..landscape.clone()
+    };
+    assert!(matches("(prefers-color-scheme: light)", &landscape));
+    assert!(!matches("(prefers-color-scheme: dark)", &landscape));
+    assert!(matches("(prefers-color-scheme: dark)", &dark));
+    assert!(!matches(
+      "(prefers-color-scheme: DARK) and (max-width: 600px)",
+      &dark
+    ));
+  }
+
+  #[test]
+  fn unknown_features_and_values_never_match() {
+    let screen = env(800.0, 600.0);
+    assert!(!matches("(hover: hover)", &screen));
+    assert!(!matches("(min-width: auto)", &screen));
+    assert!(!matches("(orientation: sideways)", &screen));
+  }
+
+  #[test]
+  fn drops_malformed_media_rules() {
+    assert!(parse_queries("(min-width: 600px").is_none());
+    assert!(parse_queries("screen and (min-width:600px) and").is_none());
+    assert!(parse_queries("screen and").is_none());
+    assert!(parse_queries("(min-width:)").is_none());
+    assert!(parse_queries("(width ! 600px)").is_none());
+    assert!(parse_queries("(400px < )").is_none());
+    assert!(parse_queries("screen print").is_none());
+    assert!(parse_queries("screen, , print").is_none());
+
+    // 媒体查询没有规则块，或在结尾处中断
+    for css in [
+      "@media screen; a { color: blue }",
+      "a { color: blue } @media (min-width: 600px",
+      "a { color: blue } @media (width >",
+      "a { color: blue } @media",
+    ] {
+      let sheet = css::parse(css.to_string());
+      assert!(matches!(&sheet.rules[..], [CssRule::Style(_)]), "{}", css);
+      assert_eq!(sheet.diagnostics.len(), 1, "{}", css);
+    }
+  }
+}
@@ -1,4 +1,7 @@
-use crate::css::{Color, Value};
+use crate::computed::ComputedStyle;
+use crate::css::Color;
+use crate::font::{FontContext, FontId, Synthesis};
+use crate::inline::PositionedGlyph;
 use crate::layout::{AnonymousBlock, BlockNode, InlineNode, LayoutBox, Rect};
 
 pub struct Canvas {
@@ -10,17 +13,25 @@ pub struct Canvas {
 #[derive(Debug)]
 pub enum DisplayCommand {
   SolidColor(Color, Rect),
+  // 一段已定位的字形，使用同一字体、字号和颜色
+  Text {
+    glyphs: Vec<PositionedGlyph>,
+    font: FontId,
+    font_size: f32,
+    synthesis: Synthesis,
+    color: Color,
+  },
 }
 
 // 绘制命令列表
 pub type DisplayList = Vec<DisplayCommand>;
 
 // 处理绘制命令，转换为像素点
-pub fn paint(layout_root: &LayoutBox, bounds: Rect) -> Canvas {
+pub fn paint(layout_root: &LayoutBox, bounds: Rect, fonts: &FontContext) -> Canvas {
   let display_list = build_display_list(layout_root);
   let mut canvas = Canvas::new(bounds.width as usize, bounds.height as usize);
   for item in display_list {
-    canvas.paint_item(&item)
+    canvas.paint_item(&item, fonts)
   }
 
   canvas
@@ -37,6 +48,7 @@ pub fn build_display_list(layout_root: &LayoutBox) -> DisplayList {
 fn render_layout_box(list: &mut DisplayList, layout_box: &LayoutBox) {
   render_background(list, layout_box);
   render_borders(list, layout_box);
+  render_text(list, layout_box);
 
   for child in &layout_box.children {
     render_layout_box(list, child);
@@ -46,18 +58,18 @@ fn render_layout_box(list: &mut DisplayList, layout_box: &LayoutBox) {
 // 绘制背景命令
 fn render_background(list: &mut DisplayList, layout_box: &LayoutBox) {
   // bg:border+padding+content
-  get_color(layout_box, "background").map(|color| {
+  if let Some(color) = get_style(layout_box).and_then(|style| style.background_color) {
     list.push(DisplayCommand::SolidColor(
       color,
       layout_box.dimensions.border_box(),
     ))
-  });
+  }
 }
 
 // 绘制边框命令
 fn render_borders(list: &mut DisplayList, layout_box: &LayoutBox) {
-  let color = match get_color(layout_box, "border-color") {
-    Some(color) => color,
+  let colors = match get_style(layout_box) {
+    Some(style) => style.border_color,
     _ => return,
   };
 
@@ -73,7 +85,9 @@ fn render_borders(list: &mut DisplayList, layout_box: &LayoutBox) {
   };
 
   // left border
-  list.push(DisplayCommand::SolidColor(color, left_rect));
+  if let Some(color) = colors.left {
+    list.push(DisplayCommand::SolidColor(color, left_rect));
+  }
 
   // right rect
   let right_rect = Rect {
@@ -84,7 +98,9 @@ fn render_borders(list: &mut DisplayList, layout_box: &LayoutBox) {
   };
 
   // right border
-  list.push(DisplayCommand::SolidColor(color, right_rect));
+  if let Some(color) = colors.right {
+    list.push(DisplayCommand::SolidColor(color, right_rect));
+  }
 
   // top rect
   let top_rect = Rect {
@@ -95,7 +111,9 @@ fn render_borders(list: &mut DisplayList, layout_box: &LayoutBox) {
   };
 
   // top border
-  list.push(DisplayCommand::SolidColor(color, top_rect));
+  if let Some(color) = colors.top {
+    list.push(DisplayCommand::SolidColor(color, top_rect));
+  }
 
   // bottom rect
   let bottom_rect = Rect {
@@ -106,17 +124,35 @@ fn render_borders(list: &mut DisplayList, layout_box: &LayoutBox) {
   };
 
   // bottom border
-  list.push(DisplayCommand::SolidColor(color, bottom_rect));
+  if let Some(color) = colors.bottom {
+    list.push(DisplayCommand::SolidColor(color, bottom_rect));
+  }
+}
+
+// 绘制匿名块中行框里的文字
+fn render_text(list: &mut DisplayList, layout_box: &LayoutBox) {
+  for line in &layout_box.lines {
+    for fragment in &line.fragments {
+      let style = &fragment.style_node.computed_style;
+      if style.color.a == 0 {
+        continue;
+      }
+      list.push(DisplayCommand::Text {
+        glyphs: fragment.glyphs.clone(),
+        font: fragment.font,
+        font_size: style.font_size,
+        synthesis: fragment.synthesis,
+        color: style.color,
+      });
+    }
+  }
 }
 
-// 获取某个属性对应的色值
-fn get_color(layout_box: &LayoutBox, name: &str) -> Option<Color> {
+// 获取盒子对应的计算样式
+fn get_style<'a>(layout_box: &LayoutBox<'a>) -> Option<&'a ComputedStyle> {
   match layout_box.box_type {
-    BlockNode(style) | InlineNode(style) => match style.value(name) {
-      Some(Value::ColorValue(color)) => Some(color),
-      _ => None,
-    },
-    AnonymousBlock => None,
+    BlockNode(style) | InlineNode(style) => Some(&style.computed_style),
+    AnonymousBlock(_) => None,
   }
 }
 
@@ -130,13 +166,13 @@ impl Canvas {
     };
     Canvas {
       pixels: vec![white; width * height],
-      width: width,
-      height: height,
+      width,
+      height,
     }
   }
 
   // 填充像素点
-  fn paint_item(&mut self, item: &DisplayCommand) {
+  fn paint_item(&mut self, item: &DisplayCommand, fonts: &FontContext) {
     match *item {
       DisplayCommand::SolidColor(color, rect) => {
         let x0 = rect.x.clamp(0.0, self.width as f32) as usize;
@@ -147,20 +183,165 @@ impl Canvas {
 
         for y in y0..y1 {
           for x in x0..x1 {
-            self.pixels[y * self.width + x] = color;
+            let index = y * self.width + x;
+            self.pixels[index] = blend(color, self.pixels[index]);
+          }
+        }
+      }
+      DisplayCommand::Text {
+        ref glyphs,
+        font,
+        font_size,
+        synthesis,
+        color,
+      } => {
+        let font = fonts.font(font);
+        for glyph in glyphs {
+          if let Some(bitmap) = font.rasterize(glyph.id, font_size, glyph.x, glyph.y, synthesis) {
+            self.paint_coverage(
+              color,
+              bitmap.left,
+              bitmap.top,
+              bitmap.width,
+              &bitmap.coverage,
+            );
           }
         }
       }
     }
   }
+
+  // 按覆盖率混合颜色，实现边缘的抗锯齿，超出画布的部分被裁掉
+  fn paint_coverage(&mut self, color: Color, left: i32, top: i32, width: usize, coverage: &[f32]) {
+    for (i, &alpha) in coverage.iter().enumerate() {
+      let x = left + (i % width) as i32;
+      let y = top + (i / width) as i32;
+      if alpha <= 0.0 || x < 0 || y < 0 || x >= self.width as i32 || y >= self.height as i32 {
+        continue;
+      }
+      let index = y as usize * self.width + x as usize;
+      let src = Color {
+        a: (color.a as f32 * alpha).round() as u8,
+        ..color
+      };
+      self.pixels[index] = blend(src, self.pixels[index]);
+    }
+  }
 }
 
-trait Clamp {
-  fn clamp(self, lower: Self, upper: Self) -> Self;
+// 按 source-over 规则将 src 叠加到 dst 上
+fn blend(src: Color, dst: Color) -> Color {
+  if src.a == 255 {
+    return src;
+  }
+
+  let src_a = src.a as f32 / 255.0;
+  let dst_a = dst.a as f32 / 255.0;
+  let out_a = src_a + dst_a * (1.0 - src_a);
+  if out_a == 0.0 {
+    return src;
+  }
+
+  let channel = |s: u8, d: u8| {
+    let c = (s as f32 * src_a + d as f32 * dst_a * (1.0 - src_a)) / out_a;
+    c.round() as u8
+  };
+  Color {
+    r: channel(src.r, dst.r),
+    g: channel(src.g, dst.g),
+    b: channel(src.b, dst.b),
+    a: (out_a * 255.0).round() as u8,
+  }
 }
 
-impl Clamp for f32 {
-  fn clamp(self, lower: f32, upper: f32) -> f32 {
-    self.max(lower).min(upper)
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const WHITE: Color = Color {
+    r: 255,
+    g: 255,
+    b: 255,
+    a: 255,
+  };
+  const RED: Color = Color {
+    r: 255,
+    g: 0,
+    b: 0,
+    a: 255,
+  };
+
+  #[test]
+  fn blends_source_over() {
+    assert_eq!(blend(RED, WHITE), RED);
+    let transparent = Color { a: 0, ..RED };
+    assert_eq!(blend(transparent, WHITE), WHITE);
+    let half = Color { a: 128, ..RED };
+    assert_eq!(
+      blend(half, WHITE),
+      Color {
+        r: 255,
+        g: 127,
+        b: 127,
+        a: 255
+      }
+    );
+  }
+
+  #[test]
+  fn paints_glyph_coverage_in_text_color() {
+    let fonts = FontContext::new();
+    let font = fonts.font(0);
+    let run = font.shape("l", 32.0, Synthesis::default());
+    let glyph = PositionedGlyph {
+      id: run.glyphs[0].id,
+      x: 10.0,
+      y: 40.0,
+    };
+    let mut canvas = Canvas::new(48, 48);
+    canvas.paint_item(
+      &DisplayCommand::Text {
+        glyphs: vec![glyph],
+        font: 0,
+        font_size: 32.0,
+        synthesis: Synthesis::default(),
+        color: RED,
+      },
+      &fonts,
+    );
+
+    let bitmap = font
+      .rasterize(glyph.id, 32.0, glyph.x, glyph.y, Synthesis::default())
+      .unwrap();
+    let inside = |x: i32, y: i32| {
+      x >= bitmap.left
+        && x < bitmap.left + bitmap.width as i32
+        && y >= bitmap.top
+        && y < bitmap.top + bitmap.height as i32
+    };
+    let mut opaque = 0;
+    for y in 0..canvas.height as i32 {
+      for x in 0..canvas.width as i32 {
+        let pixel = canvas.pixels[y as usize * canvas.width + x as usize];
+        if !inside(x, y) {
+          assert_eq!(pixel, WHITE, "({}, {})", x, y);
+          continue;
+        }
+        // 字形范围内按覆盖率把文字颜色叠加到背景上
+        let i = (y - bitmap.top) as usize * bitmap.width + (x - bitmap.left) as usize;
+        let alpha = (255.0 * bitmap.coverage[i]).round() as u8;
+        assert_eq!(
+          pixel,
+          blend(Color { a: alpha, ..RED }, WHITE),
+          "({}, {})",
+          x,
+          y
+        );
+        if pixel == RED {
+          opaque += 1;
+        }
+      }
+    }
+    assert!(opaque > 0);
   }
 }
@@ -0,0 +1,158 @@
+use crate::css::{self, Value};
+use crate::shorthand;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+// 属性注册表中的一项：是否继承，初始值
+#[derive(Debug)]
+pub struct Property {
+  pub name: &'static str,
+  pub inherited: bool,
+  pub initial: Value,
+}
+
+// (属性名，是否继承，初始值)
+static PROPERTIES: &[(&str, bool, &str)] = &[
+  // 可继承属性
+  ("color", true, "black"),
+  ("direction", true, "ltr"),
+  ("font-family", true, "serif"),
+  ("font-size", true, "16px"),
+  ("font-stretch", true, "normal"),
+  ("font-style", true, "normal"),
+  ("font-variant", true, "normal"),
+  ("font-weight", true, "normal"),
+  ("letter-spacing", true, "normal"),
+  ("line-height", true, "normal"),
+  ("list-style-type", true, "disc"),
+  ("text-align", true, "left"),
+  ("text-transform", true, "none"),
+  ("visibility", true, "visible"),
+  ("white-space", true, "normal"),
+  ("word-spacing", true, "normal"),
+  // 不可继承属性
+  ("display", false, "inline"),
+  ("width", false, "auto"),
+  ("height", false, "auto"),
+  ("margin-top", false, "0px"),
+  ("margin-right", false, "0px"),
+  ("margin-bottom", false, "0px"),
+  ("margin-left", false, "0px"),
+  ("padding-top", false, "0px"),
+  ("padding-right", false, "0px"),
+  ("padding-bottom", false, "0px"),
+  ("padding-left", false, "0px"),
+  ("border-top-width", false, "medium"),
+  ("border-right-width", false, "medium"),
+  ("border-bottom-width", false, "medium"),
+  ("border-left-width", false, "medium"),
+  ("border-top-style", false, "none"),
+  ("border-right-style", false, "none"),
+  ("border-bottom-style", false, "none"),
+  ("border-left-style", false, "none"),
+  ("border-top-color", false, "currentcolor"),
+  ("border-right-color", false, "currentcolor"),
+  ("border-bottom-color", false, "currentcolor"),
+  ("border-left-color", false, "currentcolor"),
+  ("background-color", false, "transparent"),
+  ("box-sizing", false, "content-box"),
+  ("overflow", false, "visible"),
+  ("position", false, "static"),
+  ("top", false, "auto"),
+  ("right", false, "auto"),
+  ("bottom", false, "auto"),
+  ("left", false, "auto"),
+  ("flex-grow", false, "0"),
+  ("flex-shrink", false, "1"),
+  ("flex-basis", false, "auto"),
+  ("container-type", false, "normal"),
+  ("container-name", false, "none"),
+];
+
+fn registry() -> &'static HashMap<&'static str, Property> {
+  static REGISTRY: OnceLock<HashMap<&'static str, Property>> = OnceLock::new();
+  REGISTRY.get_or_init(|| {
+    PROPERTIES
+      .iter()
+      .map(|&(name, inherited, initial)| {
+        let property = Property {
+          name,
+          inherited,
+          initial: css::parse_value(name, initial.to_string()).expect("Invalid initial value"),
+        };
+        (name, property)
+      })
+      .collect()
+  })
+}
+
+// 查找已注册的属性
+pub fn lookup(name: &str) -> Option<&'static Property> {
+  registry().get(name)
+}
+
+// 属性是否可继承，自定义属性总是继承，其余未注册的属性按不可继承处理
+pub fn is_inherited(name: &str) -> bool {
+  css::is_custom_property(name) || lookup(name).is_some_and(|property| property.inherited)
+}
+
+// 属性的初始值
+pub fn initial_value(name: &str) -> Option<Value> {
+  lookup(name).map(|property| property.initial.clone())
+}
+
+// 只接受关键字的属性及其可取值，用于 @supports 检测
+static KEYWORDS: &[(&str, &[&str])] = &[
+  (
+    "display",
+    &[
+      "inline",
+      "block",
+      "list-item",
+      "table",
+      "table-caption",
+      "table-column-group",
+      "table-column",
+      "table-header-group",
+      "table-row-group",
+      "table-footer-group",
+      "table-row",
+      "table-cell",
+      "none",
+    ],
+  ),
+  ("font-style", &["normal", "italic", "oblique"]),
+  ("text-align", &["left", "right", "center"]),
+  ("white-space", &["normal", "nowrap", "pre"]),
+  ("overflow", &["visible", "hidden", "auto", "scroll"]),
+  ("container-type", &["normal", "size", "inline-size"]),
+];
+
+// 是否支持该属性及值，@supports (name: value) 使用
+// 自定义属性和已注册的属性、简写属性均支持，只接受关键字的属性还需检查取值
+pub fn supports(name: &str, value: &str) -> bool {
+  if css::is_custom_property(name) {
+    return true;
+  }
+  if lookup(name).is_none() && shorthand::longhands(name).is_none() {
+    return false;
+  }
+
+  match KEYWORDS.iter().find(|(property, _)| *property == name) {
+    Some((_, keywords)) => {
+      shorthand::is_css_wide_keyword(value)
+        || keywords.iter().any(|k| value.eq_ignore_ascii_case(k))
+    }
+    None => true,
+  }
+}
+
+// 所有已注册的属性
+pub fn all_properties() -> impl Iterator<Item = &'static Property> {
+  registry().values()
+}
+
+// 所有可继承的属性
+pub fn inherited_properties() -> impl Iterator<Item = &'static Property> {
+  registry().values().filter(|property| property.inherited)
+}
@@ -0,0 +1,113 @@
+use crate::css::{self, Color, Value};
+use crate::dom::Node;
+use crate::properties;
+use crate::style::StyleNode;
+use std::collections::BTreeMap;
+
+// 类似 window.getComputedStyle，返回节点所有属性的解析值，按属性名排序
+// 未设置的属性取初始值，颜色统一为 rgb() 形式，长度统一为 px，节点不在样式树中时返回 None
+pub fn get_computed_style(style_tree: &StyleNode, node: &Node) -> Option<BTreeMap<String, String>> {
+  style_tree.find(node).map(resolved_values)
+}
+
+// 单个样式节点的解析值
+pub fn resolved_values(style_node: &StyleNode) -> BTreeMap<String, String> {
+  let values = &style_node.specified_values;
+  let style = &style_node.computed_style;
+
+  let mut resolved = BTreeMap::new();
+  for property in properties::all_properties() {
+    let value = values
+      .get(property.name)
+      .unwrap_or(&property.initial)
+      .to_string();
+    resolved.insert(property.name.to_string(), value);
+  }
+
+  // 自定义属性保留替换后的原始文本
+  for (name, value) in values.iter() {
+    if css::is_custom_property(name) {
+      resolved.insert(name.clone(), value.to_string().trim().to_string());
+    }
+  }
+
+  // 关键字需要计算的属性以计算值为准
+  resolved.insert("color".to_string(), rgb(Some(style.color)));
+  resolved.insert("background-color".to_string(), rgb(style.background_color));
+  for (side, width, color) in [
+    ("top", style.border_width.top, style.border_color.top),
+    ("right", style.border_width.right, style.border_color.right),
+    (
+      "bottom",
+      style.border_width.bottom,
+      style.border_color.bottom,
+    ),
+    ("left", style.border_width.left, style.border_color.left),
+  ] {
+    resolved.insert(
+      format!("border-{}-width", side),
+      Value::Length(width, css::Unit::Px).to_string(),
+    );
+    resolved.insert(format!("border-{}-color", side), rgb(color));
+  }
+
+  resolved
+}
+
+// None 表示 transparent
+fn rgb(color: Option<Color>) -> String {
+  let Color { r, g, b, a } = color.unwrap_or(Color {
+    r: 0,
+    g: 0,
+    b: 0,
+    a: 0,
+  });
+  if a == 255 {
+    format!("rgb({}, {}, {})", r, g, b)
+  } else {
+    // 透明度保留三位小数
+    let alpha = (a as f32 / 255.0 * 1000.0).round() / 1000.0;
+    format!("rgba({}, {}, {}, {})", r, g, b, alpha)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::html;
+  use crate::layout::Rect;
+  use crate::media::MediaEnvironment;
+  use crate::style::{self, StyleContext};
+
+  #[test]
+  fn resolves_values_of_a_node() {
+    let root = html::parse(r#"<div><p class="x"></p></div>"#.to_string());
+    let sheet = css::parse(
+      "div { color: red; font-size: 10px; --gap: 4px } \
+       .x { background-color: rgba(0, 0, 255, 0.5); border-top: 2px solid; margin-left: 2em }"
+        .to_string(),
+    );
+    let viewport = Rect {
+      width: 800.0,
+      height: 600.0,
+      ..Default::default()
+    };
+    let context = StyleContext::new(&sheet, viewport, MediaEnvironment::new(viewport));
+    let style_tree = style::style_tree(&root, &context);
+
+    let values = get_computed_style(&style_tree, &root.children[0]).unwrap();
+    let get = |name: &str| values.get(name).map(String::as_str);
+    assert_eq!(get("color"), Some("rgb(255, 0, 0)"));
+    assert_eq!(get("background-color"), Some("rgba(0, 0, 255, 0.502)"));
+    assert_eq!(get("border-top-width"), Some("2px"));
+    assert_eq!(get("border-top-color"), Some("rgb(255, 0, 0)"));
+    assert_eq!(get("border-left-width"), Some("0px"));
+    assert_eq!(get("margin-left"), Some("20px"));
+    assert_eq!(get("--gap"), Some("4px"));
+    assert_eq!(get("display"), Some("block"));
+
+    // 不在样式树中的节点
+    let detached = html::parse("<p></p>".to_string());
+    assert!(get_computed_style(&style_tree, &detached).is_none());
+  }
+}
@@ -0,0 +1,307 @@
+use crate::css::{ContainerRule, Rule, Selector, SimpleSelector, Specificity};
+use crate::dom::ElementData;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
+// 当前环境下生效的样式规则
+pub struct CollectedRule<'a> {
+  pub rule: &'a Rule,
+  // 所在级联层的优先级
+  pub layer: usize,
+  // 第一条声明在样式表中的全局序号
+  pub source_order: usize,
+  // 外层的 @container 规则，由外到内，需要按元素所在的容器逐个计算
+  pub containers: Vec<&'a ContainerRule>,
+}
+
+// 索引中的单个选择器
+pub struct IndexedSelector<'a> {
+  // 所属规则在 rules 中的下标
+  pub rule: usize,
+  pub selector: &'a Selector,
+  pub specificity: Specificity,
+  // 组合器左边各部分的 tag、id、class，必须都出现在祖先元素中
+  pub ancestor_hashes: Vec<u32>,
+}
+
+// 按选择器最右边部分的 id、class、tag 分组的规则索引
+// 匹配时只需检查与元素有相同 id、class、tag 的选择器
+pub struct RuleIndex<'a> {
+  pub rules: Vec<CollectedRule<'a>>,
+  pub selectors: Vec<IndexedSelector<'a>>,
+  by_id: HashMap<&'a str, Vec<usize>>,
+  by_class: HashMap<&'a str, Vec<usize>>,
+  by_tag: HashMap<&'a str, Vec<usize>>,
+  // 最右边为通配符的选择器，所有元素都要检查
+  universal: Vec<usize>,
+}
+
+impl<'a> RuleIndex<'a> {
+  pub fn new(rules: Vec<CollectedRule<'a>>) -> RuleIndex<'a> {
+    let mut index = RuleIndex {
+      rules: Vec::new(),
+      selectors: Vec::new(),
+      by_id: HashMap::new(),
+      by_class: HashMap::new(),
+      by_tag: HashMap::new(),
+      universal: Vec::new(),
+    };
+
+    for (i, collected) in rules.iter().enumerate() {
+      for selector in &collected.rule.selectors {
+        let id = index.selectors.len();
+        index.selectors.push(IndexedSelector {
+          rule: i,
+          selector,
+          specificity: selector.specificity(),
+          ancestor_hashes: ancestor_hashes(selector),
+        });
+
+        // 只放入一个分组，优先使用区分度最高的 id
+        let rightmost = selector.rightmost();
+        let bucket = if let Some(name) = &rightmost.id {
+          index.by_id.entry(name).or_default()
+        } else if let Some(name) = rightmost.class.first() {
+          index.by_class.entry(name).or_default()
+        } else if let Some(name) = &rightmost.tag_name {
+          index.by_tag.entry(name).or_default()
+        } else {
+          &mut index.universal
+        };
+        bucket.push(id);
+      }
+    }
+    index.rules = rules;
+
+    index
+  }
+
+  // 可能与元素匹配的选择器下标，按书写顺序排列
+  pub fn candidates(&self, elem: &ElementData) -> Vec<usize> {
+    let mut candidates = self.universal.clone();
+    if let Some(ids) = elem.id().and_then(|id| self.by_id.get(&**id)) {
+      candidates.extend(ids);
+    }
+    for class in elem.classes() {
+      if let Some(ids) = self.by_class.get(class) {
+        candidates.extend(ids);
+      }
+    }
+    if let Some(ids) = self.by_tag.get(&*elem.tag_name) {
+      candidates.extend(ids);
+    }
+    candidates.sort_unstable();
+    candidates
+  }
+}
+
+// 祖先元素的计数布隆过滤器，遍历时进入元素加入，离开时移除
+// 选择器要求的祖先 tag、id、class 不在过滤器中时一定不匹配，可以跳过完整的匹配
+#[derive(Clone)]
+pub struct AncestorFilter {
+  counters: Vec<u16>,
+  // 每个祖先加入的哈希，移除时使用
+  stack: Vec<Vec<u32>>,
+}
+
+const FILTER_SIZE: usize = 1 << 12;
+
+impl AncestorFilter {
+  pub fn new() -> AncestorFilter {
+    AncestorFilter {
+      counters: vec![0; FILTER_SIZE],
+      stack: Vec::new(),
+    }
+  }
+
+  pub fn push(&mut self, elem: &ElementData) {
+    let hashes = element_hashes(elem);
+    for &hash in &hashes {
+      for slot in slots(hash) {
+        self.counters[slot] += 1;
+      }
+    }
+    self.stack.push(hashes);
+  }
+
+  pub fn pop(&mut self) {
+    let hashes = self.stack.pop().expect("Pop from empty ancestor filter");
+    for hash in hashes {
+      for slot in slots(hash) {
+        self.counters[slot] -= 1;
+      }
+    }
+  }
+
+  // 可能误判为存在，但不会误判为不存在
+  pub fn may_contain_all(&self, hashes: &[u32]) -> bool {
+    hashes
+      .iter()
+      .all(|&hash| slots(hash).iter().all(|&slot| self.counters[slot] > 0))
+  }
+}
+
+impl Default for AncestorFilter {
+  fn default() -> Self {
+    AncestorFilter::new()
+  }
+}
+
+// 每个哈希占两个位置，分别取低位和高位
+fn slots(hash: u32) -> [usize; 2] {
+  let mask = FILTER_SIZE - 1;
+  [hash as usize & mask, (hash >> 16) as usize & mask]
+}
+
+// tag、id、class 使用不同的前缀，避免 div 与 .div 混淆
+fn identifier_hash(kind: char, name: &str) -> u32 {
+  let mut hasher = DefaultHasher::new();
+  kind.hash(&mut hasher);
+  name.hash(&mut hasher);
+  hasher.finish() as u32
+}
+
+fn simple_selector_hashes(selector: &SimpleSelector, hashes: &mut Vec<u32>) {
+  if let Some(tag_name) = &selector.tag_name {
+    hashes.push(identifier_hash('t', tag_name));
+  }
+  if let Some(id) = &selector.id {
+    hashes.push(identifier_hash('#', id));
+  }
+  for class in &selector.class {
+    hashes.push(identifier_hash('.', class));
+  }
+}
+
+fn element_hashes(elem: &ElementData) -> Vec<u32> {
+  let mut hashes = vec![identifier_hash('t', &elem.tag_name)];
+  if let Some(id) = elem.id() {
+    hashes.push(identifier_hash('#', id));
+  }
+  for class in elem.classes() {
+    hashes.push(identifier_hash('.', class));
+  }
+  hashes
+}
+
+fn ancestor_hashes(selector: &Selector) -> Vec<u32> {
+  let mut hashes = Vec::new();
+  let mut current = selector;
+  while let Selector::Descendant(left, _) | Selector::Child(left, _) = current {
+    simple_selector_hashes(left.rightmost(), &mut hashes);
+    current = left;
+  }
+  hashes.sort_unstable();
+  hashes.dedup();
+  hashes
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::css::{self, CssRule};
+  use crate::dom::{Node, NodeType};
+  use crate::html;
+  use crate::style;
+
+  // 逐个元素比较：索引和祖先过滤器筛选后匹配的选择器，应与逐个检查所有选择器的结果相同
+  // counts 为匹配的选择器数和被祖先过滤器跳过的候选数
+  fn compare<'a>(
+    node: &'a Node,
+    index: &RuleIndex,
+    ancestors: &mut Vec<&'a ElementData>,
+    filter: &mut AncestorFilter,
+    counts: &mut (usize, usize),
+  ) {
+    let elem = match &node.node_type {
+      NodeType::Element(elem) => elem,
+      NodeType::Text(_) => return,
+    };
+    let matches = |id: &usize| style::matches(elem, ancestors, index.selectors[*id].selector);
+    let linear: Vec<usize> = (0..index.selectors.len()).filter(matches).collect();
+    let candidates = index.candidates(elem);
+    let filtered: Vec<usize> = candidates
+      .iter()
+      .copied()
+      .filter(|&id| filter.may_contain_all(&index.selectors[id].ancestor_hashes))
+      .collect();
+    let indexed: Vec<usize> = filtered.iter().copied().filter(matches).collect();
+    assert_eq!(indexed, linear, "<{} {:?}>", elem.tag_name, elem.attributes);
+    counts.0 += linear.len();
+    counts.1 += candidates.len() - filtered.len();
+
+    ancestors.push(elem);
+    filter.push(elem);
+    for child in &node.children {
+      compare(child, index, ancestors, filter, counts);
+    }
+    filter.pop();
+    ancestors.pop();
+  }
+
+  #[test]
+  fn indexed_matching_equals_linear_matching() {
+    let stylesheet = css::parse(
+      "* { margin: 0 } p { color: red } .a { color: red } #main { color: red } \
+       div p { color: red } section > p { color: red } div > .b { color: red } \
+       #main .a span { color: red } .a.b { color: red } section.c > div.a p.b { color: red } \
+       #n3 > span, .c em { color: red } #missing p { color: red } .x > p { color: red } \
+       div div div span { color: red } section > section > p { color: red }"
+        .to_string(),
+    );
+    let rules = stylesheet
+      .rules
+      .iter()
+      .map(|rule| match rule {
+        CssRule::Style(rule) => CollectedRule {
+          rule,
+          layer: 0,
+          source_order: 0,
+          containers: Vec::new(),
+        },
+        rule => panic!("Unexpected rule {:?}", rule),
+      })
+      .collect();
+    let index = RuleIndex::new(rules);
+
+    // 不同深度的 section、div 嵌套，class 和 id 按序号变化
+    let mut document = String::from(r#"<main id="main">"#);
+    let mut open = Vec::new();
+    for i in 0..12 {
+      let classes = ["a", "b", "c", "a b", "b c"][i % 5];
+      let tag = ["div", "section"][i % 2];
+      document.push_str(&format!(r#"<{} class="{}">"#, tag, classes));
+      open.push(tag);
+      for j in 0..3 {
+        document.push_str(&format!(
+          r#"<div class="{}"><p id="n{}" class="b"><span>x</span><em>y</em></p><span></span></div>"#,
+          ["a", "b", "x"][j],
+          i * 3 + j
+        ));
+      }
+      document.push_str("<p>z</p>");
+      // 每三层回到上一层
+      if i % 3 == 2 {
+        document.push_str(&format!("</{}>", open.pop().unwrap()));
+      }
+    }
+    for tag in open.into_iter().rev() {
+      document.push_str(&format!("</{}>", tag));
+    }
+    document.push_str("</main>");
+    let root = html::parse(document);
+
+    let mut counts = (0, 0);
+    compare(
+      &root,
+      &index,
+      &mut Vec::new(),
+      &mut AncestorFilter::new(),
+      &mut counts,
+    );
+    let (matched, skipped) = counts;
+    assert!(matched > 100, "{}", matched);
+    assert!(skipped > 0);
+  }
+}
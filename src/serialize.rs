@@ -0,0 +1,630 @@
+use crate::computed::FontStyle;
+use crate::css::{
+  CalcNode, Color, Condition, CssRule, Declaration, FontFaceRule, FontSource, LayerRule, Rule,
+  Selector, SimpleSelector, StyleSheet, SupportsFeature, Unit, Value,
+};
+use crate::media::{MediaFeature, MediaQuery, MediaQueryList, MediaType, RangeOp};
+use std::fmt::{self, Display, Formatter, Write};
+
+// 将样式表序列化为 css 文本，minify 为 true 时去掉所有可省略的空白
+pub fn to_css(stylesheet: &StyleSheet, minify: bool) -> String {
+  let mut serializer = Serializer {
+    out: String::new(),
+    minify,
+    indent: 0,
+  };
+  serializer.write_rules(&stylesheet.rules);
+  serializer.out
+}
+
+// 格式化输出使用 {}，压缩输出使用 {:#}
+impl Display for StyleSheet {
+  fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+    f.write_str(&to_css(self, f.alternate()))
+  }
+}
+
+impl Display for CssRule {
+  fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+    let mut serializer = Serializer {
+      out: String::new(),
+      minify: f.alternate(),
+      indent: 0,
+    };
+    serializer.write_rule(self);
+    f.write_str(&serializer.out)
+  }
+}
+
+impl Display for Rule {
+  fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+    let mut serializer = Serializer {
+      out: String::new(),
+      minify: f.alternate(),
+      indent: 0,
+    };
+    serializer.write_style_rule(self);
+    f.write_str(&serializer.out)
+  }
+}
+
+struct Serializer {
+  out: String,
+  minify: bool,
+  indent: usize,
+}
+
+impl Serializer {
+  // 格式化输出时规则之间空一行
+  fn write_rules(&mut self, rules: &[CssRule]) {
+    for (i, rule) in rules.iter().enumerate() {
+      if i > 0 && !self.minify {
+        self.out.push('\n');
+      }
+      self.write_rule(rule);
+    }
+  }
+
+  fn write_rule(&mut self, rule: &CssRule) {
+    match rule {
+      CssRule::Style(rule) => self.write_style_rule(rule),
+      CssRule::Media(media) => {
+        let prelude = format!("@media {}", display(&media.queries, self.minify));
+        self.write_block(&prelude, |s| s.write_rules(&media.rules));
+      }
+      CssRule::Import(import) => {
+        self.write_indent();
+        let _ = write!(self.out, "@import url(\"{}\")", escape(&import.url));
+        if !import.queries.queries.is_empty() {
+          let _ = write!(self.out, " {}", display(&import.queries, self.minify));
+        }
+        self.out.push(';');
+        self.write_newline();
+      }
+      CssRule::Supports(supports) => {
+        let prelude = format!("@supports {}", display(&supports.condition, self.minify));
+        self.write_block(&prelude, |s| s.write_rules(&supports.rules));
+      }
+      CssRule::Layer(LayerRule { names, rules: None }) => {
+        self.write_indent();
+        let separator = if self.minify { "," } else { ", " };
+        let _ = write!(self.out, "@layer {};", names.join(separator));
+        self.write_newline();
+      }
+      CssRule::Layer(LayerRule {
+        names,
+        rules: Some(rules),
+      }) => {
+        let prelude = match names.first() {
+          Some(name) => format!("@layer {}", name),
+          None => "@layer".to_string(),
+        };
+        self.write_block(&prelude, |s| s.write_rules(rules));
+      }
+      CssRule::Container(container) => {
+        let mut prelude = "@container ".to_string();
+        if let Some(name) = &container.name {
+          let _ = write!(prelude, "{} ", name);
+        }
+        prelude.push_str(&display(&container.condition, self.minify));
+        self.write_block(&prelude, |s| s.write_rules(&container.rules));
+      }
+      CssRule::FontFace(font_face) => self.write_font_face_rule(font_face),
+    }
+  }
+
+  // 只输出与默认值不同的描述符
+  fn write_font_face_rule(&mut self, rule: &FontFaceRule) {
+    let separator = if self.minify { "," } else { ", " };
+    let mut descriptors = vec![
+      ("font-family", format!("\"{}\"", escape(&rule.family))),
+      (
+        "src",
+        rule
+          .sources
+          .iter()
+          .map(|source| match source {
+            FontSource::Url(url, None) => format!("url(\"{}\")", escape(url)),
+            FontSource::Url(url, Some(format)) => {
+              format!("url(\"{}\") format(\"{}\")", escape(url), escape(format))
+            }
+            FontSource::Local(name) => format!("local(\"{}\")", escape(name)),
+          })
+          .collect::<Vec<String>>()
+          .join(separator),
+      ),
+    ];
+    if rule.weight != (400, 400) {
+      descriptors.push(("font-weight", range(rule.weight.0, rule.weight.1, "")));
+    }
+    match rule.style {
+      FontStyle::Normal => {}
+      FontStyle::Italic => descriptors.push(("font-style", "italic".to_string())),
+      FontStyle::Oblique => descriptors.push(("font-style", "oblique".to_string())),
+    }
+    if rule.stretch != (100.0, 100.0) {
+      descriptors.push(("font-stretch", range(rule.stretch.0, rule.stretch.1, "%")));
+    }
+    if !rule.unicode_range.is_empty() {
+      let ranges: Vec<String> = rule
+        .unicode_range
+        .iter()
+        .map(|&(start, end)| {
+          if start == end {
+            format!("U+{:X}", start)
+          } else {
+            format!("U+{:X}-{:X}", start, end)
+          }
+        })
+        .collect();
+      descriptors.push(("unicode-range", ranges.join(separator)));
+    }
+
+    self.write_block("@font-face", |s| {
+      for (i, (name, value)) in descriptors.iter().enumerate() {
+        s.write_indent();
+        let colon = if s.minify { ":" } else { ": " };
+        let _ = write!(s.out, "{}{}{}", name, colon, value);
+        if !s.minify || i + 1 < descriptors.len() {
+          s.out.push(';');
+        }
+        s.write_newline();
+      }
+    });
+  }
+
+  fn write_style_rule(&mut self, rule: &Rule) {
+    let separator = if self.minify { "," } else { ", " };
+    let selectors: Vec<String> = rule
+      .selectors
+      .iter()
+      .map(|s| display(s, self.minify))
+      .collect();
+    let prelude = selectors.join(separator);
+
+    // 含 var() 的简写属性展开后的普通属性共用同一个原始值，合并输出为一条简写声明
+    let mut declarations: Vec<Declaration> = Vec::new();
+    for declaration in &rule.declarations {
+      let shorthand = match &declaration.value {
+        Value::Unparsed {
+          css,
+          shorthand: Some(shorthand),
+        } => Some(Declaration {
+          name: shorthand.clone(),
+          value: Value::Unparsed {
+            css: css.clone(),
+            shorthand: None,
+          },
+          important: declaration.important,
+        }),
+        _ => None,
+      };
+      match shorthand {
+        Some(shorthand) if declarations.last() == Some(&shorthand) => {}
+        Some(shorthand) => declarations.push(shorthand),
+        None => declarations.push(declaration.clone()),
+      }
+    }
+
+    self.write_block(&prelude, |s| {
+      for (i, declaration) in declarations.iter().enumerate() {
+        s.write_indent();
+        s.out.push_str(&display(declaration, s.minify));
+        // 压缩输出时省略最后一条声明的分号
+        if !s.minify || i + 1 < declarations.len() {
+          s.out.push(';');
+        }
+        s.write_newline();
+      }
+    });
+  }
+
+  // prelude { ... }，块中的内容缩进一级
+  fn write_block(&mut self, prelude: &str, write_body: impl FnOnce(&mut Serializer)) {
+    self.write_indent();
+    self.out.push_str(prelude);
+    self.out.push_str(if self.minify { "{" } else { " {" });
+    self.write_newline();
+
+    self.indent += 1;
+    write_body(self);
+    self.indent -= 1;
+
+    self.write_indent();
+    self.out.push('}');
+    self.write_newline();
+  }
+
+  fn write_indent(&mut self) {
+    if !self.minify {
+      self.out.push_str(&"  ".repeat(self.indent));
+    }
+  }
+
+  fn write_newline(&mut self) {
+    if !self.minify {
+      self.out.push('\n');
+    }
+  }
+}
+
+// 范围的两端相同时只输出一个值
+fn range<T: Display + PartialEq>(min: T, max: T, unit: &str) -> String {
+  if min == max {
+    format!("{}{}", min, unit)
+  } else {
+    format!("{}{} {}{}", min, unit, max, unit)
+  }
+}
+
+// 按是否压缩格式化
+fn display<T: Display>(value: &T, minify: bool) -> String {
+  if minify {
+    format!("{:#}", value)
+  } else {
+    value.to_string()
+  }
+}
+
+// 压缩输出时子选择器的 > 两边不留空格
+impl Display for Selector {
+  fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+    match self {
+      Selector::Simple(simple) => write!(f, "{}", simple),
+      Selector::Descendant(left, right) if f.alternate() => write!(f, "{:#} {}", left, right),
+      Selector::Descendant(left, right) => write!(f, "{} {}", left, right),
+      Selector::Child(left, right) if f.alternate() => write!(f, "{:#}>{}", left, right),
+      Selector::Child(left, right) => write!(f, "{} > {}", left, right),
+    }
+  }
+}
+
+impl Display for SimpleSelector {
+  fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+    let simple = self;
+    if simple.tag_name.is_none() && simple.id.is_none() && simple.class.is_empty() {
+      return f.write_str("*");
+    }
+
+    if let Some(tag_name) = &simple.tag_name {
+      f.write_str(tag_name)?;
+    }
+    if let Some(id) = &simple.id {
+      write!(f, "#{}", id)?;
+    }
+    for class in &simple.class {
+      write!(f, ".{}", class)?;
+    }
+    Ok(())
+  }
+}
+
+impl Display for Declaration {
+  fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+    let minify = f.alternate();
+    let separator = if minify { ":" } else { ": " };
+    write!(
+      f,
+      "{}{}{}",
+      self.name,
+      separator,
+      display(&self.value, minify)
+    )?;
+    if self.important {
+      f.write_str(if minify { "!important" } else { " !important" })?;
+    }
+    Ok(())
+  }
+}
+
+impl Display for Value {
+  fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+    let minify = f.alternate();
+    match self {
+      Value::Keyword(s) => f.write_str(s),
+      Value::Number(n) => f.write_str(&number(*n, minify)),
+      Value::Percentage(n) => write!(f, "{}%", number(*n, minify)),
+      Value::Length(n, unit) => {
+        // 压缩输出时 0 省略单位
+        if minify && *n == 0.0 {
+          f.write_str("0")
+        } else {
+          write!(f, "{}{}", number(*n, minify), unit.as_str())
+        }
+      }
+      Value::ColorValue(color) => f.write_str(&hex_color(color, minify)),
+      Value::Calc(node) => match **node {
+        CalcNode::Min(_) | CalcNode::Max(_) | CalcNode::Clamp(..) => {
+          f.write_str(&calc(node, minify))
+        }
+        _ => write!(f, "calc({})", calc(node, minify)),
+      },
+      Value::Resolution(dppx) => write!(f, "{}dppx", number(*dppx, minify)),
+      Value::Str(s) => write!(f, "\"{}\"", escape(s)),
+      Value::List(values) => {
+        let separator = if minify { "," } else { ", " };
+        let values: Vec<String> = values.iter().map(|v| display(v, minify)).collect();
+        f.write_str(&values.join(separator))
+      }
+      Value::Unparsed { css, .. } => f.write_str(css),
+    }
+  }
+}
+
+impl Unit {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      Unit::Px => "px",
+      Unit::Pt => "pt",
+      Unit::Pc => "pc",
+      Unit::Cm => "cm",
+      Unit::Mm => "mm",
+      Unit::Q => "q",
+      Unit::In => "in",
+      Unit::Em => "em",
+      Unit::Rem => "rem",
+      Unit::Ex => "ex",
+      Unit::Ch => "ch",
+      Unit::Vw => "vw",
+      Unit::Vh => "vh",
+      Unit::Vmin => "vmin",
+      Unit::Vmax => "vmax",
+    }
+  }
+}
+
+// 数字去掉多余的小数位，压缩输出时省略整数部分的 0，如 0.5 -> .5
+fn number(n: f32, minify: bool) -> String {
+  if n == 0.0 {
+    return "0".to_string();
+  }
+
+  let s = n.to_string();
+  if !minify {
+    return s;
+  }
+  if let Some(rest) = s.strip_prefix("0.") {
+    format!(".{}", rest)
+  } else if let Some(rest) = s.strip_prefix("-0.") {
+    format!("-.{}", rest)
+  } else {
+    s
+  }
+}
+
+// 不透明时输出 #rrggbb，否则输出 #rrggbbaa，压缩输出时尽量使用简写 #rgb、#rgba
+fn hex_color(color: &Color, minify: bool) -> String {
+  let mut components = vec![color.r, color.g, color.b];
+  if color.a != 255 {
+    components.push(color.a);
+  }
+
+  let short = minify && components.iter().all(|c| c >> 4 == c & 0xf);
+  let digits: Vec<String> = components
+    .iter()
+    .map(|c| {
+      if short {
+        format!("{:x}", c & 0xf)
+      } else {
+        format!("{:02x}", c)
+      }
+    })
+    .collect();
+  format!("#{}", digits.concat())
+}
+
+// 数学表达式，只在必要时加括号
+fn calc(node: &CalcNode, minify: bool) -> String {
+  let separator = if minify { "," } else { ", " };
+  let args = |nodes: &[&CalcNode]| {
+    let args: Vec<String> = nodes.iter().map(|node| calc(node, minify)).collect();
+    args.join(separator)
+  };
+
+  match node {
+    CalcNode::Number(n) => number(*n, minify),
+    CalcNode::Percentage(n) => format!("{}%", number(*n, minify)),
+    CalcNode::Length(n, unit) => format!("{}{}", number(*n, minify), unit.as_str()),
+    CalcNode::Sum(a, b) => format!("{} + {}", operand(a, 0, minify), operand(b, 1, minify)),
+    CalcNode::Difference(a, b) => format!("{} - {}", operand(a, 0, minify), operand(b, 1, minify)),
+    CalcNode::Product(a, b) => {
+      let op = if minify { "*" } else { " * " };
+      format!("{}{}{}", operand(a, 1, minify), op, operand(b, 2, minify))
+    }
+    CalcNode::Quotient(a, b) => {
+      let op = if minify { "/" } else { " / " };
+      format!("{}{}{}", operand(a, 1, minify), op, operand(b, 2, minify))
+    }
+    CalcNode::Min(nodes) => format!("min({})", args(&nodes.iter().collect::<Vec<_>>())),
+    CalcNode::Max(nodes) => format!("max({})", args(&nodes.iter().collect::<Vec<_>>())),
+    CalcNode::Clamp(min, value, max) => format!("clamp({})", args(&[min, value, max])),
+  }
+}
+
+// 运算优先级不高于 precedence 时加括号，如 (1px + 2px) * 3、1px - (2px + 3px)
+fn operand(node: &CalcNode, precedence: u8, minify: bool) -> String {
+  let own = match node {
+    CalcNode::Sum(..) | CalcNode::Difference(..) => 1,
+    CalcNode::Product(..) | CalcNode::Quotient(..) => 2,
+    _ => 3,
+  };
+  if own <= precedence {
+    format!("({})", calc(node, minify))
+  } else {
+    calc(node, minify)
+  }
+}
+
+// 转义字符串中的引号和反斜杠
+fn escape(s: &str) -> String {
+  s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+impl Display for MediaQueryList {
+  fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+    let separator = if f.alternate() { "," } else { ", " };
+    let queries: Vec<String> = self
+      .queries
+      .iter()
+      .map(|query| display(query, f.alternate()))
+      .collect();
+    f.write_str(&queries.join(separator))
+  }
+}
+
+impl Display for MediaQuery {
+  fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+    let mut parts = Vec::new();
+    if self.negated {
+      parts.push("not".to_string());
+    }
+    // 只有特性时省略 all
+    if self.media_type != MediaType::All || self.negated || self.features.is_empty() {
+      parts.push(
+        match &self.media_type {
+          MediaType::All => "all",
+          MediaType::Screen => "screen",
+          MediaType::Print => "print",
+          MediaType::Unknown(name) => name,
+        }
+        .to_string(),
+      );
+    }
+    for feature in &self.features {
+      if !parts.is_empty() {
+        parts.push("and".to_string());
+      }
+      parts.push(display(feature, f.alternate()));
+    }
+    f.write_str(&parts.join(" "))
+  }
+}
+
+impl Display for MediaFeature {
+  fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+    let minify = f.alternate();
+    match self {
+      MediaFeature::Boolean(name) => write!(f, "({})", name),
+      MediaFeature::Discrete(name, value) => {
+        write!(f, "({}{}{})", name, if minify { ":" } else { ": " }, value)
+      }
+      // 多个比较拆成以 and 连接的多个特性
+      MediaFeature::Range(name, comparisons) => {
+        let features: Vec<String> = comparisons
+          .iter()
+          .map(|(op, value)| {
+            let op = match op {
+              RangeOp::Lt => "<",
+              RangeOp::Le => "<=",
+              RangeOp::Eq => "=",
+              RangeOp::Ge => ">=",
+              RangeOp::Gt => ">",
+            };
+            if minify {
+              format!("({}{}{})", name, op, display(value, minify))
+            } else {
+              format!("({} {} {})", name, op, value)
+            }
+          })
+          .collect();
+        f.write_str(&features.join(" and "))
+      }
+    }
+  }
+}
+
+impl Display for SupportsFeature {
+  fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+    match self {
+      SupportsFeature::Declaration(name, value) => {
+        let separator = if f.alternate() { ":" } else { ": " };
+        write!(f, "({}{}{})", name, separator, value)
+      }
+      SupportsFeature::Unknown(text) => f.write_str(text),
+    }
+  }
+}
+
+impl<T: Display> Display for Condition<T> {
+  fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+    let minify = f.alternate();
+    // 嵌套的组合条件需要加括号
+    let in_parens = |condition: &Condition<T>| match condition {
+      Condition::Test(test) => display(test, minify),
+      condition => format!("({})", display(condition, minify)),
+    };
+
+    match self {
+      Condition::Test(test) => f.write_str(&display(test, minify)),
+      Condition::Not(condition) => write!(f, "not {}", in_parens(condition)),
+      Condition::And(conditions) | Condition::Or(conditions) => {
+        let combinator = match self {
+          Condition::And(_) => " and ",
+          _ => " or ",
+        };
+        let conditions: Vec<String> = conditions.iter().map(in_parens).collect();
+        f.write_str(&conditions.join(combinator))
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::css;
+
+  const SOURCE: &str = r#"
+    @import url("base.css") screen and (min-width: 400px);
+    @layer base, theme.dark;
+    @font-face { font-family: "My Font"; src: local("My Font"), url("my font.woff2") format("woff2"); font-weight: 100 900; }
+    div p, ul > li.item#first { margin: 0 auto; color: rgba(255, 0, 0, 0.5) !important; }
+    .box { width: calc(100% - 2 * 10px); --gap: 4px; padding: var(--gap, 2px); font-family: "Helvetica Neue", sans-serif; }
+    @media not print and (max-width: 600px) { @layer theme { * { color: #00ff00; } } }
+    @supports (display: flex) or (not (display: grid)) { .row { display: flex; } }
+    @container sidebar (min-width: 300px) { p { font-size: 1.5em; } }
+  "#;
+
+  // 序列化结果重新解析后应得到相同的规则
+  fn assert_round_trip(minify: bool) {
+    let sheet = css::parse(SOURCE.to_string());
+    assert!(sheet.diagnostics.is_empty(), "{:?}", sheet.diagnostics);
+    let text = to_css(&sheet, minify);
+    let reparsed = css::parse(text.clone());
+    assert!(
+      reparsed.diagnostics.is_empty(),
+      "{:?}",
+      reparsed.diagnostics
+    );
+    assert_eq!(
+      format!("{:?}", reparsed.rules),
+      format!("{:?}", sheet.rules),
+      "{}",
+      text
+    );
+    assert_eq!(to_css(&reparsed, minify), text);
+  }
+
+  #[test]
+  fn pretty_output_round_trips() {
+    assert_round_trip(false);
+  }
+
+  #[test]
+  fn minified_output_round_trips() {
+    assert_round_trip(true);
+  }
+
+  #[test]
+  fn formats_rules() {
+    let sheet = css::parse("@media screen { a, b { color: #ff0000; margin: 0 } }".to_string());
+    assert_eq!(
+      format!("{}", sheet),
+      "@media screen {\n  a, b {\n    color: #ff0000;\n    margin-top: 0;\n    margin-right: 0;\n    margin-bottom: 0;\n    margin-left: 0;\n  }\n}\n"
+    );
+    assert_eq!(
+      format!("{:#}", sheet),
+      "@media screen{a,b{color:#f00;margin-top:0;margin-right:0;margin-bottom:0;margin-left:0}}"
+    );
+  }
+}
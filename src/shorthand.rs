@@ -0,0 +1,344 @@
+use crate::css::{CalcType, Value};
+
+// 四个方向，顺序与 1~4 个值的简写规则一致
+const SIDES: [&str; 4] = ["top", "right", "bottom", "left"];
+
+const BORDER_STYLES: [&str; 10] = [
+  "none", "hidden", "dotted", "dashed", "solid", "double", "groove", "ridge", "inset", "outset",
+];
+
+const FONT_STRETCHES: [&str; 8] = [
+  "ultra-condensed",
+  "extra-condensed",
+  "condensed",
+  "semi-condensed",
+  "semi-expanded",
+  "expanded",
+  "extra-expanded",
+  "ultra-expanded",
+];
+
+const FONT_SIZES: [&str; 10] = [
+  "xx-small",
+  "x-small",
+  "small",
+  "medium",
+  "large",
+  "x-large",
+  "xx-large",
+  "xxx-large",
+  "smaller",
+  "larger",
+];
+
+// 简写属性对应的所有普通属性，不是简写属性时返回 None
+pub fn longhands(name: &str) -> Option<Vec<String>> {
+  let sides = |f: &dyn Fn(&str) -> String| SIDES.iter().map(|side| f(side)).collect();
+  let longhands = match name {
+    "margin" => sides(&|side| format!("margin-{}", side)),
+    "padding" => sides(&|side| format!("padding-{}", side)),
+    "inset" => sides(&|side| side.to_string()),
+    "border-width" => sides(&|side| format!("border-{}-width", side)),
+    "border-style" => sides(&|side| format!("border-{}-style", side)),
+    "border-color" => sides(&|side| format!("border-{}-color", side)),
+    "border-top" | "border-right" | "border-bottom" | "border-left" => vec![
+      format!("{}-width", name),
+      format!("{}-style", name),
+      format!("{}-color", name),
+    ],
+    "border" => SIDES
+      .iter()
+      .flat_map(|side| longhands(&format!("border-{}", side)).unwrap())
+      .collect(),
+    "background" => vec!["background-color".to_string()],
+    "font" => [
+      "font-style",
+      "font-variant",
+      "font-weight",
+      "font-stretch",
+      "font-size",
+      "line-height",
+      "font-family",
+    ]
+    .iter()
+    .map(|name| name.to_string())
+    .collect(),
+    "flex" => vec![
+      "flex-grow".to_string(),
+      "flex-shrink".to_string(),
+      "flex-basis".to_string(),
+    ],
+    _ => return None,
+  };
+  Some(longhands)
+}
+
+// 将简写属性展开为普通属性，groups 为逗号分隔的多组值，每组由空白分隔
+// 简写中省略的部分取初始值，不是简写属性或值不符合简写的语法时返回 None
+pub fn expand(name: &str, groups: &[Vec<Value>]) -> Option<Vec<(String, Value)>> {
+  let longhands = longhands(name)?;
+
+  // inherit、initial 等关键字作用于所有普通属性
+  if let [group] = groups {
+    if let [Value::Keyword(keyword)] = &group[..] {
+      if is_css_wide_keyword(keyword) {
+        let value = Value::Keyword(keyword.clone());
+        return Some(
+          longhands
+            .into_iter()
+            .map(|name| (name, value.clone()))
+            .collect(),
+        );
+      }
+    }
+  }
+
+  let values = match name {
+    "margin" | "inset" => four_sides(single_group(groups)?, |v| {
+      is_length_percentage(v) || is_keyword(v, &["auto"])
+    })?,
+    "padding" => four_sides(single_group(groups)?, is_length_percentage)?,
+    "border-width" => four_sides(single_group(groups)?, is_border_width)?,
+    "border-style" => four_sides(single_group(groups)?, |v| is_keyword(v, &BORDER_STYLES))?,
+    "border-color" => four_sides(single_group(groups)?, is_color)?,
+    "border-top" | "border-right" | "border-bottom" | "border-left" => {
+      border_side(single_group(groups)?)?
+    }
+    "border" => {
+      let side = border_side(single_group(groups)?)?;
+      SIDES.iter().flat_map(|_| side.clone()).collect()
+    }
+    "background" => vec![background(groups)],
+    "font" => font(groups)?,
+    "flex" => flex(single_group(groups)?)?,
+    _ => unreachable!(),
+  };
+
+  Some(longhands.into_iter().zip(values).collect())
+}
+
+pub fn is_css_wide_keyword(keyword: &str) -> bool {
+  ["inherit", "initial", "unset", "revert", "revert-layer"]
+    .iter()
+    .any(|k| keyword.eq_ignore_ascii_case(k))
+}
+
+// 不接受逗号分隔的多组值
+fn single_group(groups: &[Vec<Value>]) -> Option<&[Value]> {
+  match groups {
+    [group] => Some(group),
+    _ => None,
+  }
+}
+
+fn is_keyword(value: &Value, keywords: &[&str]) -> bool {
+  match value {
+    Value::Keyword(s) => keywords.iter().any(|k| s.eq_ignore_ascii_case(k)),
+    _ => false,
+  }
+}
+
+fn keyword(s: &str) -> Value {
+  Value::Keyword(s.to_string())
+}
+
+// 长度，无单位的数字只接受 0
+fn is_length(value: &Value) -> bool {
+  match value {
+    Value::Length(..) => true,
+    Value::Number(f) => *f == 0.0,
+    Value::Calc(node) => node.calc_type() == Some(CalcType::Length),
+    _ => false,
+  }
+}
+
+// 长度或百分比
+fn is_length_percentage(value: &Value) -> bool {
+  match value {
+    Value::Percentage(_) => true,
+    Value::Calc(node) => !matches!(node.calc_type(), Some(CalcType::Number) | None),
+    v => is_length(v),
+  }
+}
+
+fn is_border_width(value: &Value) -> bool {
+  is_length(value) || is_keyword(value, &["thin", "medium", "thick"])
+}
+
+fn is_color(value: &Value) -> bool {
+  matches!(value, Value::ColorValue(_)) || is_keyword(value, &["currentcolor"])
+}
+
+// 1 个值：四边相同；2 个值：上下、左右；3 个值：上、左右、下；4 个值：上、右、下、左
+// 任意一个值不符合 valid 时返回 None
+fn four_sides(values: &[Value], valid: fn(&Value) -> bool) -> Option<Vec<Value>> {
+  if !values.iter().all(valid) {
+    return None;
+  }
+  let indices: &[usize] = match values.len() {
+    1 => &[0, 0, 0, 0],
+    2 => &[0, 1, 0, 1],
+    3 => &[0, 1, 2, 1],
+    4 => &[0, 1, 2, 3],
+    _ => return None,
+  };
+  Some(indices.iter().map(|&i| values[i].clone()).collect())
+}
+
+// border、border-top 等，宽度、样式、颜色顺序任意
+// 同一部分出现两次或有无法识别的值时返回 None
+fn border_side(values: &[Value]) -> Option<Vec<Value>> {
+  let mut width = None;
+  let mut style = None;
+  let mut color = None;
+
+  for value in values {
+    let slot = match value {
+      v if is_color(v) => &mut color,
+      v if is_keyword(v, &BORDER_STYLES) => &mut style,
+      v if is_border_width(v) => &mut width,
+      _ => return None,
+    };
+    if slot.replace(value.clone()).is_some() {
+      return None;
+    }
+  }
+
+  Some(vec![
+    width.unwrap_or_else(|| keyword("medium")),
+    style.unwrap_or_else(|| keyword("none")),
+    color.unwrap_or_else(|| keyword("currentcolor")),
+  ])
+}
+
+// 只支持背景色，其余部分如 none、no-repeat 忽略，多层背景时颜色在最后一层
+fn background(groups: &[Vec<Value>]) -> Value {
+  groups
+    .last()
+    .and_then(|layer| {
+      layer
+        .iter()
+        .find(|value| matches!(value, Value::ColorValue(_)) || is_keyword(value, &["currentcolor"]))
+    })
+    .cloned()
+    .unwrap_or_else(|| keyword("transparent"))
+}
+
+// [style || variant || weight || stretch]? size [/ line-height]? family
+fn font(groups: &[Vec<Value>]) -> Option<Vec<Value>> {
+  let first = &groups[0];
+  let mut style = None;
+  let mut variant = None;
+  let mut weight = None;
+  let mut stretch = None;
+
+  let mut i = 0;
+  while i < first.len() {
+    let value = &first[i];
+    let slot = match value {
+      // normal 可以对应任意一项，各项缺省时本就是 normal，直接跳过
+      v if is_keyword(v, &["normal"]) => {
+        i += 1;
+        continue;
+      }
+      v if is_keyword(v, &["italic", "oblique"]) => &mut style,
+      v if is_keyword(v, &["small-caps"]) => &mut variant,
+      v if is_keyword(v, &["bold", "bolder", "lighter"]) => &mut weight,
+      Value::Number(f) if (1.0..=1000.0).contains(f) => &mut weight,
+      v if is_keyword(v, &FONT_STRETCHES) => &mut stretch,
+      _ => break,
+    };
+    *slot = Some(value.clone());
+    i += 1;
+  }
+
+  let size = first.get(i).cloned()?;
+  if !is_length_percentage(&size) && !is_keyword(&size, &FONT_SIZES) {
+    return None;
+  }
+  i += 1;
+
+  let mut line_height = None;
+  if first.get(i).is_some_and(|value| is_keyword(value, &["/"])) {
+    let value = first.get(i + 1).cloned()?;
+    let valid = match &value {
+      Value::Number(f) => *f >= 0.0,
+      Value::Calc(_) => true,
+      v => is_length_percentage(v) || is_keyword(v, &["normal"]),
+    };
+    if !valid {
+      return None;
+    }
+    line_height = Some(value);
+    i += 2;
+  }
+
+  let mut families = vec![family_name(first.get(i..)?)?];
+  for group in &groups[1..] {
+    families.push(family_name(group)?);
+  }
+
+  Some(vec![
+    style.unwrap_or_else(|| keyword("normal")),
+    variant.unwrap_or_else(|| keyword("normal")),
+    weight.unwrap_or_else(|| keyword("normal")),
+    stretch.unwrap_or_else(|| keyword("normal")),
+    size,
+    line_height.unwrap_or_else(|| keyword("normal")),
+    Value::List(families),
+  ])
+}
+
+// 字体名，带引号的字符串，或多个标识符以空格连接，如 Times New Roman
+pub fn family_name(values: &[Value]) -> Option<Value> {
+  match values {
+    [Value::Str(s)] => Some(Value::Str(s.clone())),
+    [] => None,
+    _ => {
+      let words = values
+        .iter()
+        .map(|value| match value {
+          Value::Keyword(s) => Some(s.clone()),
+          _ => None,
+        })
+        .collect::<Option<Vec<String>>>()?;
+      Some(Value::Keyword(words.join(" ")))
+    }
+  }
+}
+
+// flex: none | auto | <grow> <shrink>? || <basis>
+// grow、shrink 为非负数，basis 为 auto、content 或长度、百分比
+fn flex(values: &[Value]) -> Option<Vec<Value>> {
+  let zero_percent = Value::Percentage(0.0);
+  let values = match values {
+    [v] if is_keyword(v, &["none"]) => {
+      vec![Value::Number(0.0), Value::Number(0.0), keyword("auto")]
+    }
+    [v] if is_keyword(v, &["auto"]) => {
+      vec![Value::Number(1.0), Value::Number(1.0), keyword("auto")]
+    }
+    [Value::Number(grow)] => vec![Value::Number(*grow), Value::Number(1.0), zero_percent],
+    [basis] => vec![Value::Number(1.0), Value::Number(1.0), basis.clone()],
+    [Value::Number(grow), Value::Number(shrink)] => {
+      vec![Value::Number(*grow), Value::Number(*shrink), zero_percent]
+    }
+    [Value::Number(grow), basis] | [basis, Value::Number(grow)] => {
+      vec![Value::Number(*grow), Value::Number(1.0), basis.clone()]
+    }
+    [Value::Number(grow), Value::Number(shrink), basis] => {
+      vec![Value::Number(*grow), Value::Number(*shrink), basis.clone()]
+    }
+    _ => return None,
+  };
+
+  let valid = match &values[..] {
+    [Value::Number(grow), Value::Number(shrink), basis] => {
+      *grow >= 0.0
+        && *shrink >= 0.0
+        && (is_length_percentage(basis) || is_keyword(basis, &["auto", "content"]))
+    }
+    _ => false,
+  };
+  valid.then_some(values)
+}
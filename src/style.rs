@@ -1,27 +1,70 @@
-use crate::css::{Rule, Selector, SimpleSelector, Specificity, StyleSheet, Value};
-use crate::dom::{ElementData, Node, NodeType};
+use crate::computed::{self, ComputedStyle, ContainerType, FontUnits, MEDIUM_FONT_SIZE};
+use crate::css;
+use crate::css::{
+  ContainerRule, CssRule, Declaration, LayerRule, Rule, Selector, SimpleSelector, Specificity,
+  StyleSheet, Value,
+};
+use crate::dom::{Damage, ElementData, Node, NodeType};
+use crate::font::FontContext;
+use crate::invalidation::{self, InvalidationSet};
+use crate::layer::{LayerTree, ROOT_LAYER};
+use crate::layout::Rect;
+use crate::media::MediaEnvironment;
+use crate::properties;
+use crate::rule_index::{AncestorFilter, CollectedRule, RuleIndex};
+use crate::style_sharing::{SharedStyle, SharingStats, StyleSharingCache};
+use crate::variables;
+use rayon::prelude::*;
 use std::collections::HashMap;
+use std::sync::{Arc, OnceLock};
 
-type PropertyMap = HashMap<String, Value>;
+pub type PropertyMap = HashMap<String, Value>;
 
 #[derive(Debug)]
+// 样式可能与其他节点共享
 pub struct StyleNode<'a> {
   pub node: &'a Node,
-  pub specified_values: PropertyMap,
+  pub specified_values: Arc<PropertyMap>,
+  pub computed_style: Arc<ComputedStyle>,
   pub children: Vec<StyleNode<'a>>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Display {
   Inline,
   Block,
+  ListItem,
+  Table,
+  TableCaption,
+  TableColumnGroup,
+  TableColumn,
+  TableHeaderGroup,
+  TableRowGroup,
+  TableFooterGroup,
+  TableRow,
+  TableCell,
   None,
 }
 
-// 节点与选择器是否匹配
-fn matches(elem: &ElementData, selector: &Selector) -> bool {
+// 节点与选择器是否匹配，ancestors 为从根节点到父节点的元素
+pub fn matches(elem: &ElementData, ancestors: &[&ElementData], selector: &Selector) -> bool {
   match *selector {
     Selector::Simple(ref simple_selector) => match_simple_selector(elem, simple_selector),
+    // 任意一个祖先与左边匹配即可
+    Selector::Descendant(ref left, ref right) => {
+      match_simple_selector(elem, right)
+        && (0..ancestors.len())
+          .rev()
+          .any(|i| matches(ancestors[i], &ancestors[..i], left))
+    }
+    // 父节点与左边匹配
+    Selector::Child(ref left, ref right) => {
+      match_simple_selector(elem, right)
+        && match ancestors.split_last() {
+          Some((parent, rest)) => matches(parent, rest, left),
+          None => false,
+        }
+    }
   }
 }
 
@@ -46,7 +89,7 @@ fn match_simple_selector(elem: &ElementData, selector: &SimpleSelector) -> bool
     return false;
   }
 
-  return true;
+  true
 }
 
 impl<'a> StyleNode<'a> {
@@ -54,77 +97,1068 @@ impl<'a> StyleNode<'a> {
     self.specified_values.get(name).cloned()
   }
 
-  // 先查找 name 的值，不存在，则查找 fallback_name 的值，若仍然不存在，则返回 default
-  pub fn lookup(&self, name: &str, fallback_name: &str, default: &Value) -> Value {
-    self
-      .value(name)
-      .unwrap_or_else(|| self.value(fallback_name).unwrap_or_else(|| default.clone()))
+  pub fn display(&self) -> Display {
+    self.computed_style.display
   }
 
-  // 根据 display 的值返回对应类型
-  pub fn display(&self) -> Display {
-    match self.value("display") {
-      Some(Value::Keyword(s)) => match &*s {
-        "block" => Display::Block,
-        "none" => Display::None,
-        _ => Display::Inline,
-      },
-      _ => Display::Inline,
+  // 查找 dom 节点对应的样式节点
+  pub fn find(&self, node: &Node) -> Option<&StyleNode<'a>> {
+    if std::ptr::eq(self.node, node) {
+      return Some(self);
     }
+    self.children.iter().find_map(|child| child.find(node))
   }
 }
 
-type MatchedRule<'a> = (Specificity, &'a Rule);
+// 样式来源，按层叠中普通声明的优先级从低到高排列
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Origin {
+  UserAgent,
+  User,
+  Author,
+}
 
-// 检查节点是否满足样式规则，返回规则
-fn match_rule<'a>(elem: &ElementData, rule: &'a Rule) -> Option<MatchedRule<'a>> {
-  rule
-    .selectors
-    .iter()
-    .find(|selector| matches(elem, selector))
-    .map(|selector| (selector.specificity(), rule))
+// 层叠顺序，依次比较：来源与重要性、级联层、选择器优先级、出现顺序
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct CascadeOrder {
+  pub level: u8,
+  pub layer: usize,
+  pub specificity: Specificity,
+  pub source_order: usize,
+}
+
+// 匹配上的单条声明，携带参与层叠比较所需的信息
+#[derive(Debug)]
+pub struct MatchedDeclaration<'a> {
+  pub origin: Origin,
+  pub important: bool,
+  // 所在级联层的优先级
+  pub layer: usize,
+  pub specificity: Specificity,
+  pub source_order: usize,
+  pub declaration: &'a Declaration,
+}
+
+impl<'a> MatchedDeclaration<'a> {
+  pub fn cascade_order(&self) -> CascadeOrder {
+    // !important 声明中，层的优先级反过来，不属于任何层的声明最低
+    let layer = if self.important {
+      usize::MAX - self.layer
+    } else {
+      self.layer
+    };
+    CascadeOrder {
+      level: cascade_level(self.origin, self.important),
+      layer,
+      specificity: self.specificity,
+      source_order: self.source_order,
+    }
+  }
 }
 
-// 计算出满足节点的所有样式
-fn matching_rules<'a>(elem: &ElementData, stylesheet: &'a StyleSheet) -> Vec<MatchedRule<'a>> {
-  stylesheet
+// 来源与重要性的优先级，普通声明 UA < User < Author，!important 声明则反过来且高于所有普通声明
+fn cascade_level(origin: Origin, important: bool) -> u8 {
+  match (important, origin) {
+    (false, Origin::UserAgent) => 0,
+    (false, Origin::User) => 1,
+    (false, Origin::Author) => 2,
+    (true, Origin::Author) => 3,
+    (true, Origin::User) => 4,
+    (true, Origin::UserAgent) => 5,
+  }
+}
+
+// 祖先中的查询容器，size 为上一次布局得到的内容区宽高，首次布局前未知
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryContainer {
+  pub names: Vec<String>,
+  pub container_type: ContainerType,
+  pub size: Option<(f32, f32)>,
+}
+
+// 上一次布局中容器元素的内容区宽高，以节点的 node_id 为键
+pub type ContainerSizes = HashMap<usize, (f32, f32)>;
+
+// 收集当前环境下生效的样式规则，展开条件满足的 @media、@import、@supports 规则
+// 同时记录每条规则所在的级联层，以及依赖于元素所在容器的 @container 规则
+struct RuleCollector<'a, 'b> {
+  env: &'b MediaEnvironment,
+  layers: LayerTree,
+  containers: Vec<&'a ContainerRule>,
+  rules: Vec<(&'a Rule, usize, Vec<&'a ContainerRule>)>,
+}
+
+impl<'a, 'b> RuleCollector<'a, 'b> {
+  fn collect(&mut self, rules: &'a [CssRule], layer: usize) {
+    for rule in rules {
+      match rule {
+        CssRule::Style(rule) => self.rules.push((rule, layer, self.containers.clone())),
+        CssRule::Media(media) => {
+          if media.queries.matches(self.env) {
+            self.collect(&media.rules, layer);
+          }
+        }
+        CssRule::Import(import) => {
+          if import.queries.matches(self.env) {
+            self.collect(&import.rules, layer);
+          }
+        }
+        CssRule::Supports(supports) => {
+          if supports
+            .condition
+            .evaluate(&|feature| feature.is_supported())
+          {
+            self.collect(&supports.rules, layer);
+          }
+        }
+        CssRule::Container(container) => {
+          self.containers.push(container);
+          self.collect(&container.rules, layer);
+          self.containers.pop();
+        }
+        CssRule::Layer(LayerRule { names, rules: None }) => {
+          for name in names {
+            self.layers.declare(layer, name);
+          }
+        }
+        CssRule::Layer(LayerRule {
+          names,
+          rules: Some(rules),
+        }) => {
+          let sublayer = match names.first() {
+            Some(name) => self.layers.declare(layer, name),
+            None => self.layers.anonymous(layer),
+          };
+          self.collect(rules, sublayer);
+        }
+        CssRule::FontFace(_) => {}
+      }
+    }
+  }
+}
+
+// 在最近的同名容器上计算 @container 条件，inline-size 容器只能查询宽度
+fn container_matches(rule: &ContainerRule, containers: &[QueryContainer]) -> bool {
+  let container = containers.iter().rev().find(|container| {
+    rule
+      .name
+      .as_ref()
+      .is_none_or(|name| container.names.contains(name))
+  });
+
+  match container {
+    Some(QueryContainer {
+      container_type,
+      size: Some((width, height)),
+      ..
+    }) => {
+      let height = match container_type {
+        ContainerType::Size => Some(*height),
+        _ => None,
+      };
+      rule
+        .condition
+        .evaluate(&|feature| feature.matches_container(*width, height))
+    }
+    _ => false,
+  }
+}
+
+// 为样式表中生效的规则建立索引，source_order 为声明在样式表中的全局序号
+fn build_rule_index<'a>(stylesheet: &'a StyleSheet, env: &MediaEnvironment) -> RuleIndex<'a> {
+  let mut collector = RuleCollector {
+    env,
+    layers: LayerTree::new(),
+    containers: Vec::new(),
+    rules: Vec::new(),
+  };
+  collector.collect(&stylesheet.rules, ROOT_LAYER);
+  let ranks = collector.layers.ranks();
+
+  let mut source_order = 0;
+  let rules = collector
     .rules
-    .iter()
-    .filter_map(|rule| match_rule(elem, rule))
+    .into_iter()
+    .map(|(rule, layer, containers)| {
+      let collected = CollectedRule {
+        rule,
+        layer: ranks[layer],
+        source_order,
+        containers,
+      };
+      source_order += rule.declarations.len();
+      collected
+    })
+    .collect();
+
+  RuleIndex::new(rules)
+}
+
+// 计算出满足节点的所有声明
+fn matching_declarations<'a>(
+  elem: &ElementData,
+  index: &RuleIndex<'a>,
+  origin: Origin,
+  traversal: &StyleTraversal,
+) -> Vec<MatchedDeclaration<'a>> {
+  // 不使用索引时逐个检查所有选择器，用于对比性能
+  let use_rule_index = traversal.context.use_rule_index;
+  let candidates = if use_rule_index {
+    index.candidates(elem)
+  } else {
+    (0..index.selectors.len()).collect()
+  };
+
+  // 每条规则取匹配的选择器中优先级最高者，同一规则的选择器在索引中是相邻的
+  let mut matched_rules: Vec<(usize, Specificity)> = Vec::new();
+  for id in candidates {
+    let indexed = &index.selectors[id];
+    if use_rule_index && !traversal.filter.may_contain_all(&indexed.ancestor_hashes) {
+      continue;
+    }
+    if !matches(elem, &traversal.ancestors, indexed.selector) {
+      continue;
+    }
+    match matched_rules.last_mut() {
+      Some((rule, specificity)) if *rule == indexed.rule => {
+        *specificity = (*specificity).max(indexed.specificity);
+      }
+      _ => matched_rules.push((indexed.rule, indexed.specificity)),
+    }
+  }
+
+  let mut matched = Vec::new();
+  for (rule, specificity) in matched_rules {
+    let collected = &index.rules[rule];
+    if !collected
+      .containers
+      .iter()
+      .all(|container| container_matches(container, &traversal.containers))
+    {
+      continue;
+    }
+    for (i, declaration) in collected.rule.declarations.iter().enumerate() {
+      matched.push(MatchedDeclaration {
+        origin,
+        important: declaration.important,
+        layer: collected.layer,
+        specificity,
+        source_order: collected.source_order + i,
+        declaration,
+      });
+    }
+  }
+
+  matched
+}
+
+// 内置的用户代理样式表，对应 HTML 规范中的默认渲染规则
+static USER_AGENT_CSS: &str = include_str!("ua.css");
+
+pub fn user_agent_stylesheet() -> &'static StyleSheet {
+  static SHEET: OnceLock<StyleSheet> = OnceLock::new();
+  SHEET.get_or_init(|| css::parse(USER_AGENT_CSS.to_string()))
+}
+
+// 参与层叠的样式表，默认在最前面加入内置的用户代理样式表
+// viewport 用于解析 vw、vh 等视口单位，media 用于计算媒体查询
+pub struct StyleContext<'a> {
+  pub use_user_agent_stylesheet: bool,
+  pub user: Option<&'a StyleSheet>,
+  pub author: &'a StyleSheet,
+  pub viewport: Rect,
+  pub media: MediaEnvironment,
+  pub container_sizes: ContainerSizes,
+  // 用于解析 ex、ch 的字体，没有时按近似值解析
+  pub fonts: Option<&'a FontContext>,
+  // 是否使用规则索引和祖先过滤器加速选择器匹配，关闭时逐条匹配所有规则
+  pub use_rule_index: bool,
+  // 是否在父节点样式和属性相同的节点之间共享样式
+  pub use_style_sharing: bool,
+  // 是否在线程池中并行计算兄弟子树的样式，结果与串行计算完全一致
+  pub use_parallel_traversal: bool,
+}
+
+impl<'a> StyleContext<'a> {
+  pub fn new(author: &'a StyleSheet, viewport: Rect, media: MediaEnvironment) -> StyleContext<'a> {
+    StyleContext {
+      use_user_agent_stylesheet: true,
+      user: None,
+      author,
+      viewport,
+      media,
+      container_sizes: HashMap::new(),
+      fonts: None,
+      use_rule_index: true,
+      use_style_sharing: true,
+      use_parallel_traversal: false,
+    }
+  }
+
+  // 按来源列出所有样式表
+  fn stylesheets(&self) -> Vec<(Origin, &'a StyleSheet)> {
+    let mut sheets = Vec::new();
+    if self.use_user_agent_stylesheet {
+      sheets.push((Origin::UserAgent, user_agent_stylesheet()));
+    }
+    if let Some(user) = self.user {
+      sheets.push((Origin::User, user));
+    }
+    sheets.push((Origin::Author, self.author));
+    sheets
+  }
+}
+
+// 遍历样式树时的状态，规则索引在遍历开始时建立一次，所有线程共享
+struct StyleTraversal<'a, 'b> {
+  context: &'b StyleContext<'a>,
+  indexes: &'b [(Origin, RuleIndex<'a>)],
+  // 并行遍历时每个节点的子树大小，用于划分任务
+  subtree_sizes: &'b HashMap<usize, usize>,
+  // 并行遍历时每个任务至少包含的节点数，为 0 时串行遍历
+  grain: usize,
+  // 从根节点到父节点的元素
+  ancestors: Vec<&'b ElementData>,
+  filter: AncestorFilter,
+  // 祖先中的查询容器，越近越靠后
+  containers: Vec<QueryContainer>,
+  sharing: StyleSharingCache<'b>,
+}
+
+impl<'a, 'b> StyleTraversal<'a, 'b> {
+  fn new(
+    context: &'b StyleContext<'a>,
+    indexes: &'b [(Origin, RuleIndex<'a>)],
+    subtree_sizes: &'b HashMap<usize, usize>,
+    grain: usize,
+  ) -> StyleTraversal<'a, 'b> {
+    StyleTraversal {
+      context,
+      indexes,
+      subtree_sizes,
+      grain,
+      ancestors: Vec::new(),
+      filter: AncestorFilter::new(),
+      containers: Vec::new(),
+      sharing: StyleSharingCache::new(),
+    }
+  }
+
+  // 进入节点，子孙节点以它为祖先，返回节点是否为查询容器
+  fn enter(&mut self, node_type: &'b NodeType, node_id: usize, style: &ComputedStyle) -> bool {
+    // 当前节点是查询容器时，子孙节点的 @container 规则以它为准
+    let is_container = style.container_type != ContainerType::Normal;
+    if is_container {
+      self.containers.push(QueryContainer {
+        names: style.container_name.clone(),
+        container_type: style.container_type,
+        size: self.context.container_sizes.get(&node_id).copied(),
+      });
+    }
+    if let NodeType::Element(elem) = node_type {
+      self.ancestors.push(elem);
+      self.filter.push(elem);
+    }
+    is_container
+  }
+
+  fn leave(&mut self, node_type: &NodeType, is_container: bool) {
+    if let NodeType::Element(_) = node_type {
+      self.ancestors.pop();
+      self.filter.pop();
+    }
+    if is_container {
+      self.containers.pop();
+    }
+  }
+
+  // 复制祖先信息，用于在其他线程中遍历子树，样式共享缓存不跨线程
+  fn fork(&self) -> StyleTraversal<'a, 'b> {
+    StyleTraversal {
+      context: self.context,
+      indexes: self.indexes,
+      subtree_sizes: self.subtree_sizes,
+      grain: self.grain,
+      ancestors: self.ancestors.clone(),
+      filter: self.filter.clone(),
+      containers: self.containers.clone(),
+      sharing: StyleSharingCache::new(),
+    }
+  }
+}
+
+// 按来源为每个样式表建立规则索引
+fn rule_indexes<'a>(context: &StyleContext<'a>) -> Vec<(Origin, RuleIndex<'a>)> {
+  context
+    .stylesheets()
+    .into_iter()
+    .map(|(origin, stylesheet)| (origin, build_rule_index(stylesheet, &context.media)))
     .collect()
 }
 
-// 将样式转换为 map
-fn specified_values<'a>(elem: &ElementData, stylesheet: &'a StyleSheet) -> PropertyMap {
+// 记录每个节点的子树大小，包括节点本身，以节点的 node_id 为键
+fn count_subtree(node: &Node, sizes: &mut HashMap<usize, usize>) -> usize {
+  let size = 1
+    + node
+      .children
+      .iter()
+      .map(|child| count_subtree(child, sizes))
+      .sum::<usize>();
+  sizes.insert(node.node_id(), size);
+  size
+}
+
+// 并行遍历时每个任务至少包含的节点数，任务太小时调度的开销超过计算本身
+const PARALLEL_GRAIN: usize = 256;
+
+// 每个线程分到的任务数，任务过多时复制祖先信息、样式共享缓存从空开始的开销会抵消并行的收益
+const TASKS_PER_THREAD: usize = 4;
+
+// 按子树大小把子节点依次分组，每组在一个线程中按顺序遍历，只有一组时不需要并行
+// 相邻的小子树分在同一组，组内仍能共享样式
+fn parallel_chunks<'a>(node: &'a Node, traversal: &StyleTraversal) -> Option<Vec<&'a [Node]>> {
+  // 子树不足两个任务时不再划分
+  let grain = traversal.grain;
+  if grain == 0 || traversal.subtree_sizes[&node.node_id()] < grain * 2 {
+    return None;
+  }
+
+  let mut chunks = Vec::new();
+  let mut start = 0;
+  let mut weight = 0;
+  for (i, child) in node.children.iter().enumerate() {
+    weight += traversal.subtree_sizes[&child.node_id()];
+    if weight >= grain {
+      chunks.push(&node.children[start..=i]);
+      start = i + 1;
+      weight = 0;
+    }
+  }
+  if start < node.children.len() {
+    chunks.push(&node.children[start..]);
+  }
+
+  if chunks.len() > 1 {
+    Some(chunks)
+  } else {
+    None
+  }
+}
+
+// 层叠，得到每个属性胜出的声明值
+fn cascaded_values(elem: &ElementData, traversal: &StyleTraversal) -> PropertyMap {
+  let mut declarations: Vec<MatchedDeclaration> = traversal
+    .indexes
+    .iter()
+    .flat_map(|(origin, index)| matching_declarations(elem, index, *origin, traversal))
+    .collect();
+
+  // 从低优先级 -> 高优先级排序，优先级相同时，出现顺序靠后的声明胜出
+  declarations.sort_by_key(|matched| matched.cascade_order());
+
+  let mut by_name: HashMap<&str, Vec<&MatchedDeclaration>> = HashMap::new();
+  for matched in &declarations {
+    by_name
+      .entry(&matched.declaration.name)
+      .or_default()
+      .push(matched);
+  }
+
+  let mut values = HashMap::new();
+  for (name, candidates) in by_name {
+    // 从高优先级往低查找，遇到 revert 时丢弃该来源及更高来源的声明，
+    // 遇到 revert-layer 时丢弃同一来源、同一级联层中的其余声明
+    let mut limit: Option<Origin> = None;
+    let mut reverted_layer: Option<(u8, usize)> = None;
+    for matched in candidates.iter().rev() {
+      if limit.is_some_and(|origin| matched.origin >= origin) {
+        continue;
+      }
+      let order = matched.cascade_order();
+      if reverted_layer == Some((order.level, order.layer)) {
+        continue;
+      }
+
+      let value = &matched.declaration.value;
+      if is_keyword(value, "revert") {
+        limit = Some(matched.origin);
+        continue;
+      }
+      if is_keyword(value, "revert-layer") {
+        reverted_layer = Some((order.level, order.layer));
+        continue;
+      }
+
+      values.insert(name.to_string(), value.clone());
+      break;
+    }
+  }
+
+  values
+}
+
+fn is_keyword(value: &Value, keyword: &str) -> bool {
+  match value {
+    Value::Keyword(s) => s.eq_ignore_ascii_case(keyword),
+    _ => false,
+  }
+}
+
+// 从父节点继承所有可继承属性，包括自定义属性
+fn inherited_values(parent: Option<&PropertyMap>) -> PropertyMap {
   let mut values = HashMap::new();
-  let mut rules = matching_rules(elem, stylesheet);
-  println!("specified_values, {:?}", rules);
+  if let Some(parent) = parent {
+    for property in properties::inherited_properties() {
+      if let Some(value) = parent.get(property.name) {
+        values.insert(property.name.to_string(), value.clone());
+      }
+    }
+    for (name, value) in parent {
+      if css::is_custom_property(name) {
+        values.insert(name.clone(), value.clone());
+      }
+    }
+  }
+  values
+}
 
-  // [((1,0,0), rule1), ((0,1,1), rule2)] -> [((0,1,1), rule2), ((1,0,0), rule1)]
-  // 从低优先级 -> 高优先级排序，这样在放入 map 时，高优先级会覆盖低优先级
-  rules.sort_by(|&(a, _), &(b, _)| a.cmp(&b));
+// 将属性设置为父节点的值，父节点没有时取初始值
+fn inherit(values: &mut PropertyMap, name: &str, parent: Option<&PropertyMap>) {
+  match parent.and_then(|parent| parent.get(name)) {
+    Some(value) => {
+      values.insert(name.to_string(), value.clone());
+    }
+    None => reset_to_initial(values, name),
+  }
+}
 
-  for (_, rule) in rules {
-    for declaration in &rule.declarations {
-      values.insert(declaration.name.clone(), declaration.value.clone());
+// 将属性设置为初始值，未注册的属性直接移除
+fn reset_to_initial(values: &mut PropertyMap, name: &str) {
+  match properties::initial_value(name) {
+    Some(value) => {
+      values.insert(name.to_string(), value);
+    }
+    None => {
+      values.remove(name);
     }
   }
+}
 
-  return values;
+// 可继承属性取父节点的值，否则取初始值
+fn unset(values: &mut PropertyMap, name: &str, parent: Option<&PropertyMap>) {
+  if properties::is_inherited(name) {
+    inherit(values, name, parent);
+  } else {
+    reset_to_initial(values, name);
+  }
+}
+
+// 层叠后处理 inherit、initial、unset 关键字，未设置的可继承属性取父节点的值
+// 未设置的不可继承属性不放入 map，即为初始值
+fn specified_values(
+  elem: &ElementData,
+  traversal: &StyleTraversal,
+  parent: Option<&PropertyMap>,
+) -> PropertyMap {
+  let mut values = inherited_values(parent);
+
+  for (name, value) in cascaded_values(elem, traversal) {
+    if is_keyword(&value, "inherit") {
+      inherit(&mut values, &name, parent);
+    } else if is_keyword(&value, "initial") {
+      reset_to_initial(&mut values, &name);
+    } else if is_keyword(&value, "unset") {
+      unset(&mut values, &name, parent);
+    } else {
+      values.insert(name, value);
+    }
+  }
+
+  values
 }
 
 // 生成样式树
-pub fn style_tree<'a>(root: &'a Node, stylesheet: &'a StyleSheet) -> StyleNode<'a> {
-  StyleNode {
-    node: root,
-    specified_values: match root.node_type {
-      NodeType::Element(ref elem) => specified_values(elem, stylesheet),
-      NodeType::Text(_) => HashMap::new(),
-    },
-    children: root
+pub fn style_tree<'a>(root: &'a Node, context: &StyleContext) -> StyleNode<'a> {
+  style_tree_with_stats(root, context).0
+}
+
+// 生成样式树，同时返回样式共享缓存的命中情况
+pub fn style_tree_with_stats<'a>(
+  root: &'a Node,
+  context: &StyleContext,
+) -> (StyleNode<'a>, SharingStats) {
+  let indexes = rule_indexes(context);
+  let mut subtree_sizes = HashMap::new();
+  let mut grain = 0;
+  // 只有一个线程时并行只有额外开销，按串行遍历
+  let threads = rayon::current_num_threads();
+  if context.use_parallel_traversal && threads > 1 {
+    let total = count_subtree(root, &mut subtree_sizes);
+    grain = PARALLEL_GRAIN.max(total / (threads * TASKS_PER_THREAD));
+  }
+  let mut traversal = StyleTraversal::new(context, &indexes, &subtree_sizes, grain);
+  let root = build_style_tree(root, &mut traversal, None, MEDIUM_FONT_SIZE);
+  (root, traversal.sharing.stats)
+}
+
+// 计算单个节点的样式
+fn compute_style(
+  node_type: &NodeType,
+  traversal: &StyleTraversal,
+  parent: Option<&PropertyMap>,
+  root_font_size: f32,
+) -> SharedStyle {
+  let mut specified_values = match *node_type {
+    NodeType::Element(ref elem) => specified_values(elem, traversal, parent),
+    NodeType::Text(_) => inherited_values(parent),
+  };
+  // var() 替换失败的属性按 unset 处理
+  for name in variables::substitute(&mut specified_values) {
+    unset(&mut specified_values, &name, parent);
+  }
+  // 先按未解析的值匹配字体，bolder 等相对的粗细按 normal 处理
+  let font_units = match traversal.context.fonts {
+    Some(fonts) if computed::uses_font_units(&specified_values) => {
+      fonts.font_units(&ComputedStyle::compute(&specified_values))
+    }
+    _ => FontUnits::default(),
+  };
+  computed::absolutize(
+    &mut specified_values,
+    parent,
+    root_font_size,
+    traversal.context.viewport,
+    font_units,
+  );
+  let computed_style = ComputedStyle::compute(&specified_values);
+
+  SharedStyle {
+    specified_values: Arc::new(specified_values),
+    computed_style: Arc::new(computed_style),
+  }
+}
+
+// 递归生成样式树，parent 为父节点的计算值，root_font_size 为根节点字号，用于解析 rem
+fn build_style_tree<'a: 'b, 'b>(
+  node: &'a Node,
+  traversal: &mut StyleTraversal<'_, 'b>,
+  parent: Option<&Arc<PropertyMap>>,
+  root_font_size: f32,
+) -> StyleNode<'a> {
+  let SharedStyle {
+    specified_values,
+    computed_style,
+  } = lookup_or_compute(&node.node_type, traversal, parent, root_font_size);
+
+  let root_font_size = match parent {
+    Some(_) => root_font_size,
+    None => computed_style.font_size,
+  };
+  let is_container = traversal.enter(&node.node_type, node.node_id(), &computed_style);
+
+  let children = match parallel_chunks(node, traversal) {
+    // 各组的结果按原顺序拼接，与串行遍历一致
+    Some(chunks) => {
+      let shared: &StyleTraversal = traversal;
+      let results: Vec<(Vec<StyleNode>, SharingStats)> = chunks
+        .into_par_iter()
+        .map(|chunk| {
+          let mut forked = shared.fork();
+          let nodes = chunk
+            .iter()
+            .map(|child| {
+              build_style_tree(child, &mut forked, Some(&specified_values), root_font_size)
+            })
+            .collect();
+          (nodes, forked.sharing.stats)
+        })
+        .collect();
+
+      let mut children = Vec::with_capacity(node.children.len());
+      for (nodes, stats) in results {
+        children.extend(nodes);
+        traversal.sharing.stats.hits += stats.hits;
+        traversal.sharing.stats.misses += stats.misses;
+      }
+      children
+    }
+    None => node
       .children
       .iter()
-      .map(|child| style_tree(child, stylesheet))
+      .map(|child| build_style_tree(child, traversal, Some(&specified_values), root_font_size))
       .collect(),
+  };
+
+  traversal.leave(&node.node_type, is_container);
+
+  StyleNode {
+    node,
+    specified_values,
+    computed_style,
+    children,
+  }
+}
+
+// 计算节点的样式，优先从样式共享缓存中查找
+fn lookup_or_compute<'b>(
+  node_type: &'b NodeType,
+  traversal: &mut StyleTraversal<'_, 'b>,
+  parent: Option<&Arc<PropertyMap>>,
+  root_font_size: f32,
+) -> SharedStyle {
+  let use_style_sharing = traversal.context.use_style_sharing;
+  let depth = traversal.ancestors.len();
+  if use_style_sharing {
+    if let Some(style) = traversal
+      .sharing
+      .lookup(depth, node_type, parent, &traversal.containers)
+    {
+      return style;
+    }
+  }
+
+  let style = compute_style(node_type, traversal, parent.map(|p| &**p), root_font_size);
+  if use_style_sharing {
+    traversal.sharing.insert(
+      depth,
+      node_type,
+      parent,
+      &traversal.containers,
+      style.clone(),
+    );
+  }
+  style
+}
+
+// 增量计算样式的统计
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RestyleStats {
+  pub restyled: usize,
+  pub reused: usize,
+}
+
+// 增量生成样式树，只重新计算受变化影响的节点，其余节点沿用上一次保存在 dom 中的样式
+// 首次调用时计算所有节点，结果与 style_tree 一致
+// 样式表变化时需先调用 invalidation::stylesheet_changed，视口等环境变化时需先调用 invalidation::invalidate_all
+pub fn incremental_style_tree<'a>(
+  root: &'a mut Node,
+  context: &StyleContext,
+) -> (StyleNode<'a>, RestyleStats) {
+  let mut features = InvalidationSet::new();
+  for (_, stylesheet) in context.stylesheets() {
+    features.add_rules(&stylesheet.rules);
+  }
+  invalidation::resolve_dom_changes(root, &features);
+
+  let indexes = rule_indexes(context);
+  let subtree_sizes = HashMap::new();
+  let mut traversal = StyleTraversal::new(context, &indexes, &subtree_sizes, 0);
+  let mut stats = RestyleStats::default();
+  restyle(
+    root,
+    &mut traversal,
+    None,
+    MEDIUM_FONT_SIZE,
+    false,
+    false,
+    &mut stats,
+  );
+
+  let root: &'a Node = root;
+  (stored_style_tree(root), stats)
+}
+
+// 由 dom 中保存的样式生成样式树
+fn stored_style_tree<'a>(node: &'a Node) -> StyleNode<'a> {
+  let style = node
+    .style_data
+    .style
+    .clone()
+    .expect("Node has not been styled");
+  StyleNode {
+    node,
+    specified_values: style.specified_values,
+    computed_style: style.computed_style,
+    children: node.children.iter().map(stored_style_tree).collect(),
+  }
+}
+
+// 递归更新 dom 中保存的样式
+// subtree 表示有祖先需要重新计算整个子树，parent_changed 表示父节点的值有变化，子节点需要重新继承
+fn restyle<'b>(
+  node: &'b mut Node,
+  traversal: &mut StyleTraversal<'_, 'b>,
+  parent: Option<&Arc<PropertyMap>>,
+  root_font_size: f32,
+  subtree: bool,
+  parent_changed: bool,
+  stats: &mut RestyleStats,
+) {
+  let node_id = node.node_id();
+  let Node {
+    node_type,
+    children,
+    style_data,
+    ..
+  } = node;
+  let node_type: &'b NodeType = node_type;
+
+  let (style, changed) = match &style_data.style {
+    Some(old) if !subtree && !parent_changed && style_data.damage == Damage::None => {
+      stats.reused += 1;
+      (old.clone(), false)
+    }
+    old => {
+      stats.restyled += 1;
+      let style = lookup_or_compute(node_type, traversal, parent, root_font_size);
+      match old {
+        // 值没有变化时保留原来的样式，子节点不需要因为继承而重新计算
+        Some(old) if old.specified_values == style.specified_values => (old.clone(), false),
+        _ => (style, true),
+      }
+    }
+  };
+  let subtree = subtree || style_data.damage == Damage::Subtree;
+  style_data.style = Some(style.clone());
+  style_data.damage = Damage::None;
+
+  let root_font_size = match parent {
+    Some(_) => root_font_size,
+    None => style.computed_style.font_size,
+  };
+  let is_container = traversal.enter(node_type, node_id, &style.computed_style);
+  for child in children {
+    restyle(
+      child,
+      traversal,
+      Some(&style.specified_values),
+      root_font_size,
+      subtree,
+      changed,
+      stats,
+    );
+  }
+  traversal.leave(node_type, is_container);
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::html;
+
+  // 用给定的用户样式表与作者样式表计算 <p> 的 color
+  fn cascaded_color(user: &str, author: &str) -> Option<Value> {
+    let root = html::parse("<p></p>".to_string());
+    let user = css::parse(user.to_string());
+    let author = css::parse(author.to_string());
+    let viewport = Rect {
+      width: 800.0,
+      height: 600.0,
+      ..Default::default()
+    };
+    let mut context = StyleContext::new(&author, viewport, MediaEnvironment::new(viewport));
+    context.user = Some(&user);
+    style_tree(&root, &context).value("color")
+  }
+
+  fn color(name: &str) -> Option<Value> {
+    css::parse_value("color", name.to_string())
+  }
+
+  #[test]
+  fn later_origins_win_for_normal_declarations() {
+    assert_eq!(
+      cascaded_color("p { color: red }", "p { color: blue }"),
+      color("blue")
+    );
+  }
+
+  #[test]
+  fn important_declarations_invert_origins() {
+    assert_eq!(
+      cascaded_color(
+        "p { color: red !important }",
+        "p { color: blue !important }"
+      ),
+      color("red")
+    );
+    assert_eq!(
+      cascaded_color("p { color: red !important }", "p { color: blue }"),
+      color("red")
+    );
+  }
+
+  #[test]
+  fn unlayered_declarations_beat_layers() {
+    let layers = "@layer a, b; @layer b { p { color: red } } @layer a { p { color: green } }";
+    assert_eq!(cascaded_color("", layers), color("red"));
+    assert_eq!(
+      cascaded_color("", &format!("{} p {{ color: blue }}", layers)),
+      color("blue")
+    );
+  }
+
+  #[test]
+  fn important_declarations_invert_layers() {
+    let author = "@layer a { p { color: green !important } } \
+                  @layer b { p { color: red !important } } \
+                  p { color: blue !important }";
+    assert_eq!(cascaded_color("", author), color("green"));
+  }
+
+  #[test]
+  fn revert_rolls_back_to_the_previous_origin() {
+    assert_eq!(
+      cascaded_color("p { color: red }", "p { color: blue } p { color: revert }"),
+      color("red")
+    );
+  }
+
+  #[test]
+  fn revert_layer_rolls_back_to_the_previous_layer() {
+    let author = "@layer a { p { color: green } } \
+                  @layer b { p { color: red } p { color: revert-layer } }";
+    assert_eq!(cascaded_color("p { color: blue }", author), color("green"));
+    // 最低的层回滚后落到更低的来源
+    assert_eq!(
+      cascaded_color(
+        "p { color: blue }",
+        "@layer a { p { color: revert-layer } }"
+      ),
+      color("blue")
+    );
+    // 未分层的声明回滚到作者样式表中的层
+    assert_eq!(
+      cascaded_color(
+        "",
+        "@layer a { p { color: green } } p { color: revert-layer }"
+      ),
+      color("green")
+    );
+  }
+
+  // 计算文档中各元素的属性值，按先序排列，不包括文本节点
+  fn element_values(html: &str, css: &str) -> Vec<Arc<PropertyMap>> {
+    fn collect(node: &StyleNode, values: &mut Vec<Arc<PropertyMap>>) {
+      if let NodeType::Element(_) = node.node.node_type {
+        values.push(node.specified_values.clone());
+      }
+      for child in &node.children {
+        collect(child, values);
+      }
+    }
+
+    let root = html::parse(html.to_string());
+    let sheet = css::parse(css.to_string());
+    let viewport = Rect {
+      width: 800.0,
+      height: 600.0,
+      ..Default::default()
+    };
+    let mut context = StyleContext::new(&sheet, viewport, MediaEnvironment::new(viewport));
+    // 不受 UA 样式表中 p 的外边距等默认样式影响
+    context.use_user_agent_stylesheet = false;
+    let mut values = Vec::new();
+    collect(&style_tree(&root, &context), &mut values);
+    values
+  }
+
+  fn px(f: f32) -> Option<Value> {
+    Some(Value::Length(f, css::Unit::Px))
+  }
+
+  fn keyword(s: &str) -> Option<Value> {
+    Some(Value::Keyword(s.to_string()))
+  }
+
+  #[test]
+  fn inherits_only_inherited_properties() {
+    let values = element_values(
+      "<div><p><em>a</em></p></div>",
+      "div { color: red; font-size: 20px; text-align: center; width: 100px; margin-top: 5px; \
+       --gap: 3px }",
+    );
+    for em in [&values[1], &values[2]] {
+      assert_eq!(em.get("color").cloned(), color("red"));
+      assert_eq!(em.get("font-size").cloned(), px(20.0));
+      assert_eq!(em.get("text-align").cloned(), keyword("center"));
+      // 自定义属性总是继承
+      assert!(em.contains_key("--gap"));
+      assert!(!em.contains_key("width"));
+      assert!(!em.contains_key("margin-top"));
+    }
+  }
+
+  #[test]
+  fn inherit_takes_the_parent_value() {
+    let values = element_values(
+      "<div><p></p></div>",
+      "div { width: 100px; border-top-style: solid; color: red } \
+       p { width: inherit; border-top-style: INHERIT; color: blue } \
+       p { color: inherit }",
+    );
+    assert_eq!(values[1].get("width").cloned(), px(100.0));
+    assert_eq!(values[1].get("border-top-style").cloned(), keyword("solid"));
+    assert_eq!(values[1].get("color").cloned(), color("red"));
+
+    // 根元素没有父节点，取初始值
+    let values = element_values("<div></div>", "div { width: inherit; color: inherit }");
+    assert_eq!(values[0].get("width").cloned(), keyword("auto"));
+    assert_eq!(values[0].get("color").cloned(), color("black"));
+  }
+
+  #[test]
+  fn initial_resets_to_the_initial_value() {
+    let values = element_values(
+      "<div><p></p></div>",
+      "div { color: red; font-size: 20px } p { color: initial; font-size: initial; width: 5px } \
+       p { width: initial }",
+    );
+    assert_eq!(values[1].get("color").cloned(), color("black"));
+    assert_eq!(values[1].get("font-size").cloned(), px(16.0));
+    assert_eq!(values[1].get("width").cloned(), keyword("auto"));
+  }
+
+  #[test]
+  fn unset_inherits_or_resets() {
+    let values = element_values(
+      "<div><p></p></div>",
+      "div { color: red; width: 100px } p { color: blue; width: 50px } \
+       p { color: unset; width: unset }",
+    );
+    assert_eq!(values[1].get("color").cloned(), color("red"));
+    assert_eq!(values[1].get("width").cloned(), keyword("auto"));
+  }
+
+  #[test]
+  fn css_wide_keywords_apply_to_every_longhand() {
+    let values = element_values(
+      "<div><p></p></div>",
+      "div { margin: 1px 2px 3px 4px } p { margin: 9px; margin: inherit }",
+    );
+    for (side, width) in [("top", 1.0), ("right", 2.0), ("bottom", 3.0), ("left", 4.0)] {
+      assert_eq!(
+        values[1].get(&format!("margin-{}", side)).cloned(),
+        px(width)
+      );
+    }
+  }
+
+  #[test]
+  fn resolves_ex_and_ch_with_fonts() {
+    let root = html::parse("<p></p>".to_string());
+    let sheet = css::parse("p { font-size: 20px; width: 2ex; height: 3ch }".to_string());
+    let viewport = Rect {
+      width: 800.0,
+      height: 600.0,
+      ..Default::default()
+    };
+    let fonts = FontContext::new();
+    let mut context = StyleContext::new(&sheet, viewport, MediaEnvironment::new(viewport));
+    context.fonts = Some(&fonts);
+    let style_tree = style_tree(&root, &context);
+    let units = fonts.font_units(&style_tree.computed_style);
+    assert_eq!(style_tree.value("width"), px(40.0 * units.ex));
+    assert_eq!(style_tree.value("height"), px(60.0 * units.ch));
   }
 }
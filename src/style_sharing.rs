@@ -0,0 +1,287 @@
+use crate::computed::ComputedStyle;
+use crate::dom::{ElementData, NodeType};
+use crate::style::{PropertyMap, QueryContainer};
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+// 可以在节点之间共享的样式
+#[derive(Debug, Clone)]
+pub struct SharedStyle {
+  pub specified_values: Arc<PropertyMap>,
+  pub computed_style: Arc<ComputedStyle>,
+}
+
+// 样式共享缓存，记录最近计算过的节点样式
+// 父节点样式相同，且 tag、属性完全相同的元素，匹配的规则和继承的值都相同，可以直接复用
+// 选择器只依赖 tag、id、class，父节点样式相同意味着祖先链上这些信息也相同
+// 每层深度单独缓存，避免兄弟节点之间的子孙节点把兄弟节点挤出缓存
+pub struct StyleSharingCache<'a> {
+  levels: Vec<VecDeque<Entry<'a>>>,
+  pub stats: SharingStats,
+}
+
+struct Entry<'a> {
+  // 文本节点为 None
+  element: Option<&'a ElementData>,
+  // 持有父节点样式，保证比较指针时地址不会被复用
+  parent: Option<Arc<PropertyMap>>,
+  containers: Vec<QueryContainer>,
+  style: SharedStyle,
+}
+
+// 缓存命中情况
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SharingStats {
+  pub hits: usize,
+  pub misses: usize,
+}
+
+impl SharingStats {
+  pub fn hit_rate(&self) -> f32 {
+    let total = self.hits + self.misses;
+    if total == 0 {
+      0.0
+    } else {
+      self.hits as f32 / total as f32
+    }
+  }
+}
+
+// 每层只保留最近的节点，兄弟节点通常是相邻的
+const CACHE_SIZE: usize = 16;
+
+impl<'a> StyleSharingCache<'a> {
+  pub fn new() -> StyleSharingCache<'a> {
+    StyleSharingCache {
+      levels: Vec::new(),
+      stats: SharingStats::default(),
+    }
+  }
+
+  // 查找可以共享的样式，depth 为节点在树中的深度，containers 为祖先中的查询容器
+  pub fn lookup(
+    &mut self,
+    depth: usize,
+    node_type: &NodeType,
+    parent: Option<&Arc<PropertyMap>>,
+    containers: &[QueryContainer],
+  ) -> Option<SharedStyle> {
+    let element = match node_type {
+      // 有 id 的元素可能匹配 id 选择器，不共享
+      NodeType::Element(elem) if elem.id().is_some() => return None,
+      NodeType::Element(elem) => Some(elem),
+      NodeType::Text(_) => None,
+    };
+
+    let entries = match self.levels.get_mut(depth) {
+      Some(entries) => entries,
+      None => {
+        self.stats.misses += 1;
+        return None;
+      }
+    };
+    let found = entries.iter().position(|entry| {
+      same_parent(entry.parent.as_ref(), parent)
+        && entry.containers == containers
+        && match (entry.element, element) {
+          (Some(a), Some(b)) => a.tag_name == b.tag_name && a.attributes == b.attributes,
+          (None, None) => true,
+          _ => false,
+        }
+    });
+
+    match found {
+      Some(index) => {
+        self.stats.hits += 1;
+        // 移到最前面
+        let entry = entries.remove(index).unwrap();
+        let style = entry.style.clone();
+        entries.push_front(entry);
+        Some(style)
+      }
+      None => {
+        self.stats.misses += 1;
+        None
+      }
+    }
+  }
+
+  // 记录节点的样式，超出容量时淘汰最久未使用的
+  pub fn insert(
+    &mut self,
+    depth: usize,
+    node_type: &'a NodeType,
+    parent: Option<&Arc<PropertyMap>>,
+    containers: &[QueryContainer],
+    style: SharedStyle,
+  ) {
+    let element = match node_type {
+      NodeType::Element(elem) if elem.id().is_some() => return,
+      NodeType::Element(elem) => Some(elem),
+      NodeType::Text(_) => None,
+    };
+
+    if self.levels.len() <= depth {
+      self.levels.resize_with(depth + 1, VecDeque::new);
+    }
+    let entries = &mut self.levels[depth];
+    if entries.len() == CACHE_SIZE {
+      entries.pop_back();
+    }
+    entries.push_front(Entry {
+      element,
+      parent: parent.cloned(),
+      containers: containers.to_vec(),
+      style,
+    });
+  }
+}
+
+impl<'a> Default for StyleSharingCache<'a> {
+  fn default() -> Self {
+    StyleSharingCache::new()
+  }
+}
+
+fn same_parent(a: Option<&Arc<PropertyMap>>, b: Option<&Arc<PropertyMap>>) -> bool {
+  match (a, b) {
+    (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+    _ => false,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::computed::ContainerType;
+  use crate::dom::{self, AttrMap, Node};
+
+  // 构造带属性的元素节点
+  fn element(tag: &str, attrs: &[(&str, &str)]) -> Node {
+    let attrs: AttrMap = attrs
+      .iter()
+      .map(|(name, value)| (name.to_string(), value.to_string()))
+      .collect();
+    dom::elem(tag.to_string(), attrs, vec![])
+  }
+
+  fn shared_style() -> SharedStyle {
+    SharedStyle {
+      specified_values: Arc::new(PropertyMap::new()),
+      computed_style: Arc::new(ComputedStyle::compute(&PropertyMap::new())),
+    }
+  }
+
+  fn container(name: &str) -> QueryContainer {
+    QueryContainer {
+      names: vec![name.to_string()],
+      container_type: ContainerType::InlineSize,
+      size: Some((300.0, 100.0)),
+    }
+  }
+
+  #[test]
+  fn shares_siblings_with_same_parent_and_attributes() {
+    let parent = Arc::new(PropertyMap::new());
+    let first = element("p", &[("class", "note")]);
+    let second = element("p", &[("class", "note")]);
+    let style = shared_style();
+
+    let mut cache = StyleSharingCache::new();
+    assert!(cache
+      .lookup(1, &first.node_type, Some(&parent), &[])
+      .is_none());
+    cache.insert(1, &first.node_type, Some(&parent), &[], style.clone());
+    let found = cache
+      .lookup(1, &second.node_type, Some(&parent), &[])
+      .unwrap();
+    assert!(Arc::ptr_eq(&found.computed_style, &style.computed_style));
+    assert_eq!(cache.stats, SharingStats { hits: 1, misses: 1 });
+  }
+
+  #[test]
+  fn does_not_share_across_different_parents() {
+    // 父节点的样式内容相同，但不是同一份
+    let parent = Arc::new(PropertyMap::new());
+    let other_parent = Arc::new(PropertyMap::new());
+    let first = element("p", &[]);
+    let second = element("p", &[]);
+
+    let mut cache = StyleSharingCache::new();
+    cache.insert(1, &first.node_type, Some(&parent), &[], shared_style());
+    assert!(cache
+      .lookup(1, &second.node_type, Some(&other_parent), &[])
+      .is_none());
+    // 根节点没有父节点，也不共享
+    cache.insert(0, &first.node_type, None, &[], shared_style());
+    assert!(cache.lookup(0, &second.node_type, None, &[]).is_none());
+  }
+
+  #[test]
+  fn does_not_share_elements_with_id() {
+    let parent = Arc::new(PropertyMap::new());
+    let first = element("p", &[("id", "intro")]);
+    let second = element("p", &[("id", "intro")]);
+
+    let mut cache = StyleSharingCache::new();
+    cache.insert(1, &first.node_type, Some(&parent), &[], shared_style());
+    assert!(cache
+      .lookup(1, &second.node_type, Some(&parent), &[])
+      .is_none());
+    // 没有 id 的元素也不会命中有 id 的元素
+    let plain = element("p", &[]);
+    assert!(cache
+      .lookup(1, &plain.node_type, Some(&parent), &[])
+      .is_none());
+  }
+
+  #[test]
+  fn does_not_share_elements_with_different_attributes() {
+    let parent = Arc::new(PropertyMap::new());
+    let first = element("p", &[("class", "note")]);
+
+    let mut cache = StyleSharingCache::new();
+    cache.insert(1, &first.node_type, Some(&parent), &[], shared_style());
+    for attrs in [
+      &[("class", "warning")][..],
+      &[][..],
+      &[("class", "note"), ("title", "x")][..],
+    ] {
+      let other = element("p", attrs);
+      assert!(cache
+        .lookup(1, &other.node_type, Some(&parent), &[])
+        .is_none());
+    }
+    // tag 不同也不共享
+    let other = element("div", &[("class", "note")]);
+    assert!(cache
+      .lookup(1, &other.node_type, Some(&parent), &[])
+      .is_none());
+  }
+
+  #[test]
+  fn does_not_share_across_container_contexts() {
+    let parent = Arc::new(PropertyMap::new());
+    let first = element("p", &[]);
+    let second = element("p", &[]);
+    let sidebar = vec![container("sidebar")];
+
+    let mut cache = StyleSharingCache::new();
+    cache.insert(1, &first.node_type, Some(&parent), &sidebar, shared_style());
+    assert!(cache
+      .lookup(1, &second.node_type, Some(&parent), &[])
+      .is_none());
+    assert!(cache
+      .lookup(1, &second.node_type, Some(&parent), &[container("main")])
+      .is_none());
+    // 容器名字相同但尺寸不同，@container 条件的结果可能不同
+    let mut resized = container("sidebar");
+    resized.size = Some((600.0, 100.0));
+    assert!(cache
+      .lookup(1, &second.node_type, Some(&parent), &[resized])
+      .is_none());
+    assert!(cache
+      .lookup(1, &second.node_type, Some(&parent), &sidebar)
+      .is_some());
+  }
+}
@@ -0,0 +1,233 @@
+use crate::css::{self, Value};
+use crate::style::PropertyMap;
+use std::collections::{HashMap, HashSet};
+
+// 替换 var() 引用，返回替换失败的属性名
+// 失败的属性在计算值阶段无效，由调用方按 unset 处理
+pub fn substitute(values: &mut PropertyMap) -> Vec<String> {
+  let mut resolver = Resolver {
+    raw: HashMap::new(),
+    resolved: HashMap::new(),
+    stack: Vec::new(),
+    cyclic: HashSet::new(),
+  };
+  for (name, value) in values.iter() {
+    if let Value::Unparsed { css, .. } = value {
+      if css::is_custom_property(name) {
+        resolver.raw.insert(name.clone(), css.clone());
+      }
+    }
+  }
+
+  // 先解析所有自定义属性，循环引用或引用不存在的属性时无效
+  let names: Vec<String> = resolver.raw.keys().cloned().collect();
+  for name in names {
+    match resolver.resolve(&name) {
+      Some(css) => {
+        values.insert(
+          name,
+          Value::Unparsed {
+            css,
+            shorthand: None,
+          },
+        );
+      }
+      None => {
+        values.remove(&name);
+      }
+    }
+  }
+
+  // 再替换普通属性中的引用，并按属性的语法重新解析
+  let mut invalid = Vec::new();
+  let mut substituted = Vec::new();
+  for (name, value) in values.iter() {
+    if css::is_custom_property(name) {
+      continue;
+    }
+    if let Value::Unparsed { css, shorthand } = value {
+      match resolver.substitute(css) {
+        Some(css) => substituted.push((name.clone(), reparse(name, shorthand.as_deref(), &css))),
+        None => invalid.push(name.clone()),
+      }
+    }
+  }
+  for (name, value) in substituted {
+    match value {
+      Some(value) => {
+        values.insert(name, value);
+      }
+      None => invalid.push(name),
+    }
+  }
+
+  invalid
+}
+
+// 替换后的文本按原属性重新解析，简写属性展开后取出对应的普通属性
+// 不符合属性语法时返回 None，在计算值阶段按 unset 处理
+fn reparse(name: &str, shorthand: Option<&str>, css: &str) -> Option<Value> {
+  let declared_name = shorthand.unwrap_or(name);
+  if css.trim().is_empty() {
+    return None;
+  }
+  css::parse_declaration_value(declared_name, css)?
+    .into_iter()
+    .find(|(longhand, _)| longhand == name)
+    .map(|(_, value)| value)
+}
+
+struct Resolver {
+  // 自定义属性的原始文本
+  raw: HashMap<String, String>,
+  // 已解析的自定义属性，None 表示无效
+  resolved: HashMap<String, Option<String>>,
+  // 正在解析的属性，用于检测循环引用
+  stack: Vec<String>,
+  // 处于循环中的属性
+  cyclic: HashSet<String>,
+}
+
+impl Resolver {
+  // 解析自定义属性，替换其中的 var() 引用
+  fn resolve(&mut self, name: &str) -> Option<String> {
+    if let Some(css) = self.resolved.get(name) {
+      return css.clone();
+    }
+
+    // 循环引用，环上的所有属性都无效
+    if let Some(index) = self.stack.iter().position(|n| n == name) {
+      self.cyclic.extend(self.stack[index..].iter().cloned());
+      return None;
+    }
+
+    let raw = self.raw.get(name)?.clone();
+    self.stack.push(name.to_string());
+    let css = self.substitute(&raw);
+    self.stack.pop();
+
+    let css = if self.cyclic.contains(name) {
+      None
+    } else {
+      css
+    };
+    self.resolved.insert(name.to_string(), css.clone());
+    css
+  }
+
+  // 替换文本中所有的 var(--name, fallback)，引用无效且没有 fallback 时返回 None
+  fn substitute(&mut self, css: &str) -> Option<String> {
+    let mut result = String::new();
+    let mut rest = css;
+
+    while let Some(start) = rest.to_ascii_lowercase().find("var(") {
+      result.push_str(&rest[..start]);
+      let args_start = start + "var(".len();
+      let args_end = args_start + find_closing_paren(&rest[args_start..])?;
+      let args = &rest[args_start..args_end];
+
+      let (name, fallback) = match args.find(',') {
+        Some(comma) => (args[..comma].trim(), Some(&args[comma + 1..])),
+        None => (args.trim(), None),
+      };
+      // var() 中不是自定义属性名时替换失败
+      if !css::is_custom_property(name) {
+        return None;
+      }
+
+      let value = match self.resolve(name) {
+        Some(value) => value,
+        None => self.substitute(fallback?.trim())?,
+      };
+      result.push_str(&value);
+      rest = &rest[args_end + 1..];
+    }
+    result.push_str(rest);
+
+    Some(result)
+  }
+}
+
+// 查找与左括号匹配的右括号位置，s 为左括号之后的文本
+fn find_closing_paren(s: &str) -> Option<usize> {
+  let mut depth = 0;
+  for (i, c) in s.char_indices() {
+    match c {
+      '(' => depth += 1,
+      ')' if depth == 0 => return Some(i),
+      ')' => depth -= 1,
+      _ => {}
+    }
+  }
+  None
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::css::Unit;
+
+  // 解析声明得到替换前的属性表
+  fn specified(declarations: &[(&str, &str)]) -> PropertyMap {
+    let mut values = PropertyMap::new();
+    for (name, css) in declarations {
+      values.extend(css::parse_declaration_value(name, css).unwrap());
+    }
+    values
+  }
+
+  fn px(f: f32) -> Value {
+    Value::Length(f, Unit::Px)
+  }
+
+  #[test]
+  fn substitutes_references_and_fallbacks() {
+    let mut values = specified(&[
+      ("--width", "10px"),
+      ("--alias", "var(--width)"),
+      ("width", "var(--alias)"),
+      ("height", "var(--missing, 5px)"),
+    ]);
+    assert!(substitute(&mut values).is_empty());
+    assert_eq!(values["width"], px(10.0));
+    assert_eq!(values["height"], px(5.0));
+  }
+
+  #[test]
+  fn reexpands_shorthands_after_substitution() {
+    let mut values = specified(&[("--m", "1px 2px"), ("margin", "var(--m)")]);
+    assert!(substitute(&mut values).is_empty());
+    assert_eq!(values["margin-top"], px(1.0));
+    assert_eq!(values["margin-right"], px(2.0));
+  }
+
+  #[test]
+  fn cyclic_references_are_invalid() {
+    let mut values = specified(&[
+      ("--a", "var(--b)"),
+      ("--b", "var(--a)"),
+      ("--self", "var(--self)"),
+      ("width", "var(--a, 3px)"),
+      ("height", "var(--self)"),
+    ]);
+    let invalid = substitute(&mut values);
+    assert_eq!(invalid, vec!["height".to_string()]);
+    assert!(!values.contains_key("--a") && !values.contains_key("--b"));
+    assert!(!values.contains_key("--self"));
+    assert_eq!(values["width"], px(3.0));
+  }
+
+  // 替换后不符合属性语法的值在计算值阶段无效，而不是解析失败
+  #[test]
+  fn invalid_substitutions_are_reported() {
+    for declarations in [
+      &[("width", "var(foo, 1px)")][..],
+      &[("--g", "1px 2px"), ("width", "var(--g)")],
+      &[("--g", "3s"), ("width", "var(--g)")],
+      &[("--g", ""), ("width", "var(--g)")],
+    ] {
+      let mut values = specified(declarations);
+      assert_eq!(substitute(&mut values), vec!["width".to_string()]);
+    }
+  }
+}
@@ -0,0 +1,628 @@
+use std::io::Read;
+
+// WOFF2 格式的字体，解压后还原为 TrueType/OpenType 数据
+// 只支持单个字体，不支持字体集合
+
+const SIGNATURE: u32 = 0x774F_4632;
+const HEADER_SIZE: usize = 48;
+
+// 表目录中用 6 位序号表示的常用表，63 表示后面跟着 4 字节的表名
+const KNOWN_TAGS: [&[u8; 4]; 63] = [
+  b"cmap", b"head", b"hhea", b"hmtx", b"maxp", b"name", b"OS/2", b"post", b"cvt ", b"fpgm",
+  b"glyf", b"loca", b"prep", b"CFF ", b"VORG", b"EBDT", b"EBLC", b"gasp", b"hdmx", b"kern",
+  b"LTSH", b"PCLT", b"VDMX", b"vhea", b"vmtx", b"BASE", b"GDEF", b"GPOS", b"GSUB", b"EBSC",
+  b"JSTF", b"MATH", b"CBDT", b"CBLC", b"COLR", b"CPAL", b"SVG ", b"sbix", b"acnt", b"avar",
+  b"bdat", b"bloc", b"bsln", b"cvar", b"fdsc", b"feat", b"fmtx", b"fvar", b"gvar", b"hsty",
+  b"just", b"lcar", b"mort", b"morx", b"opbd", b"prop", b"trak", b"Zapf", b"Silf", b"Glat",
+  b"Gloc", b"Feat", b"Sill",
+];
+
+// 是否为 WOFF2 数据
+pub fn is_woff2(data: &[u8]) -> bool {
+  data.len() >= 4 && u32::from_be_bytes([data[0], data[1], data[2], data[3]]) == SIGNATURE
+}
+
+struct TableEntry {
+  tag: [u8; 4],
+  // 还原后的长度
+  orig_length: usize,
+  // 在解压后的数据中的位置和长度，经过变换的表长度与还原后不同
+  offset: usize,
+  length: usize,
+  transformed: bool,
+}
+
+// 还原为 sfnt 数据，格式错误或是字体集合时返回 None
+pub fn decode(data: &[u8]) -> Option<Vec<u8>> {
+  let (flavor, tables, stream) = read_tables(data)?;
+
+  let table_data = |tag: &[u8; 4]| {
+    tables
+      .iter()
+      .find(|table| &table.tag == tag)
+      .map(|table| &stream[table.offset..table.offset + table.length])
+  };
+
+  // glyf 和 loca 一起还原，hmtx 的还原需要用到字形的 xMin
+  let mut output_tables: Vec<([u8; 4], Vec<u8>)> = Vec::new();
+  let mut x_mins = Vec::new();
+  let glyf = tables
+    .iter()
+    .find(|table| &table.tag == b"glyf" && table.transformed);
+  if let Some(glyf) = glyf {
+    let (glyf_data, loca_data, mins) =
+      reconstruct_glyf(&stream[glyf.offset..glyf.offset + glyf.length])?;
+    x_mins = mins;
+    output_tables.push((*b"glyf", glyf_data));
+    output_tables.push((*b"loca", loca_data));
+  }
+
+  for table in &tables {
+    let data = &stream[table.offset..table.offset + table.length];
+    match &table.tag {
+      b"glyf" | b"loca" if glyf.is_some() => {}
+      b"hmtx" if table.transformed => {
+        let hhea = table_data(b"hhea")?;
+        let num_h_metrics = Reader::new(hhea.get(34..36)?).u16()? as usize;
+        output_tables.push((*b"hmtx", reconstruct_hmtx(data, num_h_metrics, &x_mins)?));
+      }
+      // loca 的偏移量统一改写为 4 字节
+      b"head" if glyf.is_some() => {
+        let mut head = data.to_vec();
+        head.get_mut(50..52)?.copy_from_slice(&1u16.to_be_bytes());
+        output_tables.push((*b"head", head));
+      }
+      _ => {
+        if table.transformed || data.len() != table.orig_length {
+          return None;
+        }
+        output_tables.push((table.tag, data.to_vec()));
+      }
+    }
+  }
+
+  Some(build_sfnt(flavor, output_tables))
+}
+
+// 读取表目录并解压，返回 sfnt 的类型、各表的位置和解压后的数据
+fn read_tables(data: &[u8]) -> Option<(u32, Vec<TableEntry>, Vec<u8>)> {
+  if !is_woff2(data) || data.len() < HEADER_SIZE {
+    return None;
+  }
+  let mut header = Reader::new(data);
+  header.skip(4)?;
+  let flavor = header.u32()?;
+  header.skip(4)?;
+  let num_tables = header.u16()? as usize;
+  header.skip(6)?;
+  let compressed_size = header.u32()? as usize;
+  if flavor == u32::from_be_bytes(*b"ttcf") {
+    return None;
+  }
+
+  let mut directory = Reader::new(&data[HEADER_SIZE..]);
+  let mut tables = Vec::with_capacity(num_tables);
+  let mut offset = 0;
+  for _ in 0..num_tables {
+    let flags = directory.u8()?;
+    let tag = match (flags & 0x3f) as usize {
+      63 => directory.u32()?.to_be_bytes(),
+      index => *KNOWN_TAGS[index],
+    };
+    let version = flags >> 6;
+    let orig_length = directory.base128()? as usize;
+    // glyf、loca 的变换版本 0 表示经过变换，其余表的版本 0 表示未变换
+    let transformed = match &tag {
+      b"glyf" | b"loca" => version == 0,
+      _ => version != 0,
+    };
+    let length = if transformed {
+      directory.base128()? as usize
+    } else {
+      orig_length
+    };
+    tables.push(TableEntry {
+      tag,
+      orig_length,
+      offset,
+      length,
+      transformed,
+    });
+    offset += length;
+  }
+
+  let start = HEADER_SIZE + directory.pos;
+  let compressed = data.get(start..start + compressed_size)?;
+  let mut stream = Vec::new();
+  brotli_decompressor::Decompressor::new(compressed, 4096)
+    .read_to_end(&mut stream)
+    .ok()?;
+  if stream.len() < offset {
+    return None;
+  }
+  Some((flavor, tables, stream))
+}
+
+// 还原变换后的 glyf 表，返回 glyf、loca 和每个字形的 xMin
+fn reconstruct_glyf(data: &[u8]) -> Option<(Vec<u8>, Vec<u8>, Vec<i16>)> {
+  let mut header = Reader::new(data);
+  header.skip(2)?;
+  let option_flags = header.u16()?;
+  let num_glyphs = header.u16()? as usize;
+  header.skip(2)?;
+
+  // 依次为轮廓数、点数、标志、字形、复合字形、包围盒、指令 7 个数据流
+  let mut streams = Vec::with_capacity(7);
+  let mut offset = 36;
+  for _ in 0..7 {
+    let size = header.u32()? as usize;
+    streams.push(Reader::new(data.get(offset..offset + size)?));
+    offset += size;
+  }
+  let overlap_bitmap = if option_flags & 1 != 0 {
+    data.get(offset..offset + num_glyphs.div_ceil(8))
+  } else {
+    None
+  };
+  let mut instructions = streams.pop()?;
+  let mut bbox_stream = streams.pop()?;
+  let mut composites = streams.pop()?;
+  let mut glyphs = streams.pop()?;
+  let mut flags_stream = streams.pop()?;
+  let mut points_stream = streams.pop()?;
+  let mut contours_stream = streams.pop()?;
+
+  let bitmap_size = num_glyphs.div_ceil(32) * 4;
+  let bbox_bitmap = bbox_stream.bytes(bitmap_size)?;
+  let has_bbox = |glyph: usize| bbox_bitmap[glyph / 8] & (0x80 >> (glyph % 8)) != 0;
+
+  let mut glyf = Vec::new();
+  let mut loca = Vec::with_capacity((num_glyphs + 1) * 4);
+  let mut x_mins = Vec::with_capacity(num_glyphs);
+  for glyph in 0..num_glyphs {
+    loca.extend_from_slice(&(glyf.len() as u32).to_be_bytes());
+    let contours = contours_stream.u16()? as i16;
+
+    if contours == 0 {
+      if has_bbox(glyph) {
+        return None;
+      }
+      x_mins.push(0);
+      continue;
+    }
+
+    if contours < 0 {
+      // 复合字形的包围盒必须显式给出
+      if !has_bbox(glyph) {
+        return None;
+      }
+      let bbox = bbox_stream.bytes(8)?;
+      let (components, has_instructions) = read_composite(&mut composites)?;
+      glyf.extend_from_slice(&(-1i16).to_be_bytes());
+      glyf.extend_from_slice(bbox);
+      glyf.extend_from_slice(components);
+      if has_instructions {
+        let length = glyphs.u255()? as usize;
+        glyf.extend_from_slice(&(length as u16).to_be_bytes());
+        glyf.extend_from_slice(instructions.bytes(length)?);
+      }
+      x_mins.push(i16::from_be_bytes([bbox[0], bbox[1]]));
+    } else {
+      let mut end_points = Vec::with_capacity(contours as usize);
+      let mut total = 0usize;
+      for _ in 0..contours {
+        total += points_stream.u255()? as usize;
+        end_points.push((total as u16).checked_sub(1)?);
+      }
+
+      // 坐标以三元组编码，解码为相对上一个点的偏移
+      let mut points = Vec::with_capacity(total);
+      for _ in 0..total {
+        let flag = flags_stream.u8()?;
+        let (dx, dy) = decode_triplet(flag & 0x7f, &mut glyphs)?;
+        points.push((dx, dy, flag & 0x80 == 0));
+      }
+      let instruction_length = glyphs.u255()? as usize;
+      let instruction_bytes = instructions.bytes(instruction_length)?;
+
+      let bbox = if has_bbox(glyph) {
+        let mut bbox = [0i16; 4];
+        for value in &mut bbox {
+          *value = bbox_stream.u16()? as i16;
+        }
+        bbox
+      } else {
+        point_bounds(&points)
+      };
+
+      glyf.extend_from_slice(&contours.to_be_bytes());
+      for value in bbox {
+        glyf.extend_from_slice(&value.to_be_bytes());
+      }
+      for end_point in end_points {
+        glyf.extend_from_slice(&end_point.to_be_bytes());
+      }
+      glyf.extend_from_slice(&(instruction_length as u16).to_be_bytes());
+      glyf.extend_from_slice(instruction_bytes);
+      let overlap =
+        overlap_bitmap.is_some_and(|bitmap| bitmap[glyph / 8] & (0x80 >> (glyph % 8)) != 0);
+      write_simple_points(&points, overlap, &mut glyf);
+      x_mins.push(bbox[0]);
+    }
+
+    while glyf.len() % 4 != 0 {
+      glyf.push(0);
+    }
+  }
+  loca.extend_from_slice(&(glyf.len() as u32).to_be_bytes());
+
+  Some((glyf, loca, x_mins))
+}
+
+// 读取复合字形的各个部件，返回原始数据和是否带有指令
+fn read_composite<'a>(stream: &mut Reader<'a>) -> Option<(&'a [u8], bool)> {
+  const ARG_1_AND_2_ARE_WORDS: u16 = 0x0001;
+  const WE_HAVE_A_SCALE: u16 = 0x0008;
+  const MORE_COMPONENTS: u16 = 0x0020;
+  const WE_HAVE_AN_X_AND_Y_SCALE: u16 = 0x0040;
+  const WE_HAVE_A_TWO_BY_TWO: u16 = 0x0080;
+  const WE_HAVE_INSTRUCTIONS: u16 = 0x0100;
+
+  let start = stream.pos;
+  let mut has_instructions = false;
+  loop {
+    let flags = stream.u16()?;
+    let mut size = 2
+      + if flags & ARG_1_AND_2_ARE_WORDS != 0 {
+        4
+      } else {
+        2
+      };
+    if flags & WE_HAVE_A_SCALE != 0 {
+      size += 2;
+    } else if flags & WE_HAVE_AN_X_AND_Y_SCALE != 0 {
+      size += 4;
+    } else if flags & WE_HAVE_A_TWO_BY_TWO != 0 {
+      size += 8;
+    }
+    stream.skip(size)?;
+    has_instructions |= flags & WE_HAVE_INSTRUCTIONS != 0;
+    if flags & MORE_COMPONENTS == 0 {
+      break;
+    }
+  }
+  Some((&stream.data[start..stream.pos], has_instructions))
+}
+
+// 按 WOFF2 规范中的三元组编码表解码坐标偏移
+fn decode_triplet(flag: u8, stream: &mut Reader) -> Option<(i32, i32)> {
+  let with_sign = |flag: u8, value: i32| if flag & 1 != 0 { value } else { -value };
+  let flag_value = flag as i32;
+  let point = if flag < 10 {
+    let b0 = stream.u8()? as i32;
+    (0, with_sign(flag, ((flag_value & 14) << 7) + b0))
+  } else if flag < 20 {
+    let b0 = stream.u8()? as i32;
+    (with_sign(flag, (((flag_value - 10) & 14) << 7) + b0), 0)
+  } else if flag < 84 {
+    let b0 = flag_value - 20;
+    let b1 = stream.u8()? as i32;
+    (
+      with_sign(flag, 1 + (b0 & 0x30) + (b1 >> 4)),
+      with_sign(flag >> 1, 1 + ((b0 & 0x0c) << 2) + (b1 & 0x0f)),
+    )
+  } else if flag < 120 {
+    let b0 = flag_value - 84;
+    let b1 = stream.u8()? as i32;
+    let b2 = stream.u8()? as i32;
+    (
+      with_sign(flag, 1 + ((b0 / 12) << 8) + b1),
+      with_sign(flag >> 1, 1 + (((b0 % 12) >> 2) << 8) + b2),
+    )
+  } else if flag < 124 {
+    let b1 = stream.u8()? as i32;
+    let b2 = stream.u8()? as i32;
+    let b3 = stream.u8()? as i32;
+    (
+      with_sign(flag, (b1 << 4) + (b2 >> 4)),
+      with_sign(flag >> 1, ((b2 & 0x0f) << 8) + b3),
+    )
+  } else {
+    let x = stream.u16()? as i32;
+    let y = stream.u16()? as i32;
+    (with_sign(flag, x), with_sign(flag >> 1, y))
+  };
+  Some(point)
+}
+
+// 由各点的相对偏移计算包围盒
+fn point_bounds(points: &[(i32, i32, bool)]) -> [i16; 4] {
+  let (mut x, mut y) = (0, 0);
+  let mut bounds: Option<[i32; 4]> = None;
+  for &(dx, dy, _) in points {
+    x += dx;
+    y += dy;
+    bounds = Some(match bounds {
+      Some([x_min, y_min, x_max, y_max]) => {
+        [x_min.min(x), y_min.min(y), x_max.max(x), y_max.max(y)]
+      }
+      None => [x, y, x, y],
+    });
+  }
+  bounds.unwrap_or_default().map(|value| value as i16)
+}
+
+// 按 TrueType 简单字形的格式写入各点的标志和坐标，不压缩重复的标志
+fn write_simple_points(points: &[(i32, i32, bool)], overlap: bool, glyf: &mut Vec<u8>) {
+  const ON_CURVE: u8 = 0x01;
+  const X_SHORT: u8 = 0x02;
+  const Y_SHORT: u8 = 0x04;
+  const X_SAME_OR_POSITIVE: u8 = 0x10;
+  const Y_SAME_OR_POSITIVE: u8 = 0x20;
+  const OVERLAP_SIMPLE: u8 = 0x40;
+
+  let mut x_bytes = Vec::new();
+  let mut y_bytes = Vec::new();
+  for (i, &(dx, dy, on_curve)) in points.iter().enumerate() {
+    let mut flag = if on_curve { ON_CURVE } else { 0 };
+    if overlap && i == 0 {
+      flag |= OVERLAP_SIMPLE;
+    }
+    for (delta, short, same, bytes) in [
+      (dx, X_SHORT, X_SAME_OR_POSITIVE, &mut x_bytes),
+      (dy, Y_SHORT, Y_SAME_OR_POSITIVE, &mut y_bytes),
+    ] {
+      if delta == 0 {
+        flag |= same;
+      } else if delta.abs() < 256 {
+        flag |= short;
+        if delta > 0 {
+          flag |= same;
+        }
+        bytes.push(delta.unsigned_abs() as u8);
+      } else {
+        bytes.extend_from_slice(&(delta as i16).to_be_bytes());
+      }
+    }
+    glyf.push(flag);
+  }
+  glyf.extend_from_slice(&x_bytes);
+  glyf.extend_from_slice(&y_bytes);
+}
+
+// 还原变换后的 hmtx 表，省略的左侧空白取字形的 xMin
+fn reconstruct_hmtx(data: &[u8], num_h_metrics: usize, x_mins: &[i16]) -> Option<Vec<u8>> {
+  let mut reader = Reader::new(data);
+  let flags = reader.u8()?;
+  let num_glyphs = x_mins.len();
+  if num_h_metrics > num_glyphs {
+    return None;
+  }
+
+  let mut advances = Vec::with_capacity(num_h_metrics);
+  for _ in 0..num_h_metrics {
+    advances.push(reader.u16()?);
+  }
+  let mut lsbs = Vec::with_capacity(num_glyphs);
+  // 第 0 位表示前 numberOfHMetrics 个字形省略了 lsb，第 1 位表示其余字形省略了 lsb
+  for (glyph, &x_min) in x_mins.iter().enumerate() {
+    let omitted = if glyph < num_h_metrics { 1 } else { 2 };
+    lsbs.push(if flags & omitted == 0 {
+      reader.u16()? as i16
+    } else {
+      x_min
+    });
+  }
+
+  let mut hmtx = Vec::with_capacity(num_h_metrics * 4 + (num_glyphs - num_h_metrics) * 2);
+  for (glyph, lsb) in lsbs.iter().enumerate() {
+    if glyph < num_h_metrics {
+      hmtx.extend_from_slice(&advances[glyph].to_be_bytes());
+    }
+    hmtx.extend_from_slice(&lsb.to_be_bytes());
+  }
+  Some(hmtx)
+}
+
+// 写入 sfnt 的表目录和各表，表按名字排序，每个表按 4 字节对齐
+fn build_sfnt(flavor: u32, mut tables: Vec<([u8; 4], Vec<u8>)>) -> Vec<u8> {
+  tables.sort_by_key(|table| table.0);
+  let num_tables = tables.len() as u16;
+  let entry_selector = 15 - num_tables.max(1).leading_zeros() as u16;
+  let search_range: u16 = (1 << entry_selector) * 16;
+
+  let mut output = Vec::new();
+  output.extend_from_slice(&flavor.to_be_bytes());
+  output.extend_from_slice(&num_tables.to_be_bytes());
+  output.extend_from_slice(&search_range.to_be_bytes());
+  output.extend_from_slice(&entry_selector.to_be_bytes());
+  output.extend_from_slice(&(num_tables * 16 - search_range).to_be_bytes());
+
+  let mut offset = 12 + tables.len() * 16;
+  for (tag, data) in &tables {
+    output.extend_from_slice(tag);
+    output.extend_from_slice(&checksum(data).to_be_bytes());
+    output.extend_from_slice(&(offset as u32).to_be_bytes());
+    output.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    offset += data.len().div_ceil(4) * 4;
+  }
+  for (_, data) in &tables {
+    output.extend_from_slice(data);
+    while output.len() % 4 != 0 {
+      output.push(0);
+    }
+  }
+  output
+}
+
+fn checksum(data: &[u8]) -> u32 {
+  data.chunks(4).fold(0u32, |sum, chunk| {
+    let mut word = [0u8; 4];
+    word[..chunk.len()].copy_from_slice(chunk);
+    sum.wrapping_add(u32::from_be_bytes(word))
+  })
+}
+
+// 大端序读取，越界时返回 None
+struct Reader<'a> {
+  data: &'a [u8],
+  pos: usize,
+}
+
+impl<'a> Reader<'a> {
+  fn new(data: &'a [u8]) -> Reader<'a> {
+    Reader { data, pos: 0 }
+  }
+
+  fn bytes(&mut self, length: usize) -> Option<&'a [u8]> {
+    let bytes = self.data.get(self.pos..self.pos.checked_add(length)?)?;
+    self.pos += length;
+    Some(bytes)
+  }
+
+  fn skip(&mut self, length: usize) -> Option<()> {
+    self.bytes(length).map(|_| ())
+  }
+
+  fn u8(&mut self) -> Option<u8> {
+    self.bytes(1).map(|bytes| bytes[0])
+  }
+
+  fn u16(&mut self) -> Option<u16> {
+    self
+      .bytes(2)
+      .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+  }
+
+  fn u32(&mut self) -> Option<u32> {
+    self
+      .bytes(4)
+      .map(|bytes| u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+  }
+
+  // UIntBase128：每字节 7 位，最高位表示后面还有字节，最多 5 字节
+  fn base128(&mut self) -> Option<u32> {
+    let mut value: u32 = 0;
+    for i in 0..5 {
+      let byte = self.u8()?;
+      if i == 0 && byte == 0x80 {
+        return None;
+      }
+      if value & 0xfe00_0000 != 0 {
+        return None;
+      }
+      value = (value << 7) | (byte & 0x7f) as u32;
+      if byte & 0x80 == 0 {
+        return Some(value);
+      }
+    }
+    None
+  }
+
+  // 255UInt16：小于 253 的值用一个字节表示
+  fn u255(&mut self) -> Option<u16> {
+    match self.u8()? {
+      253 => self.u16(),
+      254 => self.u8().map(|value| value as u16 + 253 * 2),
+      255 => self.u8().map(|value| value as u16 + 253),
+      value => Some(value as u16),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use rustybuzz::ttf_parser::{Face, OutlineBuilder, Rect};
+
+  // DejaVu Sans 中 TEXT 各字符的子集，glyf、loca 和 hmtx 均经过 WOFF2 变换
+  const SUBSET: &[u8] = include_bytes!("../fonts/DejaVuSans-subset.woff2");
+  const ORIGINAL: &[u8] = include_bytes!("../fonts/DejaVuSans.ttf");
+  // Å、É 等为复合字形
+  const TEXT: &str = "Hamburgefonstiv ÅÉéñÿ";
+
+  // 记录轮廓的绘制命令
+  #[derive(Default)]
+  struct Outline(Vec<String>);
+
+  impl OutlineBuilder for Outline {
+    fn move_to(&mut self, x: f32, y: f32) {
+      self.0.push(format!("M {} {}", x, y));
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+      self.0.push(format!("L {} {}", x, y));
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+      self.0.push(format!("Q {} {} {} {}", x1, y1, x, y));
+    }
+
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+      self
+        .0
+        .push(format!("C {} {} {} {} {} {}", x1, y1, x2, y2, x, y));
+    }
+
+    fn close(&mut self) {
+      self.0.push("Z".to_string());
+    }
+  }
+
+  // 字符的步进宽度、包围盒和轮廓
+  fn glyph(face: &Face, c: char) -> (Option<u16>, Option<Rect>, Vec<String>) {
+    let id = face.glyph_index(c).unwrap();
+    let mut outline = Outline::default();
+    let bbox = face.outline_glyph(id, &mut outline);
+    (face.glyph_hor_advance(id), bbox, outline.0)
+  }
+
+  fn table<'a>(tables: &[TableEntry], stream: &'a [u8], tag: &[u8; 4]) -> &'a [u8] {
+    let table = tables.iter().find(|table| &table.tag == tag).unwrap();
+    assert!(table.transformed);
+    &stream[table.offset..table.offset + table.length]
+  }
+
+  #[test]
+  fn decodes_glyphs_like_the_original_font() {
+    let decoded = decode(SUBSET).unwrap();
+    let subset = Face::parse(&decoded, 0).unwrap();
+    let original = Face::parse(ORIGINAL, 0).unwrap();
+    for c in TEXT.chars() {
+      assert_eq!(glyph(&subset, c), glyph(&original, c), "{}", c);
+    }
+  }
+
+  #[test]
+  fn rejects_truncated_files() {
+    // 最后最多 3 个字节是对齐用的填充
+    for length in 0..SUBSET.len() - 3 {
+      assert!(decode(&SUBSET[..length]).is_none(), "{}", length);
+    }
+  }
+
+  #[test]
+  fn rejects_truncated_tables() {
+    let (_, tables, stream) = read_tables(SUBSET).unwrap();
+    let glyf = table(&tables, &stream, b"glyf");
+    let (_, _, x_mins) = reconstruct_glyf(glyf).unwrap();
+    for length in 0..glyf.len() {
+      assert!(reconstruct_glyf(&glyf[..length]).is_none(), "{}", length);
+    }
+
+    let hmtx = table(&tables, &stream, b"hmtx");
+    let num_h_metrics = x_mins.len();
+    assert!(reconstruct_hmtx(hmtx, num_h_metrics, &x_mins).is_some());
+    for length in 0..hmtx.len() {
+      assert!(reconstruct_hmtx(&hmtx[..length], num_h_metrics, &x_mins).is_none());
+    }
+  }
+
+  #[test]
+  fn rejects_other_formats() {
+    assert!(decode(ORIGINAL).is_none());
+    let mut collection = SUBSET.to_vec();
+    collection[4..8].copy_from_slice(b"ttcf");
+    assert!(decode(&collection).is_none());
+  }
+}